@@ -279,6 +279,12 @@ impl OrbitalScheme {
 }
 
 impl Scheme for OrbitalScheme {
+    fn cancel(&mut self, id: usize) -> bool {
+        let len = self.todo.len();
+        self.todo.retain(|packet| packet.id != id);
+        self.todo.len() < len
+    }
+
     fn open(&mut self, url: &str, _flags: usize, _mode: usize) -> Result<usize> {
         let path = url.splitn(2, ":").last().unwrap_or("");
         let mut parts = path.split("/");
@@ -402,7 +408,7 @@ fn event_loop(scheme_mutex: Arc<Mutex<OrbitalScheme>>, display: Arc<Socket>, soc
 
             let mut packets = Vec::new();
             mem::swap(&mut scheme.todo, &mut packets);
-            for mut packet in packets.iter_mut() {
+            for packet in packets.iter() {
                 let delay = if packet.a == SYS_READ {
                     if let Some(window) = scheme.windows.get(&packet.b) {
                         window.async == false
@@ -413,12 +419,13 @@ fn event_loop(scheme_mutex: Arc<Mutex<OrbitalScheme>>, display: Arc<Socket>, soc
                     false
                 };
 
-                scheme.handle(packet);
+                let mut reply = *packet;
+                scheme.handle(&mut reply);
 
-                if delay && packet.a == 0 {
+                if delay && reply.a == 0 {
                     scheme.todo.push(*packet);
-                }else{
-                    responses.push(*packet);
+                } else {
+                    responses.push(reply);
                 }
             }
         }
@@ -440,7 +447,7 @@ fn server_loop(scheme_mutex: Arc<Mutex<OrbitalScheme>>, display: Arc<Socket>, so
         let mut responses = Vec::new();
         {
             let mut scheme = scheme_mutex.lock().unwrap();
-            for mut packet in packets[.. count].iter_mut() {
+            for packet in packets[.. count].iter() {
                 let delay = if packet.a == SYS_READ {
                     if let Some(window) = scheme.windows.get(&packet.b) {
                         window.async == false
@@ -451,12 +458,13 @@ fn server_loop(scheme_mutex: Arc<Mutex<OrbitalScheme>>, display: Arc<Socket>, so
                     false
                 };
 
-                scheme.handle(packet);
+                let mut reply = *packet;
+                scheme.handle(&mut reply);
 
-                if delay && packet.a == 0 {
+                if delay && reply.a == 0 {
                     scheme.todo.push(*packet);
                 } else {
-                    responses.push(*packet);
+                    responses.push(reply);
                 }
             }
         }
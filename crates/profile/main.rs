@@ -0,0 +1,212 @@
+#![deny(warnings)]
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::process::{self, Command};
+
+/// Where position independent executables are loaded when ASLR is disabled
+const PIE_BASE: usize = 0x8048000;
+
+const ELF_CLASS_64: u8 = 2;
+const ET_DYN: u16 = 3;
+const SHT_SYMTAB: u32 = 2;
+const STT_FUNC: u8 = 2;
+
+/// A function from the symbol table of an executable
+struct Symbol {
+    addr: usize,
+    size: usize,
+    name: String,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> usize {
+    data.get(offset .. offset + 2).map_or(0, |b| b[0] as usize | (b[1] as usize) << 8)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> usize {
+    data.get(offset .. offset + 4).map_or(0, |b| {
+        b[0] as usize | (b[1] as usize) << 8 | (b[2] as usize) << 16 | (b[3] as usize) << 24
+    })
+}
+
+fn read_u64(data: &[u8], offset: usize) -> usize {
+    // Addresses do not go above 4 GiB in userspace
+    read_u32(data, offset)
+}
+
+fn read_str(data: &[u8], offset: usize) -> String {
+    let bytes = data.get(offset ..).unwrap_or(&[]);
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[.. len]).into_owned()
+}
+
+/// Read the functions in the `.symtab` of an ELF executable, sorted by address, and relocated to
+/// where the kernel loads it
+fn symbols(path: &str) -> Vec<Symbol> {
+    let mut data = Vec::new();
+    if let Err(err) = File::open(path).and_then(|mut file| file.read_to_end(&mut data)) {
+        let _ = writeln!(std::io::stderr(), "profile: failed to read {}: {}", path, err);
+        return Vec::new();
+    }
+    if ! data.starts_with(b"\x7FELF") || data.len() < 0x40 {
+        let _ = writeln!(std::io::stderr(), "profile: {} is not an ELF executable", path);
+        return Vec::new();
+    }
+
+    let wide = data[4] == ELF_CLASS_64;
+    let base = if read_u16(&data, 16) as u16 == ET_DYN { PIE_BASE } else { 0 };
+    let (sh_off, sh_entsize, sh_num) = if wide {
+        (read_u64(&data, 0x28), read_u16(&data, 0x3A), read_u16(&data, 0x3C))
+    } else {
+        (read_u32(&data, 0x20), read_u16(&data, 0x2E), read_u16(&data, 0x30))
+    };
+
+    // (type, offset, size, link, entsize) of a section header
+    let section = |i: usize| -> (usize, usize, usize, usize, usize) {
+        let sh = sh_off + i * sh_entsize;
+        if wide {
+            (read_u32(&data, sh + 4), read_u64(&data, sh + 0x18), read_u64(&data, sh + 0x20),
+             read_u32(&data, sh + 0x28), read_u64(&data, sh + 0x38))
+        } else {
+            (read_u32(&data, sh + 4), read_u32(&data, sh + 0x10), read_u32(&data, sh + 0x14),
+             read_u32(&data, sh + 0x18), read_u32(&data, sh + 0x24))
+        }
+    };
+
+    let mut symbols = Vec::new();
+    for i in 0 .. sh_num {
+        let (kind, offset, size, link, entsize) = section(i);
+        if kind as u32 != SHT_SYMTAB || entsize == 0 {
+            continue;
+        }
+        let (_, strtab, _, _, _) = section(link);
+
+        for j in 0 .. size / entsize {
+            let sym = offset + j * entsize;
+            let (name, info, addr, size) = if wide {
+                (read_u32(&data, sym), data.get(sym + 4).map_or(0, |&b| b),
+                 read_u64(&data, sym + 8), read_u64(&data, sym + 16))
+            } else {
+                (read_u32(&data, sym), data.get(sym + 12).map_or(0, |&b| b),
+                 read_u32(&data, sym + 4), read_u32(&data, sym + 8))
+            };
+            if info & 0xF == STT_FUNC && addr > 0 {
+                symbols.push(Symbol {
+                    addr: base + addr,
+                    size: size,
+                    name: read_str(&data, strtab + name),
+                });
+            }
+        }
+    }
+
+    symbols.sort_by_key(|symbol| symbol.addr);
+    symbols
+}
+
+/// Find the name of the function containing `addr`
+fn symbolize(symbols: &[Symbol], addr: usize) -> String {
+    let i = match symbols.binary_search_by_key(&addr, |symbol| symbol.addr) {
+        Ok(i) => i,
+        Err(0) => return format!("{:X}", addr),
+        Err(i) => i - 1,
+    };
+
+    let symbol = &symbols[i];
+    if symbol.size == 0 || addr < symbol.addr + symbol.size {
+        symbol.name.clone()
+    } else {
+        format!("{:X}", addr)
+    }
+}
+
+fn control(command: &str) {
+    if let Err(err) = File::create("profile:").and_then(|mut file| file.write(command.as_bytes())) {
+        let _ = writeln!(std::io::stderr(), "profile: failed to {} profiling: {}", command, err);
+        process::exit(1);
+    }
+}
+
+/// Run a command while profiling, and print the samples taken from it as folded stacks, for
+/// flamegraph tools. User frames are named from the symbol table of the executable, kernel frames
+/// are printed as addresses under `[kernel]`.
+fn main() {
+    let mut args = env::args().skip(1);
+    let name = match args.next() {
+        Some(name) => name,
+        None => {
+            let _ = writeln!(std::io::stderr(), "usage: profile COMMAND [ARGS]");
+            process::exit(1);
+        }
+    };
+    let args: Vec<String> = args.collect();
+
+    // Resolve the executable the same way Command does
+    let path = if name.contains('/') {
+        name.clone()
+    } else {
+        let mut path = env::var("PATH").unwrap_or(".".to_string());
+        if ! path.ends_with('/') {
+            path.push('/');
+        }
+        path + &name
+    };
+
+    // Load the command where its symbol table says it is
+    env::set_var("ASLR", "0");
+
+    control("clear");
+    control("start");
+    let pid = match Command::new(&name).args(&args).spawn() {
+        Ok(mut child) => {
+            let pid = child.id() as usize;
+            if let Err(err) = child.wait() {
+                let _ = writeln!(std::io::stderr(), "profile: failed to wait for {}: {}", name, err);
+            }
+            pid
+        },
+        Err(err) => {
+            control("stop");
+            let _ = writeln!(std::io::stderr(), "profile: failed to run {}: {}", name, err);
+            process::exit(1);
+        }
+    };
+    control("stop");
+
+    let mut samples = String::new();
+    if let Err(err) = File::open("profile:").and_then(|mut file| file.read_to_string(&mut samples)) {
+        let _ = writeln!(std::io::stderr(), "profile: failed to read samples: {}", err);
+        process::exit(1);
+    }
+
+    let symbols = symbols(&path);
+
+    let mut stacks: BTreeMap<String, usize> = BTreeMap::new();
+    for line in samples.lines() {
+        let mut parts = line.split(' ');
+        if parts.next().and_then(|part| part.parse::<usize>().ok()) != Some(pid) {
+            continue;
+        }
+        let user = parts.next() == Some("u");
+        let addrs: Vec<usize> = parts.filter_map(|part| usize::from_str_radix(part, 16).ok()).collect();
+
+        // Outermost frame first, as flamegraphs expect
+        let mut frames: Vec<String> = addrs.iter().rev().map(|&addr| if user {
+            symbolize(&symbols, addr)
+        } else {
+            format!("{:X}", addr)
+        }).collect();
+        if ! user {
+            frames.insert(0, "[kernel]".to_string());
+        }
+        frames.insert(0, name.clone());
+
+        *stacks.entry(frames.join(";")).or_insert(0) += 1;
+    }
+
+    for (stack, count) in stacks.iter() {
+        println!("{} {}", stack, count);
+    }
+}
@@ -0,0 +1,281 @@
+#![deny(warnings)]
+
+extern crate system;
+
+use std::env;
+use std::io::{stderr, Write};
+use std::{cmp, mem, slice};
+use std::process::{self, Command};
+
+use system::error::Error;
+use system::scheme::Packet;
+use system::syscall::*;
+
+/// The most bytes of a string or buffer that are printed
+const MAX_STRING: usize = 32;
+
+/// How to print a syscall argument
+#[derive(Copy, Clone)]
+enum Arg {
+    /// A signed integer, such as a file descriptor
+    Int,
+    /// An address or flags
+    Hex,
+    /// A mode
+    Oct,
+    /// A NUL terminated string
+    Path,
+    /// Data passed in, with its length in the next argument
+    BufIn,
+    /// Data passed out, with its length in the result
+    BufOut,
+}
+
+use Arg::*;
+
+/// The name of a syscall and the kinds of its arguments
+fn syscall(number: usize) -> (&'static str, Vec<Arg>) {
+    match number {
+        // Redox
+        SYS_SUPERVISE => ("supervise", vec![Int]),
+
+        // Unix
+        SYS_ADJTIME => ("adjtime", vec![Hex, Hex]),
+        SYS_BRK => ("brk", vec![Hex]),
+        SYS_CHDIR => ("chdir", vec![Path]),
+        SYS_CHMOD => ("chmod", vec![Path, Oct]),
+        SYS_CHOWN => ("chown", vec![Path, Int, Int]),
+        SYS_CLONE => ("clone", vec![Hex]),
+        SYS_CLOSE => ("close", vec![Int]),
+        SYS_CLOCK_GETTIME => ("clock_gettime", vec![Int, Hex]),
+        SYS_CLOCK_SETTIME => ("clock_settime", vec![Int, Hex]),
+        SYS_DUP => ("dup", vec![Int]),
+        SYS_EXECVE => ("execve", vec![Path, Hex]),
+        SYS_EXIT => ("exit", vec![Int]),
+        SYS_FCHMOD => ("fchmod", vec![Int, Oct]),
+        SYS_FCHOWN => ("fchown", vec![Int, Int, Int]),
+        SYS_FMAP => ("fmap", vec![Int, Int, Int, Hex]),
+        SYS_FPATH => ("fpath", vec![Int, BufOut, Int]),
+        SYS_FSTAT => ("fstat", vec![Int, Hex]),
+        SYS_FSYNC => ("fsync", vec![Int]),
+        SYS_FTRUNCATE => ("ftruncate", vec![Int, Int]),
+        SYS_FUNMAP => ("funmap", vec![Hex]),
+        SYS_FUTIMENS => ("futimens", vec![Int, Hex]),
+        SYS_GETPID => ("getpid", vec![]),
+        SYS_GETRUSAGE => ("getrusage", vec![Int, Hex]),
+        SYS_IOPL => ("iopl", vec![Int]),
+        SYS_LSEEK => ("lseek", vec![Int, Int, Int]),
+        SYS_MKDIR => ("mkdir", vec![Path, Oct]),
+        SYS_NANOSLEEP => ("nanosleep", vec![Hex, Hex]),
+        SYS_OPEN => ("open", vec![Path, Hex]),
+        SYS_PIPE2 => ("pipe2", vec![Hex, Hex]),
+        SYS_PREAD => ("pread", vec![Int, BufOut, Int, Int]),
+        SYS_PWRITE => ("pwrite", vec![Int, BufIn, Int, Int]),
+        SYS_READ => ("read", vec![Int, BufOut, Int]),
+        SYS_READV => ("readv", vec![Int, Hex, Int]),
+        SYS_RMDIR => ("rmdir", vec![Path]),
+        SYS_STAT => ("stat", vec![Path, Hex]),
+        SYS_UNLINK => ("unlink", vec![Path]),
+        SYS_UTIMENS => ("utimens", vec![Path, Hex]),
+        SYS_WAITPID => ("waitpid", vec![Int, Hex, Hex]),
+        SYS_WRITE => ("write", vec![Int, BufIn, Int]),
+        SYS_WRITEV => ("writev", vec![Int, Hex, Int]),
+        SYS_YIELD => ("yield", vec![]),
+
+        _ => ("unknown", vec![Hex, Hex, Hex, Hex]),
+    }
+}
+
+/// Read memory of the supervised process, stopping early at memory that is not mapped
+fn read_child(fd: usize, address: usize, len: usize) -> Vec<u8> {
+    let mut data = vec![0; len];
+    if sys_pread(fd, &mut data, address).is_ok() {
+        return data;
+    }
+
+    // Part of it is mapped, so read what is
+    for i in 0 .. len {
+        if sys_pread(fd, &mut data[i .. i + 1], address + i).is_err() {
+            data.truncate(i);
+            break;
+        }
+    }
+    data
+}
+
+/// Quote bytes like a Rust string, cutting them at `MAX_STRING`
+fn quote(data: &[u8], len: usize) -> String {
+    let mut string = "\"".to_string();
+    for &b in data.iter().take(MAX_STRING) {
+        match b {
+            b'"' => string.push_str("\\\""),
+            b'\\' => string.push_str("\\\\"),
+            b'\n' => string.push_str("\\n"),
+            b'\r' => string.push_str("\\r"),
+            b'\t' => string.push_str("\\t"),
+            0x20 ... 0x7E => string.push(b as char),
+            _ => string.push_str(&format!("\\x{:02X}", b)),
+        }
+    }
+    string.push('"');
+    if len > MAX_STRING {
+        string.push_str("...");
+    }
+    string
+}
+
+/// Print a string argument of the supervised process
+fn path(fd: usize, address: usize) -> String {
+    if address == 0 {
+        return "NULL".to_string();
+    }
+
+    let data = read_child(fd, address, MAX_STRING + 1);
+    match data.iter().position(|&b| b == 0) {
+        Some(len) => quote(&data[.. len], len),
+        None if data.is_empty() => format!("0x{:X}", address),
+        None => quote(&data, data.len()),
+    }
+}
+
+/// Print a buffer of the supervised process
+fn buffer(fd: usize, address: usize, len: usize) -> String {
+    let data = read_child(fd, address, cmp::min(len, MAX_STRING));
+    if data.is_empty() && len > 0 {
+        format!("0x{:X}", address)
+    } else {
+        quote(&data, len)
+    }
+}
+
+/// Print the arguments of a syscall. Buffers passed out are printed after it returns, as `None`.
+fn args(fd: usize, call: &Packet, kinds: &[Arg]) -> Vec<Option<String>> {
+    let values = [call.b, call.c, call.d, call.e];
+    kinds.iter().enumerate().map(|(i, kind)| {
+        let value = values[i];
+        match *kind {
+            Int => Some(format!("{}", value as isize)),
+            Hex => Some(format!("0x{:X}", value)),
+            Oct => Some(format!("0o{:o}", value)),
+            Path => Some(path(fd, value)),
+            BufIn => Some(buffer(fd, value, values.get(i + 1).map_or(0, |&len| len))),
+            BufOut => None,
+        }
+    }).collect()
+}
+
+/// Print a result, with the name and description of an error
+fn result(value: usize) -> String {
+    match Error::demux(value) {
+        Ok(value) => format!("{}", value),
+        Err(err) => format!("-1 {} ({})", err.name(), err.text()),
+    }
+}
+
+/// Answer the syscall that was read, with a `Packet` to run or a result to return
+fn answer(fd: usize, data: &[u8]) {
+    if let Err(err) = sys_write(fd, data) {
+        let _ = writeln!(stderr(), "strace: failed to answer syscall: {}", err);
+        process::exit(1);
+    }
+}
+
+/// Read the next packet, or `None` when the process has exited
+fn next_packet(fd: usize) -> Option<Packet> {
+    let mut packet = Packet::default();
+    match sys_read(fd, &mut packet) {
+        Ok(0) => None,
+        Ok(_) => Some(packet),
+        Err(err) => {
+            let _ = writeln!(stderr(), "strace: failed to read syscall: {}", err);
+            None
+        }
+    }
+}
+
+/// Run a command, printing every syscall it makes, with its arguments and result, to stderr
+fn main() {
+    let mut args_iter = env::args().skip(1);
+    let name = match args_iter.next() {
+        Some(name) => name,
+        None => {
+            let _ = writeln!(stderr(), "usage: strace COMMAND [ARGS]");
+            process::exit(1);
+        }
+    };
+    let command_args: Vec<String> = args_iter.collect();
+
+    let mut child = match Command::new(&name).args(&command_args).spawn_supervise() {
+        Ok(child) => child,
+        Err(err) => {
+            let _ = writeln!(stderr(), "strace: failed to run {}: {}", name, err);
+            process::exit(1);
+        }
+    };
+
+    let fd = match sys_supervise(child.id() as usize) {
+        Ok(fd) => fd,
+        Err(err) => {
+            let _ = writeln!(stderr(), "strace: failed to supervise {}: {}", name, err);
+            process::exit(1);
+        }
+    };
+
+    let mut next = None;
+    loop {
+        let call = match next.take().or_else(|| next_packet(fd)) {
+            Some(call) => call,
+            None => break
+        };
+
+        let (call_name, kinds) = syscall(call.a);
+        let mut printed = args(fd, &call, &kinds);
+
+        // Run the syscall as it was made
+        answer(fd, &call);
+
+        let value = match next_packet(fd) {
+            Some(ret) => if ret.id == SUPERVISE_RETURN {
+                Some(ret.a)
+            } else {
+                // It did not return, and this is the next syscall
+                next = Some(ret);
+                None
+            },
+            None => None
+        };
+
+        // Buffers passed out are read before the process continues
+        if let Some(value) = value {
+            let values = [call.b, call.c, call.d, call.e];
+            for (i, kind) in kinds.iter().enumerate() {
+                if let BufOut = *kind {
+                    printed[i] = Some(match Error::demux(value) {
+                        Ok(len) => buffer(fd, values[i], len),
+                        Err(_) => format!("0x{:X}", values[i]),
+                    });
+                }
+            }
+        }
+
+        let printed: Vec<String> = printed.into_iter().map(|arg| arg.unwrap_or("?".to_string())).collect();
+        let result_string = match value {
+            Some(value) => result(value),
+            None if call.a == SYS_EXECVE && next.is_some() => "0".to_string(),
+            None => "?".to_string(),
+        };
+        let _ = writeln!(stderr(), "{}({}) = {}", call_name, printed.join(", "), result_string);
+
+        match value {
+            // Keep the result
+            Some(value) => answer(fd, unsafe {
+                slice::from_raw_parts(&value as *const usize as *const u8, mem::size_of::<usize>())
+            }),
+            None => if next.is_none() {
+                break;
+            }
+        }
+    }
+
+    let _ = child.wait();
+}
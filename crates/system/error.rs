@@ -27,6 +27,14 @@ impl Error {
         }
     }
 
+    /// The name of the `errno`, such as `ENOENT`
+    pub fn name(&self) -> &str {
+        match STR_ERRNO.get(self.errno as usize) {
+            Some(name) if ! name.is_empty() => name,
+            _ => "EUNKNOWN"
+        }
+    }
+
     pub fn text(&self) -> &str {
         if let Some(description) = STR_ERROR.get(self.errno as usize) {
             description
@@ -312,3 +320,137 @@ pub static STR_ERROR: [&'static str; 132] = ["Success",
                                              "Key was rejected by service",
                                              "Owner died",
                                              "State not recoverable"];
+
+/// The name of each `errno`, such as `ENOENT`
+pub static STR_ERRNO: [&'static str; 132] = ["",
+                                             "EPERM",
+                                             "ENOENT",
+                                             "ESRCH",
+                                             "EINTR",
+                                             "EIO",
+                                             "ENXIO",
+                                             "E2BIG",
+                                             "ENOEXEC",
+                                             "EBADF",
+                                             "ECHILD",
+                                             "EAGAIN",
+                                             "ENOMEM",
+                                             "EACCES",
+                                             "EFAULT",
+                                             "ENOTBLK",
+                                             "EBUSY",
+                                             "EEXIST",
+                                             "EXDEV",
+                                             "ENODEV",
+                                             "ENOTDIR",
+                                             "EISDIR",
+                                             "EINVAL",
+                                             "ENFILE",
+                                             "EMFILE",
+                                             "ENOTTY",
+                                             "ETXTBSY",
+                                             "EFBIG",
+                                             "ENOSPC",
+                                             "ESPIPE",
+                                             "EROFS",
+                                             "EMLINK",
+                                             "EPIPE",
+                                             "EDOM",
+                                             "ERANGE",
+                                             "EDEADLK",
+                                             "ENAMETOOLONG",
+                                             "ENOLCK",
+                                             "ENOSYS",
+                                             "ENOTEMPTY",
+                                             "ELOOP",
+                                             "EWOULDBLOCK",
+                                             "ENOMSG",
+                                             "EIDRM",
+                                             "ECHRNG",
+                                             "EL2NSYNC",
+                                             "EL3HLT",
+                                             "EL3RST",
+                                             "ELNRNG",
+                                             "EUNATCH",
+                                             "ENOCSI",
+                                             "EL2HLT",
+                                             "EBADE",
+                                             "EBADR",
+                                             "EXFULL",
+                                             "ENOANO",
+                                             "EBADRQC",
+                                             "EBADSLT",
+                                             "EDEADLOCK",
+                                             "EBFONT",
+                                             "ENOSTR",
+                                             "ENODATA",
+                                             "ETIME",
+                                             "ENOSR",
+                                             "ENONET",
+                                             "ENOPKG",
+                                             "EREMOTE",
+                                             "ENOLINK",
+                                             "EADV",
+                                             "ESRMNT",
+                                             "ECOMM",
+                                             "EPROTO",
+                                             "EMULTIHOP",
+                                             "EDOTDOT",
+                                             "EBADMSG",
+                                             "EOVERFLOW",
+                                             "ENOTUNIQ",
+                                             "EBADFD",
+                                             "EREMCHG",
+                                             "ELIBACC",
+                                             "ELIBBAD",
+                                             "ELIBSCN",
+                                             "ELIBMAX",
+                                             "ELIBEXEC",
+                                             "EILSEQ",
+                                             "ERESTART",
+                                             "ESTRPIPE",
+                                             "EUSERS",
+                                             "ENOTSOCK",
+                                             "EDESTADDRREQ",
+                                             "EMSGSIZE",
+                                             "EPROTOTYPE",
+                                             "ENOPROTOOPT",
+                                             "EPROTONOSUPPORT",
+                                             "ESOCKTNOSUPPORT",
+                                             "EOPNOTSUPP",
+                                             "EPFNOSUPPORT",
+                                             "EAFNOSUPPORT",
+                                             "EADDRINUSE",
+                                             "EADDRNOTAVAIL",
+                                             "ENETDOWN",
+                                             "ENETUNREACH",
+                                             "ENETRESET",
+                                             "ECONNABORTED",
+                                             "ECONNRESET",
+                                             "ENOBUFS",
+                                             "EISCONN",
+                                             "ENOTCONN",
+                                             "ESHUTDOWN",
+                                             "ETOOMANYREFS",
+                                             "ETIMEDOUT",
+                                             "ECONNREFUSED",
+                                             "EHOSTDOWN",
+                                             "EHOSTUNREACH",
+                                             "EALREADY",
+                                             "EINPROGRESS",
+                                             "ESTALE",
+                                             "EUCLEAN",
+                                             "ENOTNAM",
+                                             "ENAVAIL",
+                                             "EISNAM",
+                                             "EREMOTEIO",
+                                             "EDQUOT",
+                                             "ENOMEDIUM",
+                                             "EMEDIUMTYPE",
+                                             "ECANCELED",
+                                             "ENOKEY",
+                                             "EKEYEXPIRED",
+                                             "EKEYREVOKED",
+                                             "EKEYREJECTED",
+                                             "EOWNERDEAD",
+                                             "ENOTRECOVERABLE"];
@@ -5,6 +5,22 @@ use super::error::*;
 use super::syscall::*;
 use super::c_string_to_str;
 
+/// Sent by the kernel when the caller of a pending request stops waiting for it
+///
+/// The packet has an `id` of 0, and `b` is the `id` of the cancelled request.
+pub const PACKET_CANCEL: usize = !0;
+
+/// A request to a scheme, or the reply to one
+///
+/// The kernel gives every request in flight a unique, non-zero `id`. The reply is a packet with
+/// the same `id` and the result in `a`. Replies may be written in any order, so a request that
+/// cannot be answered yet can be kept, and answered later, while other requests are served.
+/// Every request must be answered exactly once, even if it is cancelled. Its `id` is not reused
+/// until then. Replies with an `id` of 0 are ignored.
+///
+/// A packet is six words, 24 bytes on i386 and 48 on x86_64. The sixth word, `e`, carries the
+/// offset of `pread` and `pwrite`. It changed the size of a packet, so daemons that read and write
+/// a fixed number of bytes, instead of `size_of::<Packet>()`, must be updated and rebuilt.
 #[derive(Copy, Clone, Debug, Default)]
 #[repr(packed)]
 pub struct Packet {
@@ -12,7 +28,8 @@ pub struct Packet {
     pub a: usize,
     pub b: usize,
     pub c: usize,
-    pub d: usize
+    pub d: usize,
+    pub e: usize
 }
 
 impl Deref for Packet {
@@ -33,28 +50,61 @@ impl DerefMut for Packet {
 }
 
 pub trait Scheme {
+    /// Handle a request, replacing it with its reply
+    ///
+    /// To keep a request pending, copy the packet before handling it, and handle the copy again
+    /// later. A cancel packet becomes the reply to the cancelled request if `cancel` dropped it,
+    /// and is ignored by the kernel otherwise, so it can be written back like any other reply.
     fn handle(&mut self, packet: &mut Packet) {
+        if packet.a == PACKET_CANCEL {
+            if self.cancel(packet.b) {
+                packet.id = packet.b;
+                packet.a = Error::mux(Err(Error::new(ECANCELED)));
+            } else {
+                packet.id = 0;
+                packet.a = 0;
+            }
+            return;
+        }
+
         packet.a = Error::mux(match packet.a {
             SYS_OPEN => self.open(c_string_to_str(packet.b as *const u8), packet.c, packet.d),
             SYS_MKDIR => self.mkdir(c_string_to_str(packet.b as *const u8), packet.c),
             SYS_RMDIR => self.rmdir(c_string_to_str(packet.b as *const u8)),
             SYS_STAT => self.stat(c_string_to_str(packet.b as *const u8), unsafe { &mut *(packet.c as *mut Stat) }),
             SYS_UNLINK => self.unlink(c_string_to_str(packet.b as *const u8)),
+            SYS_CHMOD => self.chmod(c_string_to_str(packet.b as *const u8), packet.c),
+            SYS_CHOWN => self.chown(c_string_to_str(packet.b as *const u8), packet.c, packet.d),
+            SYS_UTIMENS => self.utimens(c_string_to_str(packet.b as *const u8), unsafe { &*(packet.c as *const [TimeSpec; 2]) }),
 
             SYS_DUP => self.dup(packet.b),
             SYS_READ => self.read(packet.b, unsafe { slice::from_raw_parts_mut(packet.c as *mut u8, packet.d) }),
             SYS_WRITE => self.write(packet.b, unsafe { slice::from_raw_parts(packet.c as *const u8, packet.d) }),
+            SYS_PREAD => self.pread(packet.b, unsafe { slice::from_raw_parts_mut(packet.c as *mut u8, packet.d) }, packet.e),
+            SYS_PWRITE => self.pwrite(packet.b, unsafe { slice::from_raw_parts(packet.c as *const u8, packet.d) }, packet.e),
+            SYS_READV => self.readv(packet.b, unsafe { slice::from_raw_parts(packet.c as *const IoVec, packet.d) }),
+            SYS_WRITEV => self.writev(packet.b, unsafe { slice::from_raw_parts(packet.c as *const IoVec, packet.d) }),
             SYS_LSEEK => self.seek(packet.b, packet.c, packet.d),
             SYS_FPATH => self.fpath(packet.b, unsafe { slice::from_raw_parts_mut(packet.c as *mut u8, packet.d) }),
             SYS_FSTAT => self.fstat(packet.b, unsafe { &mut *(packet.c as *mut Stat) }),
             SYS_FSYNC => self.fsync(packet.b),
             SYS_FTRUNCATE => self.ftruncate(packet.b, packet.c),
+            SYS_FCHMOD => self.fchmod(packet.b, packet.c),
+            SYS_FCHOWN => self.fchown(packet.b, packet.c, packet.d),
+            SYS_FUTIMENS => self.futimens(packet.b, unsafe { &*(packet.c as *const [TimeSpec; 2]) }),
             SYS_CLOSE => self.close(packet.b),
 
             _ => Err(Error::new(ENOSYS))
         });
     }
 
+    /// Drop a pending request that has been cancelled
+    /// Returns true if the request was pending, in which case it is answered with `ECANCELED`.
+    #[allow(unused_variables)]
+    fn cancel(&mut self, id: usize) -> bool {
+        false
+    }
+
     /* Scheme operations */
 
     #[allow(unused_variables)]
@@ -82,6 +132,21 @@ pub trait Scheme {
         Err(Error::new(ENOENT))
     }
 
+    #[allow(unused_variables)]
+    fn chmod(&mut self, path: &str, mode: usize) -> Result<usize> {
+        Err(Error::new(EOPNOTSUPP))
+    }
+
+    #[allow(unused_variables)]
+    fn chown(&mut self, path: &str, uid: usize, gid: usize) -> Result<usize> {
+        Err(Error::new(EOPNOTSUPP))
+    }
+
+    #[allow(unused_variables)]
+    fn utimens(&mut self, path: &str, times: &[TimeSpec; 2]) -> Result<usize> {
+        Err(Error::new(EOPNOTSUPP))
+    }
+
     /* Resource operations */
     #[allow(unused_variables)]
     fn dup(&mut self, old_id: usize) -> Result<usize> {
@@ -98,6 +163,58 @@ pub trait Scheme {
         Err(Error::new(EBADF))
     }
 
+    #[allow(unused_variables)]
+    fn pread(&mut self, id: usize, buf: &mut [u8], offset: usize) -> Result<usize> {
+        Err(Error::new(ESPIPE))
+    }
+
+    #[allow(unused_variables)]
+    fn pwrite(&mut self, id: usize, buf: &[u8], offset: usize) -> Result<usize> {
+        Err(Error::new(ESPIPE))
+    }
+
+    /// Read into each buffer in turn, stopping at the first short read
+    fn readv(&mut self, id: usize, iov: &[IoVec]) -> Result<usize> {
+        let mut total = 0;
+        for vec in iov.iter() {
+            let buf = unsafe { slice::from_raw_parts_mut(vec.iov_base as *mut u8, vec.iov_len) };
+            let count = match self.read(id, buf) {
+                Ok(count) => count,
+                Err(err) => if total > 0 {
+                    break;
+                } else {
+                    return Err(err);
+                }
+            };
+            total += count;
+            if count < vec.iov_len {
+                break;
+            }
+        }
+        Ok(total)
+    }
+
+    /// Write each buffer in turn, stopping at the first short write
+    fn writev(&mut self, id: usize, iov: &[IoVec]) -> Result<usize> {
+        let mut total = 0;
+        for vec in iov.iter() {
+            let buf = unsafe { slice::from_raw_parts(vec.iov_base as *const u8, vec.iov_len) };
+            let count = match self.write(id, buf) {
+                Ok(count) => count,
+                Err(err) => if total > 0 {
+                    break;
+                } else {
+                    return Err(err);
+                }
+            };
+            total += count;
+            if count < vec.iov_len {
+                break;
+            }
+        }
+        Ok(total)
+    }
+
     #[allow(unused_variables)]
     fn seek(&mut self, id: usize, pos: usize, whence: usize) -> Result<usize> {
         Err(Error::new(EBADF))
@@ -123,6 +240,21 @@ pub trait Scheme {
         Err(Error::new(EBADF))
     }
 
+    #[allow(unused_variables)]
+    fn fchmod(&mut self, id: usize, mode: usize) -> Result<usize> {
+        Err(Error::new(EOPNOTSUPP))
+    }
+
+    #[allow(unused_variables)]
+    fn fchown(&mut self, id: usize, uid: usize, gid: usize) -> Result<usize> {
+        Err(Error::new(EOPNOTSUPP))
+    }
+
+    #[allow(unused_variables)]
+    fn futimens(&mut self, id: usize, times: &[TimeSpec; 2]) -> Result<usize> {
+        Err(Error::new(EOPNOTSUPP))
+    }
+
     #[allow(unused_variables)]
     fn close(&mut self, id: usize) -> Result<usize> {
         Err(Error::new(EBADF))
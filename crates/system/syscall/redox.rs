@@ -3,6 +3,9 @@ use error::Result;
 
 pub const SYS_SUPERVISE: usize = 1638; // loominatzi confirmed
 
+/// The `id` of a packet read from a supervisor when a forwarded syscall returns
+pub const SUPERVISE_RETURN: usize = !0;
+
 /// <!-- @MANSTART{supervise} -->
 /// Supervise a given child process' system calls.
 ///
@@ -20,13 +23,26 @@ pub const SYS_SUPERVISE: usize = 1638; // loominatzi confirmed
 /// made will mark the process as blocked and store the syscall until it is handled by the parrent.
 ///
 /// The return value (if successful) is a file descriptor, from which syscalls can be read and written:
-/// the syscalls are read in `Packet` sized packages, containing the respective blocking syscall. If
-/// no syscall is blocking (or the last blocking syscall have been handled), 0 bytes will be read to
-/// the buffer.
-///
-/// Writing pointer sized integers to this file handle will set the EAX register of the particular
-/// process, after which the process is unblocked and the syscall buffer is emptied. The behavior of
-/// writing packages of unexpected size is unspecified.
+/// the syscalls are read in `Packet` sized packages, with an `id` of 0, the syscall number in `a`
+/// and the arguments in `b` to `e`. Reading blocks until the process makes a syscall, and returns 0 bytes
+/// once it has exited. Buffers smaller than a `Packet` give EINVAL.
+///
+/// Every syscall that is read must be answered by writing to the file descriptor, which unblocks
+/// the process:
+///
+/// - Writing a pointer sized integer returns it from the syscall, which is not run.
+/// - Writing a `Packet` runs the syscall it contains in the process, which may be the one that was
+///   read or another one. When it returns, the next packet read has an `id` of
+///   `SUPERVISE_RETURN`, the result in `a` and the syscall number in `b`. It is answered by
+///   writing the pointer sized value to return. Syscalls that do not return, such as a successful
+///   `execve`, have no such packet.
+///
+/// Writing anything else, or writing when no packet is waiting for an answer, gives EINVAL, as
+/// does reading before the last packet was answered.
+///
+/// While the process is blocked by a syscall, `pread` reads its memory at the address given as the
+/// offset, so that pointer arguments can be followed. Otherwise it gives EBUSY, and EFAULT if the
+/// memory is not mapped.
 ///
 /// Note that a process blocked by a syscall will have its potential sleep cleared (i.e., it will
 /// not wake up after the sleep is finished).
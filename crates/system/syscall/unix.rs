@@ -1,8 +1,11 @@
-use syscall::arch::{syscall0, syscall1, syscall2, syscall3};
+use syscall::arch::{syscall0, syscall1, syscall2, syscall3, syscall4};
 use error::Result;
 
+pub const SYS_ADJTIME: usize = 124;
 pub const SYS_BRK: usize = 45;
 pub const SYS_CHDIR: usize = 12;
+pub const SYS_CHMOD: usize = 15;
+pub const SYS_CHOWN: usize = 182;
 pub const SYS_CLONE: usize = 120;
     pub const CLONE_VM: usize = 0x100;
     pub const CLONE_FS: usize = 0x200;
@@ -19,16 +22,29 @@ pub const SYS_CLONE: usize = 120;
     pub const CLONE_SUPERVISE: usize = 0x400000;
 pub const SYS_CLOSE: usize = 6;
 pub const SYS_CLOCK_GETTIME: usize = 265;
+pub const SYS_CLOCK_SETTIME: usize = 264;
     pub const CLOCK_REALTIME: usize = 1;
+    pub const CLOCK_PROCESS_CPUTIME_ID: usize = 2;
+    pub const CLOCK_THREAD_CPUTIME_ID: usize = 3;
     pub const CLOCK_MONOTONIC: usize = 4;
 pub const SYS_DUP: usize = 41;
 pub const SYS_EXECVE: usize = 11;
 pub const SYS_EXIT: usize = 1;
+pub const SYS_FCHMOD: usize = 94;
+pub const SYS_FCHOWN: usize = 95;
+pub const SYS_FMAP: usize = 90;
+    pub const MAP_WRITE: usize = 1;
 pub const SYS_FPATH: usize = 928;
 pub const SYS_FSTAT: usize = 28;
 pub const SYS_FSYNC: usize = 118;
 pub const SYS_FTRUNCATE: usize = 93;
+pub const SYS_FUNMAP: usize = 92;
+pub const SYS_FUTIMENS: usize = 929;
 pub const SYS_GETPID: usize = 20;
+pub const SYS_GETRUSAGE: usize = 77;
+    pub const RUSAGE_SELF: usize = 0;
+    pub const RUSAGE_THREAD: usize = 1;
+    pub const RUSAGE_CHILDREN: usize = 2;
 pub const SYS_IOPL: usize = 110;
 pub const SYS_LINK: usize = 9;
 pub const SYS_LSEEK: usize = 19;
@@ -51,14 +67,20 @@ pub const SYS_OPEN: usize = 5;
     pub const O_TRUNC: usize = 0x400;
     pub const O_EXCL: usize = 0x800;
 pub const SYS_PIPE2: usize = 331;
+pub const SYS_PREAD: usize = 180;
+pub const SYS_PWRITE: usize = 181;
 pub const SYS_READ: usize = 3;
+pub const SYS_READV: usize = 145;
 pub const SYS_RMDIR: usize = 84;
 pub const SYS_STAT: usize = 18;
     pub const MODE_DIR: u16 = 0x4000;
     pub const MODE_FILE: u16 = 0x8000;
+    pub const MODE_PERM: u16 = 0x0FFF;
 pub const SYS_UNLINK: usize = 10;
+pub const SYS_UTIMENS: usize = 930;
 pub const SYS_WAITPID: usize = 7;
 pub const SYS_WRITE: usize = 4;
+pub const SYS_WRITEV: usize = 146;
 pub const SYS_YIELD: usize = 158;
 
 #[derive(Copy, Clone, Debug, Default)]
@@ -74,7 +96,17 @@ pub struct Stat {
     pub st_size: u32,
     pub st_atime: u32,
     pub st_mtime: u32,
-    pub st_ctime: u32
+    pub st_ctime: u32,
+    pub st_atime_nsec: u32,
+    pub st_mtime_nsec: u32,
+    pub st_ctime_nsec: u32
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(packed)]
+pub struct IoVec {
+    pub iov_base: usize,
+    pub iov_len: usize,
 }
 
 #[derive(Copy, Clone, Debug, Default)]
@@ -84,6 +116,33 @@ pub struct TimeSpec {
     pub tv_nsec: i32,
 }
 
+/// Resource usage, as returned by `sys_getrusage`
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(packed)]
+pub struct RUsage {
+    /// Time spent running in user mode
+    pub ru_utime: TimeSpec,
+    /// Time spent running in the kernel
+    pub ru_stime: TimeSpec,
+    /// Peak memory usage, in kilobytes
+    pub ru_maxrss: usize,
+    /// Number of context switches
+    pub ru_nswitch: usize,
+}
+
+/// Timer settings, written to a `time:` resource to arm it
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(packed)]
+pub struct ITimerSpec {
+    pub it_interval: TimeSpec,
+    pub it_value: TimeSpec,
+}
+
+/// Gradually adjust the realtime clock by `delta`, returning the adjustment still pending in `olddelta`
+pub fn sys_adjtime(delta: &TimeSpec, olddelta: &mut TimeSpec) -> Result<usize> {
+    unsafe { syscall2(SYS_ADJTIME, delta as *const TimeSpec as usize, olddelta as *mut TimeSpec as usize) }
+}
+
 pub unsafe fn sys_brk(addr: usize) -> Result<usize> {
     syscall1(SYS_BRK, addr)
 }
@@ -92,6 +151,14 @@ pub unsafe fn sys_chdir(path: *const u8) -> Result<usize> {
     syscall1(SYS_CHDIR, path as usize)
 }
 
+pub unsafe fn sys_chmod(path: *const u8, mode: usize) -> Result<usize> {
+    syscall2(SYS_CHMOD, path as usize, mode)
+}
+
+pub unsafe fn sys_chown(path: *const u8, uid: usize, gid: usize) -> Result<usize> {
+    syscall3(SYS_CHOWN, path as usize, uid, gid)
+}
+
 pub unsafe fn sys_clone(flags: usize) -> Result<usize> {
     syscall1(SYS_CLONE, flags)
 }
@@ -104,6 +171,10 @@ pub fn sys_clock_gettime(clock: usize, tp: &mut TimeSpec) -> Result<usize> {
     unsafe { syscall2(SYS_CLOCK_GETTIME, clock, tp as *mut TimeSpec as usize) }
 }
 
+pub fn sys_clock_settime(clock: usize, tp: &TimeSpec) -> Result<usize> {
+    unsafe { syscall2(SYS_CLOCK_SETTIME, clock, tp as *const TimeSpec as usize) }
+}
+
 pub fn sys_dup(fd: usize) -> Result<usize> {
     unsafe { syscall1(SYS_DUP, fd) }
 }
@@ -116,6 +187,18 @@ pub fn sys_exit(status: usize) -> Result<usize> {
     unsafe { syscall1(SYS_EXIT, status) }
 }
 
+pub fn sys_fchmod(fd: usize, mode: usize) -> Result<usize> {
+    unsafe { syscall2(SYS_FCHMOD, fd, mode) }
+}
+
+pub fn sys_fchown(fd: usize, uid: usize, gid: usize) -> Result<usize> {
+    unsafe { syscall3(SYS_FCHOWN, fd, uid, gid) }
+}
+
+pub fn sys_fmap(fd: usize, offset: usize, size: usize, flags: usize) -> Result<usize> {
+    unsafe { syscall4(SYS_FMAP, fd, offset, size, flags) }
+}
+
 pub fn sys_fpath(fd: usize, buf: &mut [u8]) -> Result<usize> {
     unsafe { syscall3(SYS_FPATH, fd, buf.as_mut_ptr() as usize, buf.len()) }
 }
@@ -132,10 +215,23 @@ pub fn sys_ftruncate(fd: usize, len: usize) -> Result<usize> {
     unsafe { syscall2(SYS_FTRUNCATE, fd, len) }
 }
 
+/// Set the access and modification times of a file, in that order
+pub fn sys_futimens(fd: usize, times: &[TimeSpec; 2]) -> Result<usize> {
+    unsafe { syscall2(SYS_FUTIMENS, fd, times.as_ptr() as usize) }
+}
+
+pub unsafe fn sys_funmap(addr: usize) -> Result<usize> {
+    syscall1(SYS_FUNMAP, addr)
+}
+
 pub fn sys_getpid() -> Result<usize> {
     unsafe { syscall0(SYS_GETPID) }
 }
 
+pub fn sys_getrusage(who: usize, usage: &mut RUsage) -> Result<usize> {
+    unsafe { syscall2(SYS_GETRUSAGE, who, usage as *mut RUsage as usize) }
+}
+
 pub unsafe fn sys_iopl(level: usize) -> Result<usize> {
     syscall1(SYS_IOPL, level)
 }
@@ -164,10 +260,22 @@ pub unsafe fn sys_pipe2(fds: *mut usize, flags: usize) -> Result<usize> {
     syscall2(SYS_PIPE2, fds as usize, flags)
 }
 
+pub fn sys_pread(fd: usize, buf: &mut [u8], offset: usize) -> Result<usize> {
+    unsafe { syscall4(SYS_PREAD, fd, buf.as_mut_ptr() as usize, buf.len(), offset) }
+}
+
+pub fn sys_pwrite(fd: usize, buf: &[u8], offset: usize) -> Result<usize> {
+    unsafe { syscall4(SYS_PWRITE, fd, buf.as_ptr() as usize, buf.len(), offset) }
+}
+
 pub fn sys_read(fd: usize, buf: &mut [u8]) -> Result<usize> {
     unsafe { syscall3(SYS_READ, fd, buf.as_mut_ptr() as usize, buf.len()) }
 }
 
+pub fn sys_readv(fd: usize, iov: &[IoVec]) -> Result<usize> {
+    unsafe { syscall3(SYS_READV, fd, iov.as_ptr() as usize, iov.len()) }
+}
+
 pub unsafe fn sys_rmdir(path: *const u8) -> Result<usize> {
     syscall1(SYS_RMDIR, path as usize)
 }
@@ -180,6 +288,11 @@ pub unsafe fn sys_unlink(path: *const u8) -> Result<usize> {
     syscall1(SYS_UNLINK, path as usize)
 }
 
+/// Set the access and modification times of a path, in that order
+pub unsafe fn sys_utimens(path: *const u8, times: &[TimeSpec; 2]) -> Result<usize> {
+    syscall2(SYS_UTIMENS, path as usize, times.as_ptr() as usize)
+}
+
 pub fn sys_waitpid(pid: usize, status: &mut usize, options: usize) -> Result<usize> {
     unsafe { syscall3(SYS_WAITPID, pid, status as *mut usize as usize, options) }
 }
@@ -188,6 +301,10 @@ pub fn sys_write(fd: usize, buf: &[u8]) -> Result<usize> {
     unsafe { syscall3(SYS_WRITE, fd, buf.as_ptr() as usize, buf.len()) }
 }
 
+pub fn sys_writev(fd: usize, iov: &[IoVec]) -> Result<usize> {
+    unsafe { syscall3(SYS_WRITEV, fd, iov.as_ptr() as usize, iov.len()) }
+}
+
 pub fn sys_yield() -> Result<usize> {
     unsafe { syscall0(SYS_YIELD) }
 }
@@ -75,6 +75,16 @@ impl Acpi {
     }
 }
 
+impl Acpi {
+    /// The CMOS register holding the century, or zero if there is none
+    pub fn century(&self) -> u8 {
+        match self.fadt {
+            Some(fadt) => fadt.century,
+            None => 0,
+        }
+    }
+}
+
 impl KScheme for Acpi {
     fn scheme(&self) -> &str {
         "acpi"
@@ -1,17 +1,19 @@
 use arch::memory::*;
-use arch::paging::Page;
+use arch::slab::{self, CACHES, CACHE_SIZES};
+
+use core::{cmp, ptr};
 
 #[allocator]
 #[no_mangle]
 pub extern "C" fn __rust_allocate(size: usize, align: usize) -> *mut u8 {
     unsafe {
+        if let Some(cache) = slab::cache_index(size, align) {
+            return CACHES[cache].alloc() as *mut u8;
+        }
+
         let address = alloc_aligned(size, align);
         if address > 0 {
-            for page in 0..(size + CLUSTER_SIZE - 1)/CLUSTER_SIZE {
-                let physical_address = address + page * CLUSTER_SIZE;
-                let virtual_address = physical_address + LOGICAL_OFFSET;
-                Page::new(virtual_address).map_kernel_write(physical_address);
-            }
+            map_logical(address, size);
 
             (address + LOGICAL_OFFSET) as *mut u8
         } else {
@@ -21,39 +23,46 @@ pub extern "C" fn __rust_allocate(size: usize, align: usize) -> *mut u8 {
 }
 
 #[no_mangle]
-pub extern "C" fn __rust_deallocate(ptr: *mut u8, old_size: usize, _align: usize) {
+pub extern "C" fn __rust_deallocate(ptr: *mut u8, old_size: usize, align: usize) {
     unsafe {
+        if let Some(cache) = slab::cache_index(old_size, align) {
+            CACHES[cache].unalloc(ptr as usize);
+            return;
+        }
+
         let address = ptr as usize - LOGICAL_OFFSET;
 
         unalloc(address);
 
-        for page in 0..(old_size + CLUSTER_SIZE - 1)/CLUSTER_SIZE {
-            let physical_address = address + page * CLUSTER_SIZE;
-            let virtual_address = physical_address + LOGICAL_OFFSET;
-            Page::new(virtual_address).unmap();
-        }
+        unmap_logical(address, old_size);
     }
 }
 
 #[no_mangle]
 pub extern "C" fn __rust_reallocate(ptr: *mut u8, old_size: usize, size: usize, align: usize) -> *mut u8 {
+    let old_cache = slab::cache_index(old_size, align);
+    let cache = slab::cache_index(size, align);
+    if old_cache.is_some() || cache.is_some() {
+        if old_cache == cache {
+            return ptr;
+        }
+
+        let new_ptr = __rust_allocate(size, align);
+        if ! new_ptr.is_null() {
+            unsafe { ptr::copy(ptr, new_ptr, cmp::min(old_size, size)) };
+            __rust_deallocate(ptr, old_size, align);
+        }
+        return new_ptr;
+    }
+
     unsafe {
         let old_address = ptr as usize - LOGICAL_OFFSET;
         let address = realloc_aligned(old_address, size, align);
 
         if address > 0 {
             if address != old_address {
-                for page in 0..(old_size + CLUSTER_SIZE - 1)/CLUSTER_SIZE {
-                    let physical_address = old_address + page * CLUSTER_SIZE;
-                    let virtual_address = physical_address + LOGICAL_OFFSET;
-                    Page::new(virtual_address).unmap();
-                }
-
-                for page in 0..(size + CLUSTER_SIZE - 1)/CLUSTER_SIZE {
-                    let physical_address = address + page * CLUSTER_SIZE;
-                    let virtual_address = physical_address + LOGICAL_OFFSET;
-                    Page::new(virtual_address).map_kernel_write(physical_address);
-                }
+                unmap_logical(old_address, old_size);
+                map_logical(address, size);
             }
 
             (address + LOGICAL_OFFSET) as *mut u8
@@ -64,11 +73,22 @@ pub extern "C" fn __rust_reallocate(ptr: *mut u8, old_size: usize, size: usize,
 }
 
 #[no_mangle]
-pub extern "C" fn __rust_reallocate_inplace(ptr: *mut u8, _old_size: usize, size: usize, _align: usize) -> usize {
-    unsafe { realloc_inplace(ptr as usize, size) }
+pub extern "C" fn __rust_reallocate_inplace(ptr: *mut u8, old_size: usize, size: usize, align: usize) -> usize {
+    if let Some(old_cache) = slab::cache_index(old_size, align) {
+        return if slab::cache_index(size, align) == Some(old_cache) {
+            size
+        } else {
+            old_size
+        };
+    }
+
+    unsafe { realloc_inplace(ptr as usize - LOGICAL_OFFSET, size) }
 }
 
 #[no_mangle]
-pub extern "C" fn __rust_usable_size(size: usize, _align: usize) -> usize {
-    size
+pub extern "C" fn __rust_usable_size(size: usize, align: usize) -> usize {
+    match slab::cache_index(size, align) {
+        Some(cache) => CACHE_SIZES[cache],
+        None => size
+    }
 }
@@ -15,16 +15,18 @@ use common::time::Duration;
 
 use core::cell::UnsafeCell;
 use core::slice::{self, Iter, IterMut};
-use core::{mem, ptr};
+use core::{cmp, mem, ptr};
 use core::ops::DerefMut;
 
+use system::scheme::Packet;
+
 use fs::Resource;
 
 use syscall;
 
 use system::error::{Error, Result, EBADF, EFAULT, ENOMEM, ESRCH, ENOENT, EINVAL};
 
-use sync::WaitMap;
+use sync::{WaitCondition, WaitMap};
 
 pub const CONTEXT_IMAGE_ADDR: usize = 0x8048000;
 pub const CONTEXT_IMAGE_SIZE: usize = 0x10000000;
@@ -38,6 +40,10 @@ pub const CONTEXT_MMAP_SIZE: usize = 0x20000000;
 pub const CONTEXT_STACK_ADDR: usize = CONTEXT_MMAP_ADDR + CONTEXT_MMAP_SIZE + memory::CLUSTER_SIZE;
 pub const CONTEXT_STACK_SIZE: usize = 0x100000;
 
+/// The size of the allocation holding a kernel stack: a guard cluster, the stack, a cluster for
+/// the `fx` area, and another guard cluster
+const KERNEL_STACK_ALLOC: usize = CONTEXT_STACK_SIZE + 3 * memory::CLUSTER_SIZE;
+
 pub struct ContextManager {
     pub inner: Vec<Box<Context>>,
     pub enabled: bool,
@@ -198,16 +204,155 @@ pub unsafe fn context_switch() {
     }
 }
 
+/// Allocate a kernel stack, followed by the 512 byte `fx` area. Returns 0 if no memory is left.
+///
+/// The stack is used through the logical mapping, which no context ever maps over, and the
+/// clusters below the stack and above the `fx` area are left unmapped. Running off either end
+/// faults on a guard cluster, see `Context::stack_guard`.
+unsafe fn alloc_kernel_stack() -> usize {
+    let physical_address = memory::alloc(KERNEL_STACK_ALLOC);
+    if physical_address == 0 {
+        return 0;
+    }
+
+    let stack_address = physical_address + memory::CLUSTER_SIZE;
+    memory::map_logical(stack_address, CONTEXT_STACK_SIZE + memory::CLUSTER_SIZE);
+
+    // The logical range starts out mapped, so unmap the guard clusters
+    memory::unmap_logical(physical_address, memory::CLUSTER_SIZE);
+    memory::unmap_logical(stack_address + CONTEXT_STACK_SIZE + memory::CLUSTER_SIZE, memory::CLUSTER_SIZE);
+
+    stack_address + memory::LOGICAL_OFFSET
+}
+
+/// Free a kernel stack allocated with `alloc_kernel_stack`. The guard clusters stay unmapped.
+unsafe fn unalloc_kernel_stack(kernel_stack: usize) {
+    let stack_address = kernel_stack - memory::LOGICAL_OFFSET;
+    memory::unmap_logical(stack_address, CONTEXT_STACK_SIZE + memory::CLUSTER_SIZE);
+    memory::unalloc(stack_address - memory::CLUSTER_SIZE);
+}
+
+/// The exit status of a context killed by `oom_kill`
+pub const OOM_STATUS: usize = 137;
+
+/// Check if an allocation of `size` bytes could succeed once memory is freed, so that contexts are
+/// not killed for requests that can never be satisfied
+fn oom_satisfiable(size: usize) -> bool {
+    size > 0 && size <= memory::CLUSTER_SIZE << memory::MAX_ORDER
+        && size <= memory::memory_used() + memory::memory_free()
+}
+
+/// Kill the non-critical context using the most memory, and wait for its memory to be freed.
+/// Returns false if there is nothing left to kill, or if the victim blocked before exiting.
+///
+/// Contexts started by the kernel, such as init, are critical, as is the current context, which
+/// should fail its allocation with `ENOMEM` instead. The victim exits the next time it leaves the
+/// kernel, see `Context::kill`, so blocked contexts are not picked, unless their wait is
+/// interruptible. While an earlier victim has
+/// not exited, it still holds its memory, so it is waited for instead of picking another.
+pub unsafe fn oom_kill() -> bool {
+    let victim_pid = {
+        let contexts = &mut *::env().contexts.get();
+        let current_pid = match contexts.current() {
+            Ok(current) => current.pid,
+            Err(_) => return false
+        };
+
+        let mut earlier_victim = None;
+        for context in contexts.iter() {
+            if context.killed && ! context.exited {
+                earlier_victim = Some(context.pid);
+            }
+        }
+
+        match earlier_victim {
+            // The current context exits once the allocation fails
+            Some(pid) if pid == current_pid => return false,
+            Some(pid) => pid,
+            None => {
+                let mut victim_i = None;
+                let mut victim_usage = 0;
+                for (i, context) in contexts.iter().enumerate() {
+                    if context.pid != current_pid && context.ppid > 0
+                        && (context.blocked == 0 || context.interrupt.is_some())
+                        && ! context.exited && ! context.killed {
+                        let usage = context.memory_usage();
+                        if usage > victim_usage {
+                            victim_i = Some(i);
+                            victim_usage = usage;
+                        }
+                    }
+                }
+
+                let victim = match victim_i.and_then(|i| contexts.get_mut(i).ok()) {
+                    Some(victim) => victim,
+                    None => return false
+                };
+                syslog_critical!("out of memory: killing PID {} ({}), using {} KB",
+                                 victim.pid, victim.name, victim_usage / 1024);
+                victim.kill();
+                victim.pid
+            }
+        }
+    };
+
+    // Switch away until the victim has exited and been cleaned up. A victim that blocks first
+    // still holds its memory, so the allocation fails instead of killing another context.
+    loop {
+        match (& *::env().contexts.get()).find(victim_pid) {
+            Ok(victim) => if victim.blocked > 0 && ! victim.exited {
+                return false;
+            },
+            Err(_) => return true
+        }
+        context_switch();
+    }
+}
+
+/// Allocate memory for a context with `memory::alloc_user`, killing other contexts with
+/// `oom_kill` while there is not enough. Returns 0 if no memory is left.
+pub unsafe fn alloc_user_or_kill(size: usize) -> usize {
+    loop {
+        let address = memory::alloc_user(size);
+        if address > 0 || ! oom_satisfiable(size) || ! oom_kill() {
+            return address;
+        }
+    }
+}
+
+/// Reallocate memory for a context with `memory::realloc_user`, killing other contexts with
+/// `oom_kill` while there is not enough. Returns 0, leaving `ptr` allocated, if no memory is left.
+pub unsafe fn realloc_user_or_kill(ptr: usize, size: usize) -> usize {
+    loop {
+        let address = memory::realloc_user(ptr, size);
+        if address > 0 || ! oom_satisfiable(size) || ! oom_kill() {
+            return address;
+        }
+    }
+}
+
 pub unsafe fn context_clone(regs: &Regs) -> Result<usize> {
     let contexts = &mut *::env().contexts.get();
     let flags = regs.bx;
 
-    let kernel_stack = memory::alloc(CONTEXT_STACK_SIZE + 512);
+    let kernel_stack = alloc_kernel_stack();
     if kernel_stack > 0 {
         let clone_pid = Context::next_pid();
 
-        let context = {
-            let mut parent = try!(contexts.current_mut());
+        let mut context = {
+            // The context list may change if memory runs out, see `oom_kill`
+            let parent = &mut **try!(contexts.current_mut());
+
+            // Free the kernel stack if memory for the child runs out
+            macro_rules! try_clone {
+                ($expr:expr) => (match $expr {
+                    Ok(value) => value,
+                    Err(err) => {
+                        unalloc_kernel_stack(kernel_stack);
+                        return Err(err);
+                    }
+                })
+            }
 
             //debugln!("{}: {}: clone to {}: {:X}", parent.pid, parent.name, clone_pid, flags);
 
@@ -229,7 +374,7 @@ pub unsafe fn context_clone(regs: &Regs) -> Result<usize> {
             ::memcpy(fx as *mut u8, parent.fx as *const u8, 512);
 
             let stack = if let Some(ref entry) = parent.stack {
-                let physical_address = memory::alloc(entry.virtual_size);
+                let physical_address = alloc_user_or_kill(entry.virtual_size);
                 if physical_address > 0 {
                     ::memcpy(physical_address as *mut u8,
                              entry.physical_address as *const u8,
@@ -239,10 +384,12 @@ pub unsafe fn context_clone(regs: &Regs) -> Result<usize> {
                         virtual_address: entry.virtual_address,
                         virtual_size: entry.virtual_size,
                         writeable: entry.writeable,
+                        executable: entry.executable,
                         allocated: true,
+                        shared: None,
                     })
                 } else {
-                    None
+                    try_clone!(Err(Error::new(ENOMEM)))
                 }
             } else {
                 None
@@ -253,7 +400,7 @@ pub unsafe fn context_clone(regs: &Regs) -> Result<usize> {
 
                 parent.image.clone()
             } else {
-                Arc::new(UnsafeCell::new((*parent.image.get()).dup()))
+                Arc::new(UnsafeCell::new(try_clone!((*parent.image.get()).dup())))
             };
 
             let heap = if flags & syscall::CLONE_VM == syscall::CLONE_VM {
@@ -261,7 +408,7 @@ pub unsafe fn context_clone(regs: &Regs) -> Result<usize> {
 
                 parent.heap.clone()
             } else {
-                Arc::new(UnsafeCell::new((*parent.heap.get()).dup()))
+                Arc::new(UnsafeCell::new(try_clone!((*parent.heap.get()).dup())))
             };
 
             let mmap = if flags & syscall::CLONE_VM == syscall::CLONE_VM {
@@ -269,7 +416,7 @@ pub unsafe fn context_clone(regs: &Regs) -> Result<usize> {
 
                 parent.mmap.clone()
             } else {
-                Arc::new(UnsafeCell::new((*parent.mmap.get()).dup()))
+                Arc::new(UnsafeCell::new(try_clone!((*parent.mmap.get()).dup())))
             };
 
             let env_vars = if flags & syscall::CLONE_VM == syscall::CLONE_VM {
@@ -297,6 +444,7 @@ pub unsafe fn context_clone(regs: &Regs) -> Result<usize> {
 
                             files.push(ContextFile {
                                 fd: file.fd,
+                                path: file.path.clone(),
                                 resource: resource,
                             });
                         },
@@ -309,7 +457,7 @@ pub unsafe fn context_clone(regs: &Regs) -> Result<usize> {
             // Must be last, so blocking does not cause a deadlock
             let vfork = if flags & syscall::CLONE_VFORK == syscall::CLONE_VFORK {
                 parent.block("context_clone vfork");
-                Some(parent.deref_mut() as *mut Context)
+                Some(&mut *parent as *mut Context)
             } else {
                 None
             };
@@ -318,16 +466,28 @@ pub unsafe fn context_clone(regs: &Regs) -> Result<usize> {
                 pid: clone_pid,
                 ppid: parent.pid,
                 name: parent.name.clone(),
+                args: parent.args.clone(),
                 iopl: parent.iopl,
                 blocked: 0,
                 exited: false,
+                killed: false,
+                interrupt: None,
                 switch: 0,
                 time: 0,
+                user_time: 0,
+                kernel_time: 0,
+                peak_memory: 0,
+                child_user_time: 0,
+                child_kernel_time: 0,
+                child_peak_memory: 0,
                 vfork: vfork,
                 wake: None,
 
                 supervised: flags & syscall::CLONE_SUPERVISE == syscall::CLONE_SUPERVISE,
                 blocked_syscall: false,
+                syscall_stop: None,
+                syscall_reply: None,
+                syscall_forwarded: false,
                 current_syscall: None,
 
                 kernel_stack: kernel_stack,
@@ -347,6 +507,7 @@ pub unsafe fn context_clone(regs: &Regs) -> Result<usize> {
             }
         };
 
+        context.update_peak_memory();
         contexts.push(context);
 
         if flags & syscall::CLONE_VFORK == syscall::CLONE_VFORK {
@@ -415,12 +576,53 @@ unsafe extern "cdecl" fn context_box(/*Throw away extra params from ABI*/ _rdi:
     syscall::process::exit(0);
 }
 
+/// Memory that can be mapped into more than one context
+///
+/// It is freed when the last reference, from a resource or a mapping, is dropped.
+pub struct SharedMemory {
+    pub physical_address: usize,
+    pub size: usize,
+}
+
+impl SharedMemory {
+    /// Allocate zeroed, page aligned shared memory
+    pub fn new(size: usize) -> Result<SharedMemory> {
+        let physical_address = if size > 0 {
+            let physical_address = unsafe { alloc_user_or_kill(size) };
+            if physical_address == 0 {
+                return Err(Error::new(ENOMEM));
+            }
+            unsafe { ::memset(physical_address as *mut u8, 0, size) };
+            physical_address
+        } else {
+            0
+        };
+
+        Ok(SharedMemory {
+            physical_address: physical_address,
+            size: size,
+        })
+    }
+}
+
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        if self.physical_address > 0 {
+            unsafe { memory::unalloc(self.physical_address) };
+        }
+    }
+}
+
 pub struct ContextMemory {
     pub physical_address: usize,
     pub virtual_address: usize,
     pub virtual_size: usize,
     pub writeable: bool,
+    /// Mapped executable for userspace. Writeable memory is never executable.
+    pub executable: bool,
     pub allocated: bool,
+    /// The shared memory this is a mapping of, kept alive while it is mapped
+    pub shared: Option<Arc<SharedMemory>>,
 }
 
 impl ContextMemory {
@@ -429,6 +631,9 @@ impl ContextMemory {
             if self.writeable {
                 Page::new(self.virtual_address + i * 4096)
                     .map_user_write(self.physical_address + i * 4096);
+            } else if self.executable {
+                Page::new(self.virtual_address + i * 4096)
+                    .map_user_exec(self.physical_address + i * 4096);
             } else {
                 Page::new(self.virtual_address + i * 4096)
                     .map_user_read(self.physical_address + i * 4096);
@@ -454,6 +659,8 @@ impl Drop for ContextMemory {
 
 pub struct ContextFile {
     pub fd: usize,
+    /// The path the file was opened with, kept so it can be listed without asking the resource
+    pub path: String,
     pub resource: Box<Resource>,
 }
 
@@ -472,10 +679,26 @@ impl ContextZone {
         }
     }
 
-    pub fn dup(&self) -> ContextZone {
+    /// Copy the memory of the zone, sharing shared memory. Returns `ENOMEM` if there is not enough
+    /// memory for the copy.
+    pub fn dup(&self) -> Result<ContextZone> {
         let mut mem: Vec<ContextMemory> = Vec::new();
         for entry in self.memory.iter() {
-            let physical_address = unsafe { memory::alloc(entry.virtual_size) };
+            // Shared memory stays shared with the new context
+            if let Some(ref shared) = entry.shared {
+                mem.push(ContextMemory {
+                    physical_address: entry.physical_address,
+                    virtual_address: entry.virtual_address,
+                    virtual_size: entry.virtual_size,
+                    writeable: entry.writeable,
+                    executable: entry.executable,
+                    allocated: false,
+                    shared: Some(shared.clone()),
+                });
+                continue;
+            }
+
+            let physical_address = unsafe { alloc_user_or_kill(entry.virtual_size) };
             if physical_address > 0 {
                 //TODO: Remap pages during memcpy
                 unsafe {
@@ -491,18 +714,21 @@ impl ContextZone {
                     virtual_address: entry.virtual_address,
                     virtual_size: entry.virtual_size,
                     writeable: entry.writeable,
+                    executable: entry.executable,
                     allocated: true,
+                    shared: None,
                 });
             } else {
                 //debugln!("{}: {}: failed to dup memory {:X}:{:X} for {}", parent.pid, parent.name, entry.virtual_address, entry.virtual_address + entry.virtual_size, clone_pid);
+                return Err(Error::new(ENOMEM));
             }
         }
 
-        ContextZone {
+        Ok(ContextZone {
             address: self.address,
             size: self.size,
             memory: mem
-        }
+        })
     }
 
     pub fn size(&self) -> usize {
@@ -607,6 +833,14 @@ impl EnvVar {
     }
 }
 
+/// The answer of a supervisor to a syscall of a supervised context
+pub enum SyscallReply {
+    /// Return the value without running the syscall
+    Return(usize),
+    /// Run the syscall in the packet, which may differ from the one that was made
+    Forward(Packet),
+}
+
 pub struct Context {
     // These members are used for control purposes by the scheduler {
     /// The PID of the context
@@ -615,16 +849,35 @@ pub struct Context {
     pub ppid: usize,
     /// The name of the context
     pub name: Cow<'static, str>,
+    /// The arguments of the program, set by exec
+    pub args: Vec<String>,
     /// The I/O privilege level
     pub iopl: usize,
     /// Indicates that the context is blocked, and should not be switched to
     pub blocked: usize,
     /// Indicates that the context exited
     pub exited: bool,
+    /// Indicates that the context was killed, see `Context::kill`, and exits when it next leaves
+    /// the kernel
+    pub killed: bool,
+    /// The condition of the interruptible wait the context is blocked in, woken by `Context::kill`
+    pub interrupt: Option<*const WaitCondition>,
     /// How many times was the context switched to
     pub switch: usize,
     /// The number of time slices used
     pub time: usize,
+    /// The number of time slices used while running in user mode
+    pub user_time: usize,
+    /// The number of time slices used while running in kernel mode
+    pub kernel_time: usize,
+    /// The largest amount of memory seen in use, in bytes
+    pub peak_memory: usize,
+    /// Time slices used in user mode by exited children
+    pub child_user_time: usize,
+    /// Time slices used in kernel mode by exited children
+    pub child_kernel_time: usize,
+    /// The largest peak memory of exited children, in bytes
+    pub child_peak_memory: usize,
     /// Indicates that the context needs to unblock parent
     pub vfork: Option<*mut Context>,
     /// When to wake up
@@ -640,6 +893,15 @@ pub struct Context {
     ///
     /// This means that the process is waiting for the superviser to handle the syscall.
     pub blocked_syscall: bool,
+    /// The syscall stop that the supervisor has not read yet
+    pub syscall_stop: Option<Packet>,
+    /// How the supervisor handled the syscall stop
+    pub syscall_reply: Option<SyscallReply>,
+    /// The current syscall was forwarded by the supervisor, which sees its result when it returns
+    ///
+    /// This is kept here, and not on the kernel stack, so a context cloned during the syscall
+    /// does not stop for a supervisor it does not have.
+    pub syscall_forwarded: bool,
     /// The current syscall
     pub current_syscall: Option<(usize, usize, usize, usize, usize)>,
 
@@ -714,16 +976,28 @@ impl Context {
             pid: Context::next_pid(),
             ppid: 0,
             name: "kidle".into(),
+            args: Vec::new(),
             iopl: 3,
             blocked: 0,
             exited: false,
+            killed: false,
+            interrupt: None,
             switch: 0,
             time: 0,
+            user_time: 0,
+            kernel_time: 0,
+            peak_memory: 0,
+            child_user_time: 0,
+            child_kernel_time: 0,
+            child_peak_memory: 0,
             vfork: None,
             wake: None,
 
             supervised: false,
             blocked_syscall: false,
+            syscall_stop: None,
+            syscall_reply: None,
+            syscall_forwarded: false,
             current_syscall: None,
 
             kernel_stack: 0,
@@ -745,7 +1019,7 @@ impl Context {
     }
 
     pub unsafe fn new(name: Cow<'static, str>, call: usize, args: &Vec<usize>) -> Box<Self> {
-        let kernel_stack = memory::alloc(CONTEXT_STACK_SIZE + 512);
+        let kernel_stack = alloc_kernel_stack();
 
         let mut regs = Regs::default();
         regs.sp = kernel_stack + CONTEXT_STACK_SIZE - 128;
@@ -756,16 +1030,28 @@ impl Context {
             pid: Context::next_pid(),
             ppid: 0,
             name: name,
+            args: Vec::new(),
             iopl: 3,
             blocked: 0,
             exited: false,
+            killed: false,
+            interrupt: None,
             switch: 0,
             time: 0,
+            user_time: 0,
+            kernel_time: 0,
+            peak_memory: 0,
+            child_user_time: 0,
+            child_kernel_time: 0,
+            child_peak_memory: 0,
             vfork: None,
             wake: None,
 
             supervised: false,
             blocked_syscall: false,
+            syscall_stop: None,
+            syscall_reply: None,
+            syscall_forwarded: false,
             current_syscall: None,
 
             kernel_stack: kernel_stack,
@@ -820,6 +1106,15 @@ impl Context {
         // debugln!("    BLOCK {}: {}: {} {}", self.pid, self.name, self.blocked, reason);
     }
 
+    /// Kill the context, so that it exits when it next leaves the kernel. If it is blocked in an
+    /// interruptible wait, it is woken to give up on it.
+    pub unsafe fn kill(&mut self) {
+        self.killed = true;
+        if let Some(condition) = self.interrupt {
+            (*condition).notify("Context::kill");
+        }
+    }
+
     pub fn unblock(&mut self, _reason: &str) {
         // debugln!("    UNBLOCK {}: {}: {} {}", self.pid, self.name, self.blocked, reason);
         if self.blocked > 0 {
@@ -827,6 +1122,28 @@ impl Context {
         }
     }
 
+    /// The amount of memory currently mapped for this context, in bytes
+    pub fn memory_usage(&self) -> usize {
+        let mut size = 0;
+        if let Some(ref stack) = self.stack {
+            size += stack.virtual_size;
+        }
+        for zone in [&self.image, &self.heap, &self.mmap].iter() {
+            for mem in unsafe { (*zone.get()).memory.iter() } {
+                size += mem.virtual_size;
+            }
+        }
+        size
+    }
+
+    /// Record the current memory usage, if it is a new peak
+    pub fn update_peak_memory(&mut self) {
+        let usage = self.memory_usage();
+        if usage > self.peak_memory {
+            self.peak_memory = usage;
+        }
+    }
+
     pub fn exit(&mut self) {
         // debugln!("    EXIT {}: {}", self.pid, self.name);
         self.files = Arc::new(UnsafeCell::new(Vec::new()));
@@ -893,6 +1210,17 @@ impl Context {
         Err(Error::new(EBADF))
     }
 
+    /// Get the path a file descriptor was opened with
+    pub fn get_file_path<'a>(&self, fd: usize) -> Result<&'a String> {
+        for file in unsafe { (*self.files.get()).iter() } {
+            if file.fd == fd {
+                return Ok(&file.path);
+            }
+        }
+
+        Err(Error::new(EBADF))
+    }
+
     /// Get a mutable resource from a file descriptor
     pub fn get_file_mut<'a>(&mut self, fd: usize) -> Result<&'a mut Box<Resource>> {
         for file in unsafe { (*self.files.get()).iter_mut() } {
@@ -958,6 +1286,32 @@ impl Context {
         Err(Error::new(EFAULT))
     }
 
+    /// Check if an address is in one of the guard clusters around the kernel or user stack
+    ///
+    /// The user stack is guarded by the whole gap between the end of the mmap zone and the stack,
+    /// which is one cluster plus the random offset of the stack, and by the cluster above it.
+    /// These are never mapped for userspace.
+    pub fn stack_guard(&self, address: usize) -> bool {
+        let guard = |start: usize| address >= start && address < start + memory::CLUSTER_SIZE;
+
+        if self.kernel_stack > 0 {
+            if guard(self.kernel_stack - memory::CLUSTER_SIZE)
+                || guard(self.kernel_stack + CONTEXT_STACK_SIZE + memory::CLUSTER_SIZE) {
+                return true;
+            }
+        }
+
+        if let Some(ref stack) = self.stack {
+            let gap_start = cmp::min(CONTEXT_MMAP_ADDR + CONTEXT_MMAP_SIZE, stack.virtual_address - memory::CLUSTER_SIZE);
+            if (address >= gap_start && address < stack.virtual_address)
+                || guard(stack.virtual_address + stack.virtual_size) {
+                return true;
+            }
+        }
+
+        false
+    }
+
     /// Translate to physical if a ptr is inside of the mapped memory
     pub fn translate(&self, ptr: usize, len: usize) -> Result<usize> {
         if let Some(ref stack) = self.stack {
@@ -1126,7 +1480,7 @@ impl Drop for Context {
             unsafe { (*vfork).unblock("Context::drop vfork") };
         }
         if self.kernel_stack > 0 {
-            unsafe { memory::unalloc(self.kernel_stack) };
+            unsafe { unalloc_kernel_stack(self.kernel_stack) };
         }
     }
 }
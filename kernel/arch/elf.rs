@@ -16,6 +16,21 @@ mod arch;
 #[path="x86_64/elf.rs"]
 mod arch;
 
+/// Segment flag: executable
+pub const PF_X: u32 = 1;
+/// Segment flag: writeable
+pub const PF_W: u32 = 2;
+/// Segment flag: readable
+pub const PF_R: u32 = 4;
+
+/// Segment type: loadable
+const PT_LOAD: u32 = 1;
+/// Segment type: dynamic linking information
+const PT_DYNAMIC: u32 = 2;
+
+/// Object type: position independent executable or shared object
+pub const ET_DYN: u16 = 3;
+
 /// An ELF executable
 pub struct Elf<'a> {
     pub data: &'a [u8],
@@ -43,7 +58,7 @@ impl<'a> Elf<'a> {
         for i in 0..header.ph_len {
             let segment = ptr::read((self.data.as_ptr() as usize + header.ph_off as usize + i as usize * header.ph_ent_len as usize) as *const ElfSegment);
 
-            if segment._type == 1 {
+            if segment._type == PT_LOAD {
                 segments.push(segment);
             }
         }
@@ -57,6 +72,62 @@ impl<'a> Elf<'a> {
         header.entry as usize
     }
 
+    /// Check if the executable is position independent, so it can be loaded at any base
+    pub unsafe fn position_independent(&self) -> bool {
+        let header = &*(self.data.as_ptr() as usize as *const ElfHeader);
+        header._type == ET_DYN
+    }
+
+    /// Get the relocations listed by the dynamic section, which a position independent executable
+    /// needs applied when it is loaded at a base address
+    pub unsafe fn relocations(&self) -> Vec<ElfRel> {
+        let header = &*(self.data.as_ptr() as usize as *const ElfHeader);
+
+        let mut segments = Vec::new();
+        for i in 0..header.ph_len {
+            segments.push(ptr::read((self.data.as_ptr() as usize + header.ph_off as usize + i as usize * header.ph_ent_len as usize) as *const ElfSegment));
+        }
+
+        let mut table = 0;
+        let mut table_len = 0;
+        let mut entry_len = mem::size_of::<ElfRel>();
+        for segment in segments.iter().filter(|segment| segment._type == PT_DYNAMIC) {
+            for i in 0..segment.file_len as usize / mem::size_of::<ElfDyn>() {
+                let offset = segment.off as usize + i * mem::size_of::<ElfDyn>();
+                if offset + mem::size_of::<ElfDyn>() > self.data.len() {
+                    break;
+                }
+
+                let entry = ptr::read((self.data.as_ptr() as usize + offset) as *const ElfDyn);
+                match entry.tag {
+                    DT_REL => table = entry.val as usize,
+                    DT_RELSZ => table_len = entry.val as usize,
+                    DT_RELENT => entry_len = entry.val as usize,
+                    _ => ()
+                }
+            }
+        }
+
+        let mut relocations = Vec::new();
+        if table > 0 && entry_len >= mem::size_of::<ElfRel>() {
+            // The table is given by its address, so find it in the file through its segment
+            for segment in segments.iter().filter(|segment| segment._type == PT_LOAD) {
+                let start = segment.vaddr as usize;
+                if table >= start && table + table_len <= start + segment.file_len as usize {
+                    let offset = segment.off as usize + table - start;
+                    if offset + table_len <= self.data.len() {
+                        for i in 0..table_len / entry_len {
+                            relocations.push(ptr::read((self.data.as_ptr() as usize + offset + i * entry_len) as *const ElfRel));
+                        }
+                    }
+                    break;
+                }
+            }
+        }
+
+        relocations
+    }
+
     /// ELF symbol
     pub unsafe fn symbol(&self, name: &str) -> usize {
         let header = &*(self.data.as_ptr() as usize as *const ElfHeader);
@@ -1,17 +1,51 @@
 // TODO: Doc the rest
 
-use core::{cmp, intrinsics, mem};
+use core::{cmp, intrinsics, mem, u32};
 use core::ops::{Index, IndexMut};
 use core::{ptr, slice};
 
 use system::error::{Result, Error, ENOMEM};
 
-use super::paging::{Page, PAGE_END};
+use super::paging::{Page, PAGE_END, PHYSICAL_END};
 
 pub const CLUSTER_ADDRESS: usize = PAGE_END;
-pub const CLUSTER_COUNT: usize = 1024 * 1024; // 4 GiB
 pub const CLUSTER_SIZE: usize = 4096; // Of 4 K chunks
 
+/// The largest block is `1 << MAX_ORDER` clusters, 1 GiB
+pub const MAX_ORDER: usize = 18;
+
+/// The cluster state and links are kept from `CLUSTER_ADDRESS` to here, below the addresses used
+/// by contexts. This limits memory to about 40 GiB.
+const METADATA_END: usize = 0x8000000;
+/// The size of the state and `Link` of each cluster
+const METADATA_SIZE: usize = 1 + LINK_SIZE;
+/// The size of a `Link`
+const LINK_SIZE: usize = 8;
+/// The alignment of the first cluster. Blocks up to this size are aligned to their size.
+pub const CLUSTER_ALIGN: usize = 4 * 1024 * 1024;
+
+/// Memory below `LOGICAL_OFFSET`, which can be mapped into the kernel heap and used by devices
+pub const ZONE_LOW: usize = 0;
+/// Memory above 4 GiB, which is only used by contexts
+pub const ZONE_HIGH: usize = 1;
+/// The number of zones
+pub const ZONES: usize = 2;
+/// The start of `ZONE_HIGH`. Memory from `LOGICAL_OFFSET` to here is not used, as the kernel heap
+/// replaces its identity mapping.
+const HIGH_START: u64 = 0x100000000;
+
+/// The cluster does not exist, or is used by firmware
+const STATE_RESERVED: u8 = 0xFF;
+/// The cluster is inside a block, but not the first cluster of it
+const STATE_INSIDE: u8 = 0;
+/// The first cluster of a free block, ORed with the order of the block
+const STATE_FREE: u8 = 0x80;
+/// The first cluster of an allocation, which has its cluster count in its link
+const STATE_USED: u8 = 0x40;
+
+/// No cluster, for the links
+const LINK_NONE: u32 = u32::MAX;
+
 pub const LOGICAL_OFFSET: usize = 0x80000000;
 
 /// A wrapper around raw pointers
@@ -185,109 +219,425 @@ struct MemoryMapEntry {
 
 const MEMORY_MAP: *const MemoryMapEntry = 0x500 as *const MemoryMapEntry;
 
-/// Get the data (address) of a given cluster
-pub unsafe fn cluster(number: usize) -> usize {
-    if number < CLUSTER_COUNT {
-        ptr::read((CLUSTER_ADDRESS + number * mem::size_of::<usize>()) as *const usize)
+/// The free list links of a free block, or the cluster count of an allocation in `next`
+#[derive(Copy, Clone)]
+struct Link {
+    prev: u32,
+    next: u32,
+}
+
+/// The first cluster of the free blocks of each zone and order
+static mut FREE_LISTS: [[u32; MAX_ORDER + 1]; ZONES] = [[LINK_NONE; MAX_ORDER + 1]; ZONES];
+/// The number of free blocks of each zone and order
+static mut FREE_BLOCKS: [[usize; MAX_ORDER + 1]; ZONES] = [[0; MAX_ORDER + 1]; ZONES];
+/// The number of allocations of each order, by the order of their cluster count
+static mut USED_BLOCKS: [usize; MAX_ORDER + 1] = [0; MAX_ORDER + 1];
+/// The number of usable clusters in each zone
+static mut TOTAL_CLUSTERS: [usize; ZONES] = [0; ZONES];
+/// The number of free clusters in each zone
+static mut FREE_CLUSTERS: [usize; ZONES] = [0; ZONES];
+
+/// The number of clusters, set by `cluster_init` from the memory map
+static mut CLUSTER_COUNT: usize = 0;
+/// The address of the first cluster, after the state and links of every cluster
+static mut CLUSTER_BASE: usize = 0;
+/// The state of each cluster, one byte each, see `STATE_RESERVED` and friends
+const STATE_ADDRESS: usize = CLUSTER_ADDRESS;
+/// The free list links of each cluster, after the states
+static mut LINK_ADDRESS: usize = 0;
+
+unsafe fn state(number: usize) -> u8 {
+    ptr::read((STATE_ADDRESS + number) as *const u8)
+}
+
+unsafe fn set_state(number: usize, state: u8) {
+    ptr::write((STATE_ADDRESS + number) as *mut u8, state);
+}
+
+unsafe fn link(number: usize) -> Link {
+    ptr::read((LINK_ADDRESS + number * LINK_SIZE) as *const Link)
+}
+
+unsafe fn set_link(number: usize, link: Link) {
+    ptr::write((LINK_ADDRESS + number * LINK_SIZE) as *mut Link, link);
+}
+
+/// The zone of a cluster
+fn zone(number: usize) -> usize {
+    if cluster_to_address(number) as u64 >= HIGH_START {
+        ZONE_HIGH
     } else {
-        0
+        ZONE_LOW
     }
 }
 
-/// Set the address of a cluster
-pub unsafe fn set_cluster(number: usize, address: usize) {
-    if number < CLUSTER_COUNT {
-        ptr::write((CLUSTER_ADDRESS + number * mem::size_of::<usize>()) as *mut usize,
-                   address);
+/// The smallest order with at least `count` clusters
+fn order_of(count: usize) -> usize {
+    let mut order = 0;
+    while (1 << order) < count {
+        order += 1;
     }
+    order
 }
 
-/// Convert an adress to the cluster number
-pub unsafe fn address_to_cluster(address: usize) -> usize {
-    if address >= CLUSTER_ADDRESS + CLUSTER_COUNT * mem::size_of::<usize>() {
-        (address - CLUSTER_ADDRESS - CLUSTER_COUNT * mem::size_of::<usize>()) / CLUSTER_SIZE
+/// Add a free block to the free list of its zone and order
+unsafe fn list_push(number: usize, order: usize) {
+    let zone = zone(number);
+    let head = FREE_LISTS[zone][order];
+    if head != LINK_NONE {
+        let mut head_link = link(head as usize);
+        head_link.prev = number as u32;
+        set_link(head as usize, head_link);
+    }
+    set_link(number, Link {
+        prev: LINK_NONE,
+        next: head,
+    });
+    FREE_LISTS[zone][order] = number as u32;
+    FREE_BLOCKS[zone][order] += 1;
+    set_state(number, STATE_FREE | order as u8);
+}
+
+/// Remove a free block from the free list of its zone and order
+unsafe fn list_remove(number: usize, order: usize) {
+    let zone = zone(number);
+    let number_link = link(number);
+    if number_link.prev != LINK_NONE {
+        let mut prev_link = link(number_link.prev as usize);
+        prev_link.next = number_link.next;
+        set_link(number_link.prev as usize, prev_link);
     } else {
-        0
+        FREE_LISTS[zone][order] = number_link.next;
+    }
+    if number_link.next != LINK_NONE {
+        let mut next_link = link(number_link.next as usize);
+        next_link.prev = number_link.prev;
+        set_link(number_link.next as usize, next_link);
+    }
+    FREE_BLOCKS[zone][order] -= 1;
+    set_state(number, STATE_INSIDE);
+}
+
+/// Free a block, merging it with its buddy while the buddy is free and in the same zone
+unsafe fn free_block(mut number: usize, mut order: usize) {
+    while order < MAX_ORDER {
+        let buddy = number ^ (1 << order);
+        if buddy >= CLUSTER_COUNT || state(buddy) != STATE_FREE | order as u8 || zone(buddy) != zone(number) {
+            break;
+        }
+
+        list_remove(buddy, order);
+        number = cmp::min(number, buddy);
+        order += 1;
     }
+
+    list_push(number, order);
 }
 
-pub unsafe fn cluster_to_address(number: usize) -> usize {
-    CLUSTER_ADDRESS + CLUSTER_COUNT * mem::size_of::<usize>() + number * CLUSTER_SIZE
+/// Free a range of clusters, as the largest aligned blocks that fit
+unsafe fn free_range(mut number: usize, mut count: usize) {
+    while count > 0 {
+        let mut order = 0;
+        while order < MAX_ORDER && number % (2 << order) == 0 && (2 << order) <= count {
+            order += 1;
+        }
+
+        free_block(number, order);
+
+        number += 1 << order;
+        count -= 1 << order;
+    }
+}
+
+/// Convert an adress to the cluster number
+pub fn address_to_cluster(address: usize) -> usize {
+    unsafe {
+        if address >= CLUSTER_BASE {
+            (address - CLUSTER_BASE) / CLUSTER_SIZE
+        } else {
+            CLUSTER_COUNT
+        }
+    }
+}
+
+pub fn cluster_to_address(number: usize) -> usize {
+    unsafe { CLUSTER_BASE + number * CLUSTER_SIZE }
+}
+
+/// An entry of the memory map, as start and end addresses, or `(0, 0)` if it is not usable
+///
+/// The map is written by `asm/memory_map.asm`, and ends at the first empty entry. Memory past
+/// `PHYSICAL_END` cannot be reached by the kernel and is left out.
+unsafe fn memory_map_entry(i: usize) -> Option<(u64, u64)> {
+    if i >= (0x5000 - 0x500) / mem::size_of::<MemoryMapEntry>() {
+        return None;
+    }
+
+    let entry = &*MEMORY_MAP.offset(i as isize);
+    if entry.len == 0 {
+        None
+    } else if entry.class == 1 {
+        Some((cmp::min(entry.base, PHYSICAL_END),
+              cmp::min(entry.base.saturating_add(entry.len), PHYSICAL_END)))
+    } else {
+        Some((0, 0))
+    }
 }
 
 /// Initialize clusters
 pub unsafe fn cluster_init() {
+    // Size the clusters to cover the end of usable memory, leaving room for their state and links
+    let mut end = 0;
+    let mut i = 0;
+    while let Some((_, entry_end)) = memory_map_entry(i) {
+        end = cmp::max(end, entry_end);
+        i += 1;
+    }
+
+    let max_count = (METADATA_END - CLUSTER_ADDRESS) / METADATA_SIZE;
+    let count = cmp::min(end.saturating_sub(CLUSTER_ADDRESS as u64) / CLUSTER_SIZE as u64, max_count as u64) as usize;
+
+    LINK_ADDRESS = STATE_ADDRESS + count;
+    CLUSTER_BASE = (LINK_ADDRESS + count * LINK_SIZE + CLUSTER_ALIGN - 1) / CLUSTER_ALIGN * CLUSTER_ALIGN;
+    CLUSTER_COUNT = count;
+
     // First, set all clusters to the not present value
-    for cluster in 0..CLUSTER_COUNT {
-        set_cluster(cluster, 0xFFFFFFFF);
-    }
-
-    // Next, set all valid clusters to the free value
-    // TODO: Optimize this function
-    for i in 0..((0x5000 - 0x500) / mem::size_of::<MemoryMapEntry>()) {
-        let entry = &*MEMORY_MAP.offset(i as isize);
-        if entry.len > 0 && entry.class == 1 {
-            for cluster in 0..CLUSTER_COUNT {
-                let address = cluster_to_address(cluster);
-                if address as u64 >= entry.base &&
-                   (address as u64 + CLUSTER_SIZE as u64) <= (entry.base + entry.len) {
-                    set_cluster(cluster, 0);
-                }
+    ::memset(STATE_ADDRESS as *mut u8, STATE_RESERVED as i32, CLUSTER_COUNT);
+
+    // Next, free the clusters inside each usable entry, skipping the memory between the zones
+    let clusters_start = CLUSTER_BASE as u64;
+    let clusters_end = clusters_start + CLUSTER_COUNT as u64 * CLUSTER_SIZE as u64;
+    let mut i = 0;
+    while let Some((entry_start, entry_end)) = memory_map_entry(i) {
+        i += 1;
+
+        for &(zone_start, zone_end) in [(0, LOGICAL_OFFSET as u64), (HIGH_START, clusters_end)].iter() {
+            let start = cmp::max(cmp::max(entry_start, zone_start), clusters_start);
+            let end = cmp::min(cmp::min(entry_end, zone_end), clusters_end);
+            if end <= start {
+                continue;
+            }
+
+            let first = ((start - clusters_start + CLUSTER_SIZE as u64 - 1) / CLUSTER_SIZE as u64) as usize;
+            let last = ((end - clusters_start) / CLUSTER_SIZE as u64) as usize;
+            if last > first {
+                let count = last - first;
+
+                ::memset((STATE_ADDRESS + first) as *mut u8, STATE_INSIDE as i32, count);
+                free_range(first, count);
+
+                TOTAL_CLUSTERS[zone(first)] += count;
+                FREE_CLUSTERS[zone(first)] += count;
             }
         }
     }
 }
 
+/// Map `size` bytes of clusters at `address` into the kernel heap, at `address + LOGICAL_OFFSET`
+pub unsafe fn map_logical(address: usize, size: usize) {
+    for page in 0..(size + CLUSTER_SIZE - 1)/CLUSTER_SIZE {
+        let physical_address = address + page * CLUSTER_SIZE;
+        let virtual_address = physical_address + LOGICAL_OFFSET;
+        Page::new(virtual_address).map_kernel_write(physical_address);
+    }
+}
+
+/// Unmap clusters mapped with `map_logical`
+pub unsafe fn unmap_logical(address: usize, size: usize) {
+    for page in 0..(size + CLUSTER_SIZE - 1)/CLUSTER_SIZE {
+        let physical_address = address + page * CLUSTER_SIZE;
+        let virtual_address = physical_address + LOGICAL_OFFSET;
+        Page::new(virtual_address).unmap();
+    }
+}
+
 /// Allocate memory
 pub unsafe fn alloc(size: usize) -> usize {
     alloc_aligned(size, 1)
 }
 
 /// Allocate memory, aligned
+///
+/// The memory is below `LOGICAL_OFFSET`, so it can be used by the kernel heap and by devices.
 pub unsafe fn alloc_aligned(size: usize, align: usize) -> usize {
-    if size > 0 {
-        let mut number = 0;
-        let mut count = 0;
-
-        for i in 0..CLUSTER_COUNT {
-            if cluster(i) == 0 && (count > 0 || cluster_to_address(i) % align == 0) {
-                if count == 0 {
-                    number = i;
-                }
+    alloc_zone(size, align, ZONE_LOW)
+}
+
+/// Allocate page aligned memory for a context
+///
+/// Memory above 4 GiB is used first, as only contexts can use it.
+pub unsafe fn alloc_user(size: usize) -> usize {
+    let address = alloc_zone(size, CLUSTER_SIZE, ZONE_HIGH);
+    if address > 0 {
+        address
+    } else {
+        alloc_zone(size, CLUSTER_SIZE, ZONE_LOW)
+    }
+}
+
+/// A buffer of the current context given to a device, which may only reach 32-bit addresses
+///
+/// Memory from `alloc_user` may be above 4 GiB, so such a buffer is copied through memory from
+/// `ZONE_LOW`. Data the device writes is copied back to the context when this is dropped.
+pub struct DmaBuffer {
+    /// The address of the buffer in the context
+    virtual_address: usize,
+    /// The physical address given to the device
+    physical_address: usize,
+    size: usize,
+    /// Copied through memory from `ZONE_LOW`
+    bounce: bool,
+    /// The device writes to the buffer
+    from_device: bool,
+}
+
+impl DmaBuffer {
+    /// Prepare `size` bytes at `virtual_address`, which the context maps to `physical_address`
+    pub unsafe fn new(virtual_address: usize, physical_address: usize, size: usize, from_device: bool) -> Result<DmaBuffer> {
+        if physical_address as u64 + size as u64 <= LOGICAL_OFFSET as u64 {
+            return Ok(DmaBuffer {
+                virtual_address: virtual_address,
+                physical_address: physical_address,
+                size: size,
+                bounce: false,
+                from_device: from_device,
+            });
+        }
+
+        let bounce = alloc(size);
+        if bounce == 0 {
+            return Err(Error::new(ENOMEM));
+        }
+        map_logical(bounce, size);
+        if ! from_device {
+            ptr::copy(virtual_address as *const u8, (bounce + LOGICAL_OFFSET) as *mut u8, size);
+        }
 
-                count += 1;
+        Ok(DmaBuffer {
+            virtual_address: virtual_address,
+            physical_address: bounce,
+            size: size,
+            bounce: true,
+            from_device: from_device,
+        })
+    }
 
-                if count * CLUSTER_SIZE >= size {
-                    break;
+    /// The address to give the device
+    pub fn physical_address(&self) -> usize {
+        self.physical_address
+    }
+}
+
+impl Drop for DmaBuffer {
+    fn drop(&mut self) {
+        if self.bounce {
+            unsafe {
+                if self.from_device {
+                    ptr::copy((self.physical_address + LOGICAL_OFFSET) as *const u8, self.virtual_address as *mut u8, self.size);
                 }
-            } else {
-                count = 0;
+                unmap_logical(self.physical_address, self.size);
+                unalloc(self.physical_address);
             }
         }
+    }
+}
+
+/// Run `f` with the cluster at `address` mapped for the kernel
+///
+/// Clusters below 4 GiB are mapped by the 4 KiB page tables, where the current context may have
+/// replaced them, so they are mapped and then restored. Clusters above are always mapped.
+unsafe fn with_cluster<F: FnOnce()>(address: usize, writeable: bool, f: F) {
+    if address as u64 >= HIGH_START {
+        f();
+    } else {
+        let mut page = Page::new(address);
+        let old = page.entry_data();
+        if writeable {
+            page.map_kernel_write(address);
+        } else {
+            page.map_kernel_read(address);
+        }
+
+        f();
+
+        page.set_entry_data(old);
+        page.flush();
+    }
+}
 
-        if count * CLUSTER_SIZE >= size {
-            let address = cluster_to_address(number);
+/// Copy memory of another context, from the physical `address` found by `Context::translate`
+///
+/// Each part is copied through the kernel stack, as the mapping made for it may hide `buf`.
+pub unsafe fn read_physical(address: usize, buf: &mut [u8]) {
+    let mut bounce = [0; 512];
+    let mut i = 0;
+    while i < buf.len() {
+        let read_address = address + i;
+        let cluster_address = read_address - read_address % CLUSTER_SIZE;
+        let count = cmp::min(cmp::min(buf.len() - i, bounce.len()),
+                             cluster_address + CLUSTER_SIZE - read_address);
+
+        with_cluster(cluster_address, false, || {
+            ::memmove(bounce.as_mut_ptr(), read_address as *const u8, count);
+        });
+        for (b, p) in buf[i .. i + count].iter_mut().zip(bounce.iter()) {
+            *b = *p;
+        }
 
-            for i in number..number + count {
-                set_cluster(i, address);
+        i += count;
+    }
+}
 
-                let cluster_address = cluster_to_address(i);
+/// Allocate zeroed memory from a zone
+///
+/// The smallest free block that fits is split in halves until it fits, and the clusters past
+/// the end of the allocation are freed again. `align` can be at most `CLUSTER_ALIGN`.
+unsafe fn alloc_zone(size: usize, align: usize, zone: usize) -> usize {
+    if size == 0 || align > CLUSTER_ALIGN {
+        return 0;
+    }
 
-                let mut page = Page::new(cluster_address);
-                let old = page.entry_data();
-                page.map_kernel_write(cluster_address);
+    let count = (size + CLUSTER_SIZE - 1) / CLUSTER_SIZE;
+    let order = cmp::max(order_of(count), order_of((align + CLUSTER_SIZE - 1) / CLUSTER_SIZE));
+    if order > MAX_ORDER {
+        return 0;
+    }
 
-                ::memset(cluster_address as *mut u8, 0, CLUSTER_SIZE);
+    let mut block_order = order;
+    while block_order <= MAX_ORDER && FREE_LISTS[zone][block_order] == LINK_NONE {
+        block_order += 1;
+    }
+    if block_order > MAX_ORDER {
+        return 0;
+    }
 
-                page.set_entry_data(old);
-                page.flush();
-            }
+    let number = FREE_LISTS[zone][block_order] as usize;
+    list_remove(number, block_order);
+    while block_order > order {
+        block_order -= 1;
+        list_push(number + (1 << block_order), block_order);
+    }
+    if (1 << order) > count {
+        free_range(number + count, (1 << order) - count);
+    }
 
-            return address;
-        }
+    set_state(number, STATE_USED);
+    set_link(number, Link {
+        prev: LINK_NONE,
+        next: count as u32,
+    });
+    USED_BLOCKS[order_of(count)] += 1;
+    FREE_CLUSTERS[zone] -= count;
+
+    let address = cluster_to_address(number);
+    for i in 0..count {
+        let cluster_address = address + i * CLUSTER_SIZE;
+        with_cluster(cluster_address, true, || {
+            ::memset(cluster_address as *mut u8, 0, CLUSTER_SIZE);
+        });
     }
 
-    0
+    address
 }
 
 /// Allocate a type
@@ -295,31 +645,32 @@ pub unsafe fn alloc_type<T>() -> *mut T {
     alloc(mem::size_of::<T>()) as *mut T
 }
 
-pub unsafe fn alloc_size(ptr: usize) -> usize {
-    let mut size = 0;
-
-    if ptr > 0 {
-        for i in address_to_cluster(ptr)..CLUSTER_COUNT {
-            if cluster(i) == ptr {
-                size += CLUSTER_SIZE;
-            } else {
-                break;
-            }
-        }
+/// The first cluster of the allocation at `ptr`, if there is one
+unsafe fn allocation(ptr: usize) -> Option<usize> {
+    let number = address_to_cluster(ptr);
+    if number < CLUSTER_COUNT && cluster_to_address(number) == ptr && state(number) == STATE_USED {
+        Some(number)
+    } else {
+        None
     }
+}
 
-    size
+pub unsafe fn alloc_size(ptr: usize) -> usize {
+    match allocation(ptr) {
+        Some(number) => link(number).next as usize * CLUSTER_SIZE,
+        None => 0
+    }
 }
 
 pub unsafe fn unalloc(ptr: usize) {
-    if ptr > 0 {
-        for i in address_to_cluster(ptr)..CLUSTER_COUNT {
-            if cluster(i) == ptr {
-                set_cluster(i, 0);
-            } else {
-                break;
-            }
-        }
+    if let Some(number) = allocation(ptr) {
+        let count = link(number).next as usize;
+
+        set_state(number, STATE_INSIDE);
+        USED_BLOCKS[order_of(count)] -= 1;
+        FREE_CLUSTERS[zone(number)] += count;
+
+        free_range(number, count);
     }
 }
 
@@ -333,6 +684,20 @@ pub unsafe fn realloc(ptr: usize, size: usize) -> usize {
 }
 
 pub unsafe fn realloc_aligned(ptr: usize, size: usize, align: usize) -> usize {
+    realloc_zone(ptr, size, align, ZONE_LOW)
+}
+
+/// Reallocate memory for a context, see `alloc_user`
+pub unsafe fn realloc_user(ptr: usize, size: usize) -> usize {
+    let address = realloc_zone(ptr, size, CLUSTER_SIZE, ZONE_HIGH);
+    if address > 0 || size == 0 {
+        address
+    } else {
+        realloc_zone(ptr, size, CLUSTER_SIZE, ZONE_LOW)
+    }
+}
+
+unsafe fn realloc_zone(ptr: usize, size: usize, align: usize, zone: usize) -> usize {
     let mut ret = 0;
 
     if size == 0 {
@@ -344,35 +709,21 @@ pub unsafe fn realloc_aligned(ptr: usize, size: usize, align: usize) -> usize {
         if size <= old_size {
             ret = ptr;
         } else {
-            ret = alloc_aligned(size, align);
-            if ptr > 0 {
-                if ret > 0 {
-                    let copy_size = cmp::min(old_size, size);
-
-                    let read_cluster = address_to_cluster(ptr);
-                    let write_cluster = address_to_cluster(ret);
-
-                    for i in 0..(copy_size + CLUSTER_SIZE - 1)/CLUSTER_SIZE {
-                        let read_address = cluster_to_address(read_cluster + i);
-                        let write_address = cluster_to_address(write_cluster + i);
-
-                        let mut read_page = Page::new(read_address);
-                        let read_old = read_page.entry_data();
-                        read_page.map_kernel_read(read_address);
-
-                        let mut write_page = Page::new(write_address);
-                        let write_old = write_page.entry_data();
-                        write_page.map_kernel_write(write_address);
-
-                        ::memmove(write_address as *mut u8, read_address as *const u8, CLUSTER_SIZE);
-
-                        write_page.set_entry_data(write_old);
-                        write_page.flush();
-
-                        read_page.set_entry_data(read_old);
-                        read_page.flush();
-                    }
+            ret = alloc_zone(size, align, zone);
+            if ptr > 0 && ret > 0 {
+                let copy_size = cmp::min(old_size, size);
+
+                for i in 0..(copy_size + CLUSTER_SIZE - 1)/CLUSTER_SIZE {
+                    let read_address = ptr + i * CLUSTER_SIZE;
+                    let write_address = ret + i * CLUSTER_SIZE;
+
+                    with_cluster(read_address, false, || {
+                        with_cluster(write_address, true, || {
+                            ::memmove(write_address as *mut u8, read_address as *const u8, CLUSTER_SIZE);
+                        });
+                    });
                 }
+
                 unalloc(ptr);
             }
         }
@@ -392,28 +743,26 @@ pub unsafe fn realloc_inplace(ptr: usize, size: usize) -> usize {
 
 pub fn memory_used() -> usize {
     let mut ret = 0;
-
-    unsafe {
-        for i in 0..CLUSTER_COUNT {
-            if cluster(i) != 0 && cluster(i) != 0xFFFFFFFF {
-                ret += CLUSTER_SIZE;
-            }
-        }
+    for zone in 0..ZONES {
+        ret += unsafe { TOTAL_CLUSTERS[zone] - FREE_CLUSTERS[zone] } * CLUSTER_SIZE;
     }
-
     ret
 }
 
 pub fn memory_free() -> usize {
     let mut ret = 0;
-
-    unsafe {
-        for i in 0..CLUSTER_COUNT {
-            if cluster(i) == 0 {
-                ret += CLUSTER_SIZE;
-            }
-        }
+    for zone in 0..ZONES {
+        ret += unsafe { FREE_CLUSTERS[zone] } * CLUSTER_SIZE;
     }
-
     ret
 }
+
+/// The number of free blocks of `order` in `zone`
+pub fn free_blocks(zone: usize, order: usize) -> usize {
+    unsafe { FREE_BLOCKS[zone][order] }
+}
+
+/// The number of allocations of `order`, rounding their size up to a block
+pub fn used_blocks(order: usize) -> usize {
+    unsafe { USED_BLOCKS[order] }
+}
@@ -3,4 +3,6 @@ pub mod elf;
 pub mod memory;
 pub mod paging;
 pub mod regs;
+pub mod slab;
+pub mod tsc;
 pub mod tss;
@@ -10,14 +10,17 @@ mod arch;
 #[path="x86_64/regs.rs"]
 mod arch;
 
+/// The syscall in the registers, as read by a supervisor: an `id` of 0, the number in `a`, and the
+/// arguments in `b` to `e`
 impl Into<Packet> for Regs {
     fn into(self) -> Packet {
         Packet {
-            id: 0, // TODO
+            id: 0,
             a: self.ax,
             b: self.bx,
             c: self.cx,
             d: self.dx,
+            e: self.si,
         }
     }
 }
@@ -0,0 +1,188 @@
+use core::{cmp, mem, ptr};
+
+use super::memory::{self, LOGICAL_OFFSET};
+
+/// The object size of each cache
+pub const CACHE_SIZES: [usize; 8] = [16, 32, 64, 128, 256, 512, 1024, 2048];
+
+/// The number of objects a slab is sized for
+const SLAB_OBJECTS: usize = 16;
+
+/// Header at the start of every slab
+struct SlabHeader {
+    /// Previous slab with free objects, 0 for none
+    prev: usize,
+    /// Next slab with free objects, 0 for none
+    next: usize,
+    /// First free object, each free object holds the address of the next, 0 for none
+    free: usize,
+    /// Number of allocated objects
+    used: usize,
+}
+
+/// A cache of objects of one size, carved out of slabs of clusters
+pub struct SlabCache {
+    /// The size of each object
+    pub size: usize,
+    /// The first slab with free objects, 0 for none
+    partial: usize,
+    /// The number of slabs
+    pub slabs: usize,
+    /// The number of allocated objects
+    pub used: usize,
+}
+
+impl SlabCache {
+    const fn new(size: usize) -> SlabCache {
+        SlabCache {
+            size: size,
+            partial: 0,
+            slabs: 0,
+            used: 0,
+        }
+    }
+
+    /// The size of each slab. Slabs are aligned to their size, so the header of an object's slab
+    /// can be found by masking its address.
+    pub fn slab_size(&self) -> usize {
+        cmp::max(memory::CLUSTER_SIZE, self.size * SLAB_OBJECTS)
+    }
+
+    /// The offset of the first object, after the header
+    fn first_object(&self) -> usize {
+        (mem::size_of::<SlabHeader>() + self.size - 1) / self.size * self.size
+    }
+
+    /// The number of objects in each slab
+    pub fn slab_objects(&self) -> usize {
+        (self.slab_size() - self.first_object()) / self.size
+    }
+
+    unsafe fn header<'a>(&self, slab: usize) -> &'a mut SlabHeader {
+        &mut *(slab as *mut SlabHeader)
+    }
+
+    unsafe fn push_partial(&mut self, slab: usize) {
+        let partial = self.partial;
+        if partial > 0 {
+            self.header(partial).prev = slab;
+        }
+
+        let header = self.header(slab);
+        header.prev = 0;
+        header.next = partial;
+        self.partial = slab;
+    }
+
+    unsafe fn remove_partial(&mut self, slab: usize) {
+        let (prev, next) = {
+            let header = self.header(slab);
+            (header.prev, header.next)
+        };
+
+        if prev > 0 {
+            self.header(prev).next = next;
+        } else {
+            self.partial = next;
+        }
+        if next > 0 {
+            self.header(next).prev = prev;
+        }
+    }
+
+    /// Allocate a slab and add its objects to the cache
+    unsafe fn grow(&mut self) -> bool {
+        let size = self.slab_size();
+        let address = memory::alloc_aligned(size, size);
+        if address == 0 {
+            return false;
+        }
+        memory::map_logical(address, size);
+
+        let slab = address + LOGICAL_OFFSET;
+        let mut free = 0;
+        for i in (0..self.slab_objects()).rev() {
+            let object = slab + self.first_object() + i * self.size;
+            ptr::write(object as *mut usize, free);
+            free = object;
+        }
+
+        ptr::write(slab as *mut SlabHeader, SlabHeader {
+            prev: 0,
+            next: 0,
+            free: free,
+            used: 0,
+        });
+        self.push_partial(slab);
+        self.slabs += 1;
+
+        true
+    }
+
+    /// Allocate an object, returning 0 if no memory is left
+    pub unsafe fn alloc(&mut self) -> usize {
+        if self.partial == 0 && ! self.grow() {
+            return 0;
+        }
+
+        let slab = self.partial;
+        let (object, full) = {
+            let header = self.header(slab);
+            let object = header.free;
+            header.free = ptr::read(object as *const usize);
+            header.used += 1;
+            (object, header.free == 0)
+        };
+        if full {
+            self.remove_partial(slab);
+        }
+        self.used += 1;
+
+        object
+    }
+
+    /// Free an object. Empty slabs are returned to the frame allocator, unless it is the last
+    /// slab with free objects.
+    pub unsafe fn unalloc(&mut self, object: usize) {
+        let slab = object & !(self.slab_size() - 1);
+        let (was_full, empty, alone) = {
+            let header = self.header(slab);
+            let was_full = header.free == 0;
+            ptr::write(object as *mut usize, header.free);
+            header.free = object;
+            header.used -= 1;
+            (was_full, header.used == 0, header.prev == 0 && header.next == 0)
+        };
+        self.used -= 1;
+
+        if was_full {
+            self.push_partial(slab);
+        } else if empty && ! alone {
+            self.remove_partial(slab);
+
+            let size = self.slab_size();
+            let address = slab - LOGICAL_OFFSET;
+            memory::unmap_logical(address, size);
+            memory::unalloc(address);
+            self.slabs -= 1;
+        }
+    }
+}
+
+/// The caches, one for each of `CACHE_SIZES`
+pub static mut CACHES: [SlabCache; 8] = [
+    SlabCache::new(16),
+    SlabCache::new(32),
+    SlabCache::new(64),
+    SlabCache::new(128),
+    SlabCache::new(256),
+    SlabCache::new(512),
+    SlabCache::new(1024),
+    SlabCache::new(2048),
+];
+
+/// The cache for objects of `size` and `align`, if they are small enough
+pub fn cache_index(size: usize, align: usize) -> Option<usize> {
+    let size = cmp::max(size, align);
+    CACHE_SIZES.iter().position(|&cache_size| size <= cache_size)
+}
@@ -0,0 +1,106 @@
+//! Time stamp counter, used to interpolate the clocks between PIT ticks
+
+/// The number of PIT ticks to measure the TSC frequency over
+const CALIBRATION_TICKS: u64 = 64;
+
+/// Read the time stamp counter
+pub fn rdtsc() -> u64 {
+    let low: u32;
+    let high: u32;
+    unsafe { asm!("rdtsc" : "={eax}"(low), "={edx}"(high) : : : "intel", "volatile"); }
+    (high as u64) << 32 | low as u64
+}
+
+/// Check CPUID for a time stamp counter
+pub fn has_tsc() -> bool {
+    let edx: u32;
+    unsafe { asm!("cpuid" : "={edx}"(edx) : "{eax}"(1u32) : "ebx", "ecx" : "intel", "volatile"); }
+    edx & 1 << 4 == 1 << 4
+}
+
+/// Time stamp counter state
+pub struct Tsc {
+    /// Whether the CPU has a TSC
+    present: bool,
+    /// The TSC value at the last PIT tick
+    last: u64,
+    /// The TSC value when calibration started, on the first PIT tick
+    start: u64,
+    /// The number of PIT ticks counted during calibration
+    ticks: u64,
+    /// Nanoseconds per TSC cycle, as a 32.32 fixed point number. Zero until calibrated
+    scale: u64,
+    /// The length of a PIT tick in nanoseconds
+    tick_nanos: u64,
+}
+
+impl Tsc {
+    pub fn new(tick_nanos: u64) -> Tsc {
+        Tsc {
+            present: has_tsc(),
+            last: 0,
+            start: 0,
+            ticks: 0,
+            scale: 0,
+            tick_nanos: tick_nanos,
+        }
+    }
+
+    /// Whether the TSC frequency is known
+    pub fn calibrated(&self) -> bool {
+        self.scale > 0
+    }
+
+    /// Record a PIT tick, calibrating the TSC against it on the first ticks
+    pub fn tick(&mut self) {
+        if ! self.present {
+            return;
+        }
+
+        self.last = rdtsc();
+
+        // The PIT was not running before its first tick, so calibration starts here
+        if self.start == 0 {
+            self.start = self.last;
+            return;
+        }
+
+        if self.ticks < CALIBRATION_TICKS {
+            self.ticks += 1;
+            if self.ticks == CALIBRATION_TICKS {
+                let cycles = self.last - self.start;
+                if cycles > 0 {
+                    self.scale = ((CALIBRATION_TICKS * self.tick_nanos) << 32) / cycles;
+                    syslog_info!("  * TSC: {} MHz", cycles * 1000 / (CALIBRATION_TICKS * self.tick_nanos));
+                }
+            }
+        }
+    }
+
+    /// The number of TSC cycles in `nanos` nanoseconds, or 0 if the TSC is not calibrated
+    pub fn cycles(&self, nanos: u64) -> u64 {
+        if self.calibrated() {
+            (nanos << 32) / self.scale
+        } else {
+            0
+        }
+    }
+
+    /// Nanoseconds since the last PIT tick
+    ///
+    /// This is kept below the length of a tick, so the clocks never go backwards when the next
+    /// tick is counted.
+    pub fn offset(&self) -> i32 {
+        if self.calibrated() {
+            let cycles = rdtsc() - self.last;
+            let nanos = cycles.checked_mul(self.scale).map_or(self.tick_nanos, |nanos| nanos >> 32);
+            if nanos < self.tick_nanos {
+                nanos as i32
+            } else {
+                self.tick_nanos as i32 - 1
+            }
+        } else {
+            0
+        }
+    }
+}
@@ -85,3 +85,40 @@ pub struct ElfSymbol {
     pub other: u8,
     pub sh_index: ElfHalf,
 }
+
+/// A dynamic section entry
+#[repr(packed)]
+pub struct ElfDyn {
+    pub tag: ElfWord,
+    pub val: ElfWord,
+}
+
+/// Dynamic section tag: the address of the relocation table
+pub const DT_REL: ElfWord = 17;
+/// Dynamic section tag: the size of the relocation table
+pub const DT_RELSZ: ElfWord = 18;
+/// Dynamic section tag: the size of a relocation
+pub const DT_RELENT: ElfWord = 19;
+
+/// Relocation type: the base address plus the value at the offset
+const R_386_RELATIVE: ElfWord = 8;
+
+/// A relocation, with the addend at its offset
+#[repr(packed)]
+pub struct ElfRel {
+    pub offset: ElfAddr,
+    pub info: ElfWord,
+}
+
+impl ElfRel {
+    /// Check if the relocation only depends on the base address
+    pub fn relative(&self) -> bool {
+        self.info & 0xFF == R_386_RELATIVE
+    }
+
+    /// Apply a relative relocation to an image loaded, and mapped, at `base`
+    pub unsafe fn relocate(&self, base: usize) {
+        let address = (base + self.offset as usize) as *mut usize;
+        *address = (*address).wrapping_add(base);
+    }
+}
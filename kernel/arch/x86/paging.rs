@@ -205,6 +205,9 @@ pub const PAGE_DIRECTORY: usize = 0x1000000;
 pub const PAGE_TABLES: usize = PAGE_DIRECTORY + PAGE_TABLE_SIZE * PAGE_ENTRY_SIZE;
 pub const PAGE_END: usize = PAGE_TABLES + PAGE_TABLE_SIZE * PAGE_TABLE_SIZE * PAGE_ENTRY_SIZE;
 
+/// The end of the physical memory that is identity mapped
+pub const PHYSICAL_END: u64 = 0x100000000;
+
 /// A memory page
 pub struct Page {
     /// The virtual address
@@ -303,6 +306,15 @@ impl Page {
         self.flush();
     }
 
+    /// Map the memory page to a given physical memory address, and allow userspace read/execute
+    /// access
+    ///
+    /// Without PAE there is no no-execute bit, so this is the same as `map_user_read`, and every
+    /// readable page is executable. Writeable pages are still never mapped as code.
+    pub unsafe fn map_user_exec(&mut self, physical_address: usize) {
+        self.map_user_read(physical_address);
+    }
+
     /// Unmap the memory page
     pub unsafe fn unmap(&mut self) {
         self.set_entry_data(0);
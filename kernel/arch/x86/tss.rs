@@ -28,3 +28,10 @@ pub struct Tss {
     pub trap: u16,
     pub iomap_base: u16,
 }
+
+impl Tss {
+    /// Does nothing, there is no interrupt stack table in 32-bit mode. A double fault from an
+    /// overflowing kernel stack would need a task gate, which is not set up, so kernel stack
+    /// overflows are only reported on x86_64. Here they still reset the machine.
+    pub fn set_fault_stack(&mut self, _sp: usize) {}
+}
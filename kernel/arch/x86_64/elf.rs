@@ -86,3 +86,40 @@ pub struct ElfSymbol {
     pub value: ElfAddr,
     pub size: ElfXword,
 }
+
+/// A dynamic section entry
+#[repr(packed)]
+pub struct ElfDyn {
+    pub tag: ElfXword,
+    pub val: ElfXword,
+}
+
+/// Dynamic section tag: the address of the relocation table
+pub const DT_REL: ElfXword = 7;
+/// Dynamic section tag: the size of the relocation table
+pub const DT_RELSZ: ElfXword = 8;
+/// Dynamic section tag: the size of a relocation
+pub const DT_RELENT: ElfXword = 9;
+
+/// Relocation type: the base address plus the addend
+const R_X86_64_RELATIVE: ElfXword = 8;
+
+/// A relocation, with an explicit addend
+#[repr(packed)]
+pub struct ElfRel {
+    pub offset: ElfAddr,
+    pub info: ElfXword,
+    pub addend: i64,
+}
+
+impl ElfRel {
+    /// Check if the relocation only depends on the base address
+    pub fn relative(&self) -> bool {
+        self.info & 0xFFFFFFFF == R_X86_64_RELATIVE
+    }
+
+    /// Apply a relative relocation to an image loaded, and mapped, at `base`
+    pub unsafe fn relocate(&self, base: usize) {
+        *((base + self.offset as usize) as *mut usize) = base.wrapping_add(self.addend as usize);
+    }
+}
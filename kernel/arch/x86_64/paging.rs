@@ -14,9 +14,13 @@ pub const PF_GLOBAL: usize = 1 << 8;
 pub const PF_ALLOC: usize = 1 << 9;
 pub const PF_EXEC: usize = 1 << 10;
 pub const PF_STACK: usize = 1 << 11;
+pub const PF_NO_EXEC: usize = 1 << 63;
 
 pub const PF_ALL: usize =  0xFFF;
-pub const PF_NONE: usize = 0xFFFFFFFFFFFFF000;
+pub const PF_NONE: usize = 0x000FFFFFFFFFF000;
+
+/// `PF_NO_EXEC` if the processor supports it and it has been enabled, otherwise 0
+static mut NO_EXEC: usize = 0;
 
 // PAGE_LEVEL_4:
 // 512 qwords pointing to page directory pointers
@@ -26,6 +30,8 @@ pub const PF_NONE: usize = 0xFFFFFFFFFFFFF000;
 // 512 qwords pointing to page tables
 // PAGE_TABLES:
 // 512 * 512 qwords pointing to pages
+// PAGE_HIGH_DIRECTORIES:
+// 508 * 512 qwords pointing to 2 MiB pages, identity mapping 4 GiB to 512 GiB
 // PAGE_END:
 //
 
@@ -41,7 +47,13 @@ pub const PAGE_LEVEL_4: usize = 0x1000000;
 pub const PAGE_DIR_PTRS: usize = PAGE_LEVEL_4 + PAGE_TABLE_SIZE * PAGE_ENTRY_SIZE;
 pub const PAGE_DIRECTORIES: usize = PAGE_DIR_PTRS + PAGE_TABLE_SIZE * PAGE_ENTRY_SIZE;
 pub const PAGE_TABLES: usize = PAGE_DIRECTORIES + 4 * PAGE_TABLE_SIZE * PAGE_ENTRY_SIZE;
-pub const PAGE_END: usize = PAGE_TABLES + 4 * PAGE_TABLE_SIZE * PAGE_TABLE_SIZE * PAGE_ENTRY_SIZE;
+pub const PAGE_HIGH_DIRECTORIES: usize = PAGE_TABLES + 4 * PAGE_TABLE_SIZE * PAGE_TABLE_SIZE * PAGE_ENTRY_SIZE;
+pub const PAGE_END: usize = PAGE_HIGH_DIRECTORIES + (PAGE_TABLE_SIZE - 4) * PAGE_TABLE_SIZE * PAGE_ENTRY_SIZE;
+
+/// The size of the pages mapped by `PAGE_HIGH_DIRECTORIES`
+pub const PAGE_HIGH_SIZE: usize = 2 * 1024 * 1024;
+/// The end of the physical memory that is identity mapped
+pub const PHYSICAL_END: u64 = (PAGE_TABLE_SIZE * PAGE_TABLE_SIZE * PAGE_HIGH_SIZE) as u64;
 
 /// A memory page
 pub struct Page {
@@ -52,6 +64,20 @@ pub struct Page {
 impl Page {
     /// Initialize the memory page
     pub unsafe fn init() {
+        // Enable no-execute pages, if supported, before any are mapped
+        let edx: u32;
+        asm!("cpuid" : "={edx}"(edx) : "{eax}"(0x80000001u32) : "ebx", "ecx" : "intel", "volatile");
+        if edx & 1 << 20 == 1 << 20 {
+            asm!("rdmsr
+                or eax, 0x800
+                wrmsr"
+                :
+                : "{ecx}"(0xC0000080u32)
+                : "eax", "edx", "memory"
+                : "intel", "volatile");
+            NO_EXEC = PF_NO_EXEC;
+        }
+
         for l4_i in 0..PAGE_TABLE_SIZE {
             if l4_i == 0 {
                 ptr::write((PAGE_LEVEL_4 + l4_i * PAGE_ENTRY_SIZE) as *mut usize,
@@ -68,7 +94,17 @@ impl Page {
                            (PAGE_DIRECTORIES + dp_i * PAGE_TABLE_SIZE * PAGE_ENTRY_SIZE) |
                            PF_USER | PF_WRITE | PF_PRESENT); //Allow userspace, read/write, present
             } else {
-                ptr::write((PAGE_DIR_PTRS + dp_i * PAGE_ENTRY_SIZE) as *mut usize, 0);
+                // Memory above 4 GiB is only used by the kernel, and by contexts through their own
+                // mappings, so it is mapped once with large pages
+                let directory = PAGE_HIGH_DIRECTORIES + (dp_i - 4) * PAGE_TABLE_SIZE * PAGE_ENTRY_SIZE;
+                ptr::write((PAGE_DIR_PTRS + dp_i * PAGE_ENTRY_SIZE) as *mut usize,
+                           directory | PF_WRITE | PF_PRESENT); //Read/write, present
+
+                for entry_i in 0..PAGE_TABLE_SIZE {
+                    let addr = (dp_i * PAGE_TABLE_SIZE + entry_i) * PAGE_HIGH_SIZE;
+                    ptr::write((directory + entry_i * PAGE_ENTRY_SIZE) as *mut usize,
+                               addr | PF_SIZE | PF_WRITE | PF_PRESENT); //Large page, read/write, present
+                }
             }
         }
 
@@ -108,7 +144,7 @@ impl Page {
     }
 
     /// Flush the memory page
-    unsafe fn flush(&self) {
+    pub unsafe fn flush(&self) {
         asm!("invlpg [$0]"
             :
             : "{rax}"(self.virtual_address)
@@ -116,9 +152,19 @@ impl Page {
             : "intel", "volatile");
     }
 
+    /// Get the current entry data
+    pub unsafe fn entry_data(&self) -> usize {
+        ptr::read(self.entry_address() as *mut usize)
+    }
+
+    /// Set the current entry data
+    pub unsafe fn set_entry_data(&mut self, data: usize) {
+        ptr::write(self.entry_address() as *mut usize, data)
+    }
+
     /// Get the current physical address
     pub fn phys_addr(&self) -> usize {
-        unsafe { (ptr::read(self.entry_address() as *mut usize) & PF_NONE) as usize }
+        unsafe { self.entry_data() & PF_NONE }
     }
 
     /// Get the current virtual address
@@ -143,14 +189,22 @@ impl Page {
     /// Map the memory page to a given physical memory address and allow userspace read access
     pub unsafe fn map_user_read(&mut self, physical_address: usize) {
         ptr::write(self.entry_address() as *mut usize,
-                   (physical_address & PF_NONE) | PF_USER | PF_PRESENT); //Allow userspace, present
+                   (physical_address & PF_NONE) | NO_EXEC | PF_USER | PF_PRESENT); //No execute, allow userspace, present
         self.flush();
     }
 
     /// Map the memory page to a given physical memory address and allow userspace read/write access
     pub unsafe fn map_user_write(&mut self, physical_address: usize) {
         ptr::write(self.entry_address() as *mut usize,
-                   (physical_address & PF_NONE) | PF_USER | PF_WRITE | PF_PRESENT); //Allow userspace, read/write, present
+                   (physical_address & PF_NONE) | NO_EXEC | PF_USER | PF_WRITE | PF_PRESENT); //No execute, allow userspace, read/write, present
+        self.flush();
+    }
+
+    /// Map the memory page to a given physical memory address and allow userspace read/execute
+    /// access
+    pub unsafe fn map_user_exec(&mut self, physical_address: usize) {
+        ptr::write(self.entry_address() as *mut usize,
+                   (physical_address & PF_NONE) | PF_USER | PF_PRESENT); //Allow userspace, present
         self.flush();
     }
 
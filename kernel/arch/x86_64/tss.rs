@@ -18,3 +18,10 @@ pub struct Tss {
     pub reserved6: u16,
     pub iomap_base: u16,
 }
+
+impl Tss {
+    /// Set the stack double faults switch to, through the first interrupt stack table entry
+    pub fn set_fault_stack(&mut self, sp: usize) {
+        self.ist1 = sp;
+    }
+}
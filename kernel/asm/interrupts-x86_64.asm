@@ -92,7 +92,12 @@ idt:
 	istruc IDTEntry
 		at IDTEntry.offsetl, dw interrupts+(interrupts.second-interrupts.first)*i
 		at IDTEntry.selector, dw gdt.kernel_code
+%if i = 8
+		;Double faults use the stack in TSS.ist1, so kernel stack overflows can be reported
+		at IDTEntry.ist, db 1
+%else
 		at IDTEntry.ist, db 0
+%endif
 		at IDTEntry.attribute, db attrib.present | attrib.interrupt64
 		at IDTEntry.offsetm, dw 0
 		at IDTEntry.offseth, dd 0
@@ -81,6 +81,14 @@ impl Resource for Ac97Resource {
                 (*self.bdl.offset(i)).samples.write(0);
             }
 
+            // The device plays from 32-bit addresses, so the buffer may need to be copied
+            let dma = {
+                let contexts = & *::env().contexts.get();
+                let current = try!(contexts.current());
+                let physical_address = try!(current.translate(buf.as_ptr() as usize, buf.len()));
+                try!(memory::DmaBuffer::new(buf.as_ptr() as usize, physical_address, buf.len(), false))
+            };
+
             let mut wait = false;
             let mut position = 0;
 
@@ -123,16 +131,8 @@ impl Resource for Ac97Resource {
                 let bytes = cmp::min(65534 * 2, (buf.len() - position + 1));
                 let samples = bytes / 2;
 
-                let mut phys_buf = buf.as_ptr() as usize;
-                {
-                    let contexts = &mut *::env().contexts.get();
-                    if let Ok(current) = contexts.current() {
-                        if let Ok(phys) = current.translate(buf.as_ptr().offset(position as isize) as usize, bytes) {
-                            debugln!("logical {:#X} -> physical {:#X}", &(buf.as_ptr() as usize), &phys);
-                            phys_buf = phys;
-                        }
-                    }
-                }
+                let phys_buf = dma.physical_address() + position;
+                debugln!("logical {:#X} -> physical {:#X}", buf.as_ptr() as usize + position, phys_buf);
 
                 (*self.bdl.offset(lvi as isize)).ptr.write(phys_buf as u32);
                 (*self.bdl.offset(lvi as isize)).samples.write((samples & 0xFFFF) as u32);
@@ -1,19 +1,197 @@
-static mut seed: u64 = 19940046431; //259261034506304368955239; //1706322144714608529217229883707268827757977089;
+//! Entropy pool and ChaCha20 based random number generator
+//!
+//! Entropy is gathered from interrupt timing, RTC jitter and, when the CPU provides them, the
+//! RDSEED and RDRAND instructions. It is mixed into a pool, which is folded into the generator key
+//! once enough events have been collected. Every request rekeys the generator with fresh output,
+//! so earlier output cannot be recovered from the state.
 
-/// Generate pseudo random number
-pub fn rand() -> usize {
+use arch::tsc;
+
+use core::{cmp, mem, ptr, slice};
+
+use sync::Intex;
+
+/// The number of entropy events collected before the pool is folded into the key
+const RESEED_EVENTS: usize = 64;
+
+/// Entropy pool, mixed by rotation and XOR
+static mut POOL: [u32; 16] = [0; 16];
+/// The next word of the pool to mix into
+static mut POOL_I: usize = 0;
+/// Events mixed into the pool since the last reseed
+static mut POOL_EVENTS: usize = 0;
+
+/// Generator key
+static mut KEY: [u32; 8] = [0; 8];
+/// Generator block counter
+static mut COUNTER: u64 = 0;
+
+fn quarter_round(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    x[a] = x[a].wrapping_add(x[b]); x[d] = (x[d] ^ x[a]).rotate_left(16);
+    x[c] = x[c].wrapping_add(x[d]); x[b] = (x[b] ^ x[c]).rotate_left(12);
+    x[a] = x[a].wrapping_add(x[b]); x[d] = (x[d] ^ x[a]).rotate_left(8);
+    x[c] = x[c].wrapping_add(x[d]); x[b] = (x[b] ^ x[c]).rotate_left(7);
+}
+
+/// Compute one ChaCha20 block
+fn chacha20_block(key: &[u32; 8], counter: u64, nonce: u64) -> [u32; 16] {
+    let input = [0x61707865, 0x3320646e, 0x79622d32, 0x6b206574,
+                 key[0], key[1], key[2], key[3],
+                 key[4], key[5], key[6], key[7],
+                 counter as u32, (counter >> 32) as u32, nonce as u32, (nonce >> 32) as u32];
+
+    let mut x = input;
+    for _ in 0..10 {
+        quarter_round(&mut x, 0, 4, 8, 12);
+        quarter_round(&mut x, 1, 5, 9, 13);
+        quarter_round(&mut x, 2, 6, 10, 14);
+        quarter_round(&mut x, 3, 7, 11, 15);
+        quarter_round(&mut x, 0, 5, 10, 15);
+        quarter_round(&mut x, 1, 6, 11, 12);
+        quarter_round(&mut x, 2, 7, 8, 13);
+        quarter_round(&mut x, 3, 4, 9, 14);
+    }
+
+    for i in 0..16 {
+        x[i] = x[i].wrapping_add(input[i]);
+    }
+
+    x
+}
+
+/// Check CPUID for the RDRAND instruction
+fn has_rdrand() -> bool {
+    let ecx: u32;
+    unsafe { asm!("cpuid" : "={ecx}"(ecx) : "{eax}"(1u32) : "ebx", "edx" : "intel", "volatile"); }
+    ecx & 1 << 30 == 1 << 30
+}
+
+/// Check CPUID for the RDSEED instruction
+fn has_rdseed() -> bool {
+    let max: u32;
+    unsafe { asm!("cpuid" : "={eax}"(max) : "{eax}"(0u32) : "ebx", "ecx", "edx" : "intel", "volatile"); }
+    if max < 7 {
+        return false;
+    }
+
+    let ebx: u32;
+    unsafe { asm!("cpuid" : "={ebx}"(ebx) : "{eax}"(7u32), "{ecx}"(0u32) : "edx" : "intel", "volatile"); }
+    ebx & 1 << 18 == 1 << 18
+}
+
+/// Read the hardware random number generator, returning `None` if it is not ready
+fn rdrand() -> Option<usize> {
+    let value: usize;
+    let ok: u8;
+    unsafe { asm!("rdrand $0 ; setc $1" : "=r"(value), "=r"(ok) : : "cc" : "intel", "volatile"); }
+    if ok != 0 {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+/// Read the hardware entropy source, returning `None` if it is not ready
+fn rdseed() -> Option<usize> {
+    let value: usize;
+    let ok: u8;
+    unsafe { asm!("rdseed $0 ; setc $1" : "=r"(value), "=r"(ok) : : "cc" : "intel", "volatile"); }
+    if ok != 0 {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+/// Mix a sample into the entropy pool
+pub fn add_entropy(value: u64) {
+    // Interrupt handlers add entropy too
+    let _intex = Intex::static_lock();
     unsafe {
-        seed ^= seed << 12;
-        seed ^= seed << 25;
-        seed ^= seed << 27;
-        seed = seed * 82724793451 + 12345;
-        seed as usize % ::core::usize::MAX
+        let i = POOL_I;
+        POOL[i] = POOL[i].rotate_left(7) ^ (value as u32);
+        POOL[(i + 1) % 16] = POOL[(i + 1) % 16].rotate_left(7) ^ ((value >> 32) as u32);
+        POOL_I = (i + 2) % 16;
+        POOL_EVENTS += 1;
+
+        if POOL_EVENTS >= RESEED_EVENTS {
+            reseed();
+        }
+    }
+}
+
+/// Mix the timing of an interrupt into the entropy pool
+pub fn add_interrupt_entropy(irq: u8) {
+    add_entropy(tsc::rdtsc() ^ (irq as u64) << 56);
+}
+
+/// Fold the entropy pool into the generator key
+unsafe fn reseed() {
+    let mut key = KEY;
+    for i in 0..8 {
+        key[i] ^= POOL[i];
+    }
+    let nonce = (POOL[8] as u64) << 32 | POOL[9] as u64;
+    let block = chacha20_block(&key, COUNTER, nonce ^ 0xFFFF_FFFF_FFFF_FFFF);
+    for i in 0..8 {
+        KEY[i] = block[i] ^ POOL[i + 8];
+    }
+    COUNTER = 0;
+    POOL_EVENTS = 0;
+}
+
+/// Seed the pool from the hardware sources available at boot
+pub fn init() {
+    if tsc::has_tsc() {
+        add_entropy(tsc::rdtsc());
+    }
+
+    if has_rdseed() {
+        for _ in 0..RESEED_EVENTS {
+            if let Some(value) = rdseed() {
+                add_entropy(value as u64);
+            }
+        }
+    }
+
+    if has_rdrand() {
+        for _ in 0..RESEED_EVENTS {
+            if let Some(value) = rdrand() {
+                add_entropy(value as u64);
+            }
+        }
     }
+
+    unsafe { reseed(); }
 }
 
-/// Set the seed
-pub fn srand(s: usize) {
+/// Fill a buffer with random bytes
+pub fn fill(buf: &mut [u8]) {
+    // An interrupt may reseed the generator
+    let _intex = Intex::static_lock();
     unsafe {
-        seed = s as u64;
+        let mut i = 0;
+        while i < buf.len() {
+            let block = chacha20_block(&KEY, COUNTER, 0);
+            COUNTER += 1;
+
+            let count = cmp::min(buf.len() - i, 64);
+            ptr::copy(block.as_ptr() as *const u8, buf.as_mut_ptr().offset(i as isize), count);
+            i += count;
+        }
+
+        // Rekey, so this output cannot be reconstructed from the state
+        let block = chacha20_block(&KEY, COUNTER, 0);
+        for j in 0..8 {
+            KEY[j] = block[j];
+        }
+        COUNTER = 0;
     }
 }
+
+/// Generate random number
+pub fn rand() -> usize {
+    let mut value: usize = 0;
+    fill(unsafe { slice::from_raw_parts_mut(&mut value as *mut usize as *mut u8, mem::size_of::<usize>()) });
+    value
+}
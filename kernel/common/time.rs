@@ -35,12 +35,19 @@ impl Duration {
 
     /// Get the current duration
     pub fn monotonic() -> Self {
-        unsafe { *::env().clock_monotonic.get() }
+        unsafe { *::env().clock_monotonic.get() + Duration::new(0, (*::env().tsc.get()).offset()) }
     }
 
     /// Get the realtime
     pub fn realtime() -> Self {
-        unsafe { *::env().clock_realtime.get() }
+        unsafe { *::env().clock_realtime.get() + Duration::new(0, (*::env().tsc.get()).offset()) }
+    }
+
+    /// Get the duration of a number of PIT ticks
+    pub fn from_ticks(ticks: usize) -> Self {
+        let tick = ::PIT_DURATION.secs * NANOS_PER_SEC as i64 + ::PIT_DURATION.nanos as i64;
+        let total = tick * ticks as i64;
+        Duration::new(total / NANOS_PER_SEC as i64, (total % NANOS_PER_SEC as i64) as i32)
     }
 }
 
@@ -1,4 +1,4 @@
-use arch::memory::{self, Memory};
+use arch::memory::{self, DmaBuffer, Memory};
 
 use core::mem::size_of;
 use core::u32;
@@ -318,7 +318,10 @@ impl HbaPort {
         if sectors > 0 {
             let contexts = unsafe { & *::env().contexts.get() };
             let current = try!(contexts.current());
-            let physical_address = try!(current.translate(buf, sectors * 512));
+            let dma = try!(unsafe {
+                DmaBuffer::new(buf, try!(current.translate(buf, sectors * 512)), sectors * 512, ! write)
+            });
+            let physical_address = dma.physical_address();
 
             let mut sector: usize = 0;
             while sectors - sector >= 255 {
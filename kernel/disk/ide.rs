@@ -5,7 +5,7 @@ use collections::vec::Vec;
 
 use core::ptr;
 
-use arch::memory::Memory;
+use arch::memory::{DmaBuffer, Memory};
 
 use disk::Disk;
 
@@ -542,7 +542,10 @@ impl IdeDisk {
         if sectors > 0 {
             let contexts = unsafe { & *::env().contexts.get() };
             let current = try!(contexts.current());
-            let physical_address = try!(current.translate(buf, sectors * 512));
+            let dma = try!(unsafe {
+                DmaBuffer::new(buf, try!(current.translate(buf, sectors * 512)), sectors * 512, ! write)
+            });
+            let physical_address = dma.physical_address();
 
             // debugln!("IDE DMA TRANSLATED {:X}", physical_address);
 
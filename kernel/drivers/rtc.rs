@@ -6,10 +6,40 @@ fn cvt_bcd(value: usize) -> usize {
     (value & 0xF) + ((value / 16) * 10)
 }
 
+fn cvt_dec(value: usize) -> usize {
+    ((value / 10) << 4) | (value % 10)
+}
+
+/// Days since the epoch of a civil date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = (if year >= 0 { year } else { year - 399 }) / 400;
+    let yoe = year - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Civil date of a number of days since the epoch
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = (if days >= 0 { days } else { days - 146096 }) / 146097;
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
 /// RTC
 pub struct Rtc {
     addr: Pio<u8>,
     data: Pio<u8>,
+    /// The CMOS register holding the century, as given by the FADT. Zero if there is none
+    pub century: u8,
 }
 
 impl Rtc {
@@ -18,6 +48,7 @@ impl Rtc {
         return Rtc {
             addr: Pio::<u8>::new(0x70),
             data: Pio::<u8>::new(0x71),
+            century: 0,
         };
     }
 
@@ -27,6 +58,12 @@ impl Rtc {
         return self.data.read();
     }
 
+    /// Write
+    unsafe fn write(&mut self, reg: u8, value: u8) {
+        self.addr.write(reg);
+        self.data.write(value);
+    }
+
     /// Wait
     unsafe fn wait(&mut self) {
         while self.read(0xA) & 0x80 != 0x80 {}
@@ -41,6 +78,7 @@ impl Rtc {
         let mut day;
         let mut month;
         let mut year;
+        let mut century = 0;
         let register_b;
         unsafe {
             self.wait();
@@ -50,6 +88,10 @@ impl Rtc {
             day = self.read(7) as usize;
             month = self.read(8) as usize;
             year = self.read(9) as usize;
+            if self.century != 0 {
+                let reg = self.century;
+                century = self.read(reg) as usize;
+            }
             register_b = self.read(0xB);
         }
 
@@ -60,46 +102,88 @@ impl Rtc {
             day = cvt_bcd(day);
             month = cvt_bcd(month);
             year = cvt_bcd(year);
+            century = cvt_bcd(century);
         }
 
-        if register_b & 2 != 2 || hour & 0x80 == 0x80 {
-            hour = ((hour & 0x7F) + 12) % 24;
-        }
-
-        // TODO: Century Register
-        year += 2000;
-
-        // Unix time from clock
-        let mut secs: i64 = (year as i64 - 1970) * 31536000;
-
-        let mut leap_days = (year as i64 - 1972) / 4 + 1;
-        if year % 4 == 0 {
-            if month <= 2 {
-                leap_days -= 1;
+        if register_b & 2 != 2 {
+            let pm = hour & 0x80 == 0x80;
+            hour = (hour & 0x7F) % 12;
+            if pm {
+                hour += 12;
             }
         }
-        secs += leap_days * 86400;
-
-        match month {
-            2 => secs += 2678400,
-            3 => secs += 5097600,
-            4 => secs += 7776000,
-            5 => secs += 10368000,
-            6 => secs += 13046400,
-            7 => secs += 15638400,
-            8 => secs += 18316800,
-            9 => secs += 20995200,
-            10 => secs += 23587200,
-            11 => secs += 26265600,
-            12 => secs += 28857600,
-            _ => (),
+
+        if century != 0 {
+            year += century * 100;
+        } else {
+            year += 2000;
         }
 
-        secs += (day as i64 - 1) * 86400;
+        // Unix time from clock
+        let mut secs = days_from_civil(year as i64, month as i64, day as i64) * 86400;
         secs += hour as i64 * 3600;
         secs += minute as i64 * 60;
         secs += second as i64;
 
         Duration::new(secs, 0)
     }
+
+    /// Set time
+    pub fn set_time(&mut self, time: Duration) {
+        let secs = if time.secs > 0 { time.secs } else { 0 };
+
+        let (year, month, day) = civil_from_days(secs / 86400);
+        let mut second = (secs % 60) as usize;
+        let mut minute = (secs / 60 % 60) as usize;
+        let mut hour = (secs / 3600 % 24) as usize;
+        let mut day = day as usize;
+        let mut month = month as usize;
+        let mut century = (year / 100) as usize;
+        let mut year = (year % 100) as usize;
+
+        unsafe {
+            let register_b = self.read(0xB);
+
+            if register_b & 2 != 2 {
+                let pm = hour >= 12;
+                hour = hour % 12;
+                if hour == 0 {
+                    hour = 12;
+                }
+                if register_b & 4 != 4 {
+                    hour = cvt_dec(hour);
+                }
+                if pm {
+                    hour |= 0x80;
+                }
+            } else if register_b & 4 != 4 {
+                hour = cvt_dec(hour);
+            }
+
+            if register_b & 4 != 4 {
+                second = cvt_dec(second);
+                minute = cvt_dec(minute);
+                day = cvt_dec(day);
+                month = cvt_dec(month);
+                year = cvt_dec(year);
+                century = cvt_dec(century);
+            }
+
+            // Inhibit updates while the time is written
+            self.write(0xB, register_b | 0x80);
+
+            self.write(0, second as u8);
+            self.write(2, minute as u8);
+            self.write(4, hour as u8);
+            self.write(7, day as u8);
+            self.write(8, month as u8);
+            self.write(9, year as u8);
+            if self.century != 0 {
+                let reg = self.century;
+                self.write(reg, century as u8);
+            }
+
+            self.write(0xB, register_b & 0x7F);
+        }
+    }
 }
@@ -1,12 +1,23 @@
+use alloc::arc::Arc;
 use alloc::boxed::Box;
 
-use collections::string::String;
+use collections::string::{String, ToString};
+use collections::vec::Vec;
+use collections::vec_deque::VecDeque;
+
+use core::cell::{Cell, UnsafeCell};
+use core::{cmp, fmt};
 
 use common::event;
 
 use drivers::io::{Io, Pio};
 
-use fs::KScheme;
+use fs::{KScheme, Resource, Url, VecResource};
+
+use sync::WaitCondition;
+
+use system::error::{Error, Result, EAGAIN, EINVAL, ENOENT};
+use system::syscall::{MODE_FILE, O_NONBLOCK, Stat};
 
 #[derive(Copy, Clone, Debug, Default)]
 #[repr(packed)]
@@ -20,129 +31,737 @@ pub unsafe fn bda_init() {
     SERIALINFO = Some(*(0x400 as *const SerialInfo));
 }
 
-/// Serial
-pub struct Serial {
-    pub data: Pio<u8>,
-    pub status: Pio<u8>,
+/// The address of COM1, used when the BDA lists no ports
+const COM1: u16 = 0x3F8;
+
+/// The clock of the UART divided by 16, which the divisor divides into the baud rate
+const BAUD_BASE: u32 = 115200;
+
+/// The number of bytes each of the transmit and receive buffers holds
+pub const SERIAL_BUFFER: usize = 4096;
+
+/// The size of the transmit FIFO of a 16550
+const TX_FIFO: usize = 16;
+
+/// Software flow control: stop sending
+const XOFF: u8 = 0x13;
+/// Software flow control: resume sending
+const XON: u8 = 0x11;
+
+// Interrupt enable register
+const IER_RX: u8 = 0x01;
+const IER_TX: u8 = 0x02;
+const IER_MODEM: u8 = 0x08;
+
+// Line control register
+const LCR_STOP_2: u8 = 0x04;
+const LCR_DLAB: u8 = 0x80;
+
+// Modem control register: DTR, RTS and OUT2, which enables the interrupt line
+const MCR_RTS: u8 = 0x02;
+const MCR_DEFAULT: u8 = 0x0B;
+
+// Line status register
+const LSR_DATA_READY: u8 = 0x01;
+const LSR_TX_EMPTY: u8 = 0x20;
+
+// Modem status register
+const MSR_CTS: u8 = 0x10;
+
+/// The parity bit sent after each character
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+    Mark,
+    Space,
+}
+
+impl Parity {
+    /// The parity bits of the line control register
+    fn lcr(&self) -> u8 {
+        match *self {
+            Parity::None => 0x00,
+            Parity::Odd => 0x08,
+            Parity::Even => 0x18,
+            Parity::Mark => 0x28,
+            Parity::Space => 0x38,
+        }
+    }
+
+    /// The letter used in settings such as `8N1`
+    fn letter(&self) -> char {
+        match *self {
+            Parity::None => 'N',
+            Parity::Odd => 'O',
+            Parity::Even => 'E',
+            Parity::Mark => 'M',
+            Parity::Space => 'S',
+        }
+    }
+
+    fn from_letter(letter: char) -> Option<Parity> {
+        match letter {
+            'N' | 'n' => Some(Parity::None),
+            'O' | 'o' => Some(Parity::Odd),
+            'E' | 'e' => Some(Parity::Even),
+            'M' | 'm' => Some(Parity::Mark),
+            'S' | 's' => Some(Parity::Space),
+            _ => None
+        }
+    }
+}
+
+/// How the other end tells a port to stop sending
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FlowControl {
+    None,
+    /// Send only while CTS is asserted, and deassert RTS while the receive buffer is nearly full
+    RtsCts,
+    /// Stop sending on XOFF, and resume on XON
+    XonXoff,
+}
+
+impl FlowControl {
+    fn name(&self) -> &'static str {
+        match *self {
+            FlowControl::None => "none",
+            FlowControl::RtsCts => "rtscts",
+            FlowControl::XonXoff => "xonxoff",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<FlowControl> {
+        match name {
+            "none" => Some(FlowControl::None),
+            "rtscts" => Some(FlowControl::RtsCts),
+            "xonxoff" => Some(FlowControl::XonXoff),
+            _ => None
+        }
+    }
+}
+
+/// The line settings of a port
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SerialSettings {
+    pub baud: u32,
+    /// 5 to 8
+    pub data_bits: u8,
+    pub parity: Parity,
+    /// 1 or 2
+    pub stop_bits: u8,
+    pub flow: FlowControl,
+}
+
+impl SerialSettings {
+    /// Parse settings separated by whitespace, each changing one part of `self`:
+    /// - a baud rate, such as `9600`
+    /// - data bits, parity and stop bits, such as `8N1` or `7E2`
+    /// - flow control: `none`, `rtscts` or `xonxoff`
+    pub fn parse(mut self, string: &str) -> Option<SerialSettings> {
+        for word in string.split_whitespace() {
+            let chars: Vec<char> = word.chars().collect();
+            if let Ok(baud) = word.parse::<u32>() {
+                if baud == 0 || BAUD_BASE % baud != 0 || BAUD_BASE / baud > 0xFFFF {
+                    return None;
+                }
+                self.baud = baud;
+            } else if let Some(flow) = FlowControl::from_name(word) {
+                self.flow = flow;
+            } else if chars.len() == 3 {
+                self.data_bits = match chars[0].to_digit(10) {
+                    Some(bits @ 5 ... 8) => bits as u8,
+                    _ => return None
+                };
+                self.parity = match Parity::from_letter(chars[1]) {
+                    Some(parity) => parity,
+                    None => return None
+                };
+                self.stop_bits = match chars[2].to_digit(10) {
+                    Some(bits @ 1 ... 2) => bits as u8,
+                    _ => return None
+                };
+            } else {
+                return None;
+            }
+        }
+        Some(self)
+    }
+
+    /// The line control register value
+    fn lcr(&self) -> u8 {
+        let mut lcr = (self.data_bits - 5) | self.parity.lcr();
+        if self.stop_bits == 2 {
+            lcr |= LCR_STOP_2;
+        }
+        lcr
+    }
+}
+
+impl Default for SerialSettings {
+    fn default() -> SerialSettings {
+        SerialSettings {
+            baud: 38400,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: 1,
+            flow: FlowControl::None,
+        }
+    }
+}
+
+impl fmt::Display for SerialSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}{}{} {}", self.baud, self.data_bits, self.parity.letter(), self.stop_bits, self.flow.name())
+    }
+}
+
+/// A 16550 UART
+///
+/// Bytes are written to a buffer, which the transmitter empty interrupt sends to the FIFO. Bytes
+/// received by the interrupt are buffered until read. The console port gives its input to the
+/// console instead while it is not open.
+pub struct SerialPort {
+    /// The I/O port of the data register
+    pub base: u16,
     pub irq: u8,
-    pub escape: bool,
-    pub cursor_control: bool,
+    settings: Cell<SerialSettings>,
+    /// Give received bytes to the console while the port is not open
+    console: bool,
+    /// The number of open resources for the port
+    opened: Cell<usize>,
+    /// Sending was stopped by XOFF
+    paused: Cell<bool>,
+    tx: UnsafeCell<VecDeque<u8>>,
+    rx: UnsafeCell<VecDeque<u8>>,
+    /// Notified when bytes are sent
+    tx_condition: WaitCondition,
+    /// Notified when bytes are received
+    rx_condition: WaitCondition,
+    escape: Cell<bool>,
+    cursor_control: Cell<bool>,
 }
 
-impl Serial {
+impl SerialPort {
     /// Create new
-    pub fn new(port: u16, irq: u8) -> Box<Self> {
-        Pio::<u8>::new(port + 1).write(0x00);
-        Pio::<u8>::new(port + 3).write(0x80);
-        Pio::<u8>::new(port + 0).write(0x03);
-        Pio::<u8>::new(port + 1).write(0x00);
-        Pio::<u8>::new(port + 3).write(0x03);
-        Pio::<u8>::new(port + 2).write(0xC7);
-        Pio::<u8>::new(port + 4).write(0x0B);
-        Pio::<u8>::new(port + 1).write(0x01);
-
-        box Serial {
-            data: Pio::<u8>::new(port),
-            status: Pio::<u8>::new(port + 5),
+    pub fn new(base: u16, irq: u8, console: bool) -> SerialPort {
+        let port = SerialPort {
+            base: base,
             irq: irq,
-            escape: false,
-            cursor_control: false,
+            settings: Cell::new(SerialSettings::default()),
+            console: console,
+            opened: Cell::new(0),
+            paused: Cell::new(false),
+            tx: UnsafeCell::new(VecDeque::new()),
+            rx: UnsafeCell::new(VecDeque::new()),
+            tx_condition: WaitCondition::new(),
+            rx_condition: WaitCondition::new(),
+            escape: Cell::new(false),
+            cursor_control: Cell::new(false),
+        };
+        port.configure(SerialSettings::default());
+        port
+    }
+
+    fn reg(&self, offset: u16) -> Pio<u8> {
+        Pio::<u8>::new(self.base + offset)
+    }
+
+    unsafe fn tx<'a>(&'a self) -> &'a mut VecDeque<u8> {
+        &mut *self.tx.get()
+    }
+
+    unsafe fn rx<'a>(&'a self) -> &'a mut VecDeque<u8> {
+        &mut *self.rx.get()
+    }
+
+    pub fn settings(&self) -> SerialSettings {
+        self.settings.get()
+    }
+
+    /// Program the divisor, line and flow control, and enable the interrupts
+    pub fn configure(&self, settings: SerialSettings) {
+        let divisor = (BAUD_BASE / settings.baud) as u16;
+
+        self.reg(1).write(0x00);
+        self.reg(3).write(LCR_DLAB);
+        self.reg(0).write(divisor as u8);
+        self.reg(1).write((divisor >> 8) as u8);
+        self.reg(3).write(settings.lcr());
+        self.reg(2).write(0xC7);
+        self.reg(4).write(MCR_DEFAULT);
+
+        self.settings.set(settings);
+        self.paused.set(false);
+
+        self.update_interrupts();
+    }
+
+    /// Enable the transmit interrupt only while there is something to send
+    fn update_interrupts(&self) {
+        let mut ier = IER_RX;
+        if self.settings.get().flow == FlowControl::RtsCts {
+            ier |= IER_MODEM;
+        }
+        if ! unsafe { self.tx() }.is_empty() {
+            ier |= IER_TX;
         }
+        self.reg(1).write(ier);
     }
 
-    pub fn writeb(&mut self, byte: u8){
-        while !self.status.readf(0x20) {}
-        self.data.write(byte);
+    /// Whether the other end lets the port send
+    fn clear_to_send(&self) -> bool {
+        match self.settings.get().flow {
+            FlowControl::None => true,
+            FlowControl::RtsCts => self.reg(6).readf(MSR_CTS),
+            FlowControl::XonXoff => ! self.paused.get(),
+        }
+    }
+
+    /// Assert RTS while the receive buffer has room, for `FlowControl::RtsCts`
+    fn update_rts(&self) {
+        if self.settings.get().flow == FlowControl::RtsCts {
+            let room = unsafe { self.rx() }.len() < SERIAL_BUFFER * 3 / 4;
+            self.reg(4).writef(MCR_RTS, room);
+        }
     }
 
-    pub fn write(&mut self, bytes: &[u8]) {
+    /// Fill the transmit FIFO from the buffer, if it is empty
+    fn transmit(&self) {
+        let tx = unsafe { self.tx() };
+        if ! tx.is_empty() && self.clear_to_send() && self.reg(5).readf(LSR_TX_EMPTY) {
+            for _ in 0..TX_FIFO {
+                match tx.pop_front() {
+                    Some(byte) => self.reg(0).write(byte),
+                    None => break
+                }
+            }
+            self.tx_condition.notify("SerialPort::transmit");
+        }
+        self.update_interrupts();
+    }
+
+    /// Buffer bytes to send, returning how many fit
+    pub fn write(&self, bytes: &[u8]) -> usize {
+        let count = {
+            let tx = unsafe { self.tx() };
+            let count = cmp::min(bytes.len(), SERIAL_BUFFER - tx.len());
+            tx.extend(bytes[.. count].iter().cloned());
+            count
+        };
+        self.transmit();
+        count
+    }
+
+    /// Take received bytes
+    pub fn read(&self, buf: &mut [u8]) -> usize {
+        let mut i = 0;
+        {
+            let rx = unsafe { self.rx() };
+            while i < buf.len() {
+                match rx.pop_front() {
+                    Some(byte) => {
+                        buf[i] = byte;
+                        i += 1;
+                    },
+                    None => break
+                }
+            }
+        }
+        self.update_rts();
+        i
+    }
+
+    /// Send bytes by polling, before interrupts are enabled
+    pub fn write_polled(&self, bytes: &[u8]) {
         for &byte in bytes.iter() {
-            self.writeb(byte);
+            while ! self.reg(5).readf(LSR_TX_EMPTY) {}
+            self.reg(0).write(byte);
+        }
+    }
+
+    /// Receive a byte by polling, before interrupts are enabled
+    pub fn read_polled(&self) -> u8 {
+        while ! self.reg(5).readf(LSR_DATA_READY) {}
+        self.reg(0).read()
+    }
+
+    /// Handle every pending interrupt of the port
+    pub fn on_irq(&self) {
+        loop {
+            let iir = self.reg(2).read();
+            if iir & 1 == 1 {
+                break;
+            }
+
+            match (iir >> 1) & 7 {
+                // Modem status, CTS may allow sending again
+                0 => {
+                    self.reg(6).read();
+                    self.transmit();
+                },
+                // Transmitter empty
+                1 => self.transmit(),
+                // Received data, or a character timeout
+                2 | 6 => {
+                    while self.reg(5).readf(LSR_DATA_READY) {
+                        let byte = self.reg(0).read();
+                        self.receive(byte);
+                    }
+                    self.update_rts();
+                    self.rx_condition.notify("SerialPort::on_irq");
+                },
+                // Line status, such as parity and framing errors
+                _ => {
+                    self.reg(5).read();
+                }
+            }
         }
     }
 
-    pub fn readb(&mut self) -> u8 {
-        while self.status.read() & 1 == 0 {}
-        self.data.read()
+    fn receive(&self, byte: u8) {
+        if self.settings.get().flow == FlowControl::XonXoff {
+            if byte == XOFF {
+                self.paused.set(true);
+                return;
+            } else if byte == XON {
+                self.paused.set(false);
+                self.transmit();
+                return;
+            }
+        }
+
+        if self.console && self.opened.get() == 0 {
+            self.console_input(byte);
+        } else {
+            let rx = unsafe { self.rx() };
+            if rx.len() < SERIAL_BUFFER {
+                rx.push_back(byte);
+            }
+        }
+    }
+
+    /// Give a received byte to the console
+    fn console_input(&self, byte: u8) {
+        let mut c = byte as char;
+        let mut sc = 0;
+
+        let console = unsafe { &mut *::env().console.get() };
+
+        if self.escape.get() {
+            self.escape.set(false);
+
+            if c == '[' {
+                self.cursor_control.set(true);
+            }
+
+            c = '\0';
+        } else if self.cursor_control.get() {
+            self.cursor_control.set(false);
+
+            if c == 'A' {
+                sc = event::K_UP;
+            } else if c == 'B' {
+                sc = event::K_DOWN;
+            } else if c == 'C' {
+                sc = event::K_RIGHT;
+            } else if c == 'D' {
+                sc = event::K_LEFT;
+            }
+
+            c = '\0';
+        } else if c == '\x03' {
+            console.write(b"^C\n");
+            console.commands.send(String::new(), "Serial Control C");
+
+            c = '\0';
+            sc = 0;
+        } else if c == '\x04' {
+            console.write(b"^D\n");
+
+            {
+                let contexts = unsafe { &mut *::env().contexts.get() };
+                debugln!("Magic CTRL-D {}", ::common::time::Duration::monotonic().secs);
+                for context in contexts.iter() {
+                    debugln!("  PID {}: {}", context.pid, context.name);
+
+                    if context.blocked > 0 {
+                        debugln!("    BLOCKED {}", context.blocked);
+                    }
+
+                    if let Some(current_syscall) = context.current_syscall {
+                        debugln!("    SYS {:X}: {} {} {:X} {:X} {:X}", current_syscall.0, current_syscall.1, ::syscall::name(current_syscall.1), current_syscall.2, current_syscall.3, current_syscall.4);
+                    }
+                }
+            }
+
+            c = '\0';
+            sc = 0;
+        } else if c == '\x1B' {
+            self.escape.set(true);
+            c = '\0';
+        } else if c == '\r' {
+            c = '\n';
+        } else if c == '\x7F' {
+            c = '\0';
+            sc = event::K_BKSP;
+        }
+
+        if c != '\0' || sc != 0 {
+            let key_event = event::KeyEvent {
+                character: c,
+                scancode: sc,
+                pressed: true,
+            };
+
+            console.event(key_event.to_event());
+        }
+    }
+}
+
+/// The serial scheme.
+///
+/// Each port found in the BDA is `serial:N`, from `serial:0` for COM1. Reading and writing it
+/// receives and sends bytes. `serial:N/settings` reads the line settings, such as
+/// `38400 8N1 none`, and writing the same form changes them, see `SerialSettings::parse`.
+/// `serial:` lists the ports.
+///
+/// COM1 is the console while it is not open.
+pub struct Serial {
+    pub ports: Vec<Arc<SerialPort>>,
+}
+
+impl Serial {
+    /// Detect COM1 to COM4 from the BDA. COM1 is assumed if it lists none.
+    pub fn new() -> Box<Self> {
+        let mut ports = Vec::new();
+        if let Some(info) = unsafe { SERIALINFO } {
+            for (i, &base) in info.ports.iter().enumerate() {
+                if base != 0 {
+                    // COM1 and COM3 share IRQ 4, COM2 and COM4 share IRQ 3
+                    let irq = if i % 2 == 0 { 0x4 } else { 0x3 };
+                    ports.push(Arc::new(SerialPort::new(base, irq, base == COM1)));
+                }
+            }
+        }
+        if ports.is_empty() {
+            ports.push(Arc::new(SerialPort::new(COM1, 0x4, true)));
+        }
+
+        box Serial {
+            ports: ports,
+        }
+    }
+
+    /// The port used as the console
+    pub fn console(&self) -> &SerialPort {
+        for port in self.ports.iter() {
+            if port.console {
+                return port;
+            }
+        }
+        &self.ports[0]
     }
 }
 
 impl KScheme for Serial {
     fn on_irq(&mut self, irq: u8) {
-        if irq == self.irq {
-            let mut c = self.readb() as char;
-            let mut sc = 0;
+        for port in self.ports.iter() {
+            if port.irq == irq {
+                port.on_irq();
+            }
+        }
+    }
 
-            let console = unsafe { &mut *::env().console.get() };
+    fn scheme(&self) -> &str {
+        "serial"
+    }
 
-            if self.escape {
-                self.escape = false;
+    fn open(&mut self, url: Url, flags: usize) -> Result<Box<Resource>> {
+        let path = url.reference().trim_matches('/');
+        if path.is_empty() {
+            let mut string = format!("{:<6}{:<8}{:<6}{}\n", "PORT", "ADDR", "IRQ", "SETTINGS");
+            for (i, port) in self.ports.iter().enumerate() {
+                string.push_str(&format!("{:<6}{:<8}{:<6}{}\n",
+                                         i,
+                                         format!("{:X}", port.base),
+                                         port.irq,
+                                         port.settings().to_string()));
+            }
+            return Ok(box VecResource::new("serial:".to_string(), string.into_bytes()));
+        }
 
-                if c == '[' {
-                    self.cursor_control = true;
-                }
+        let mut parts = path.splitn(2, '/');
+        let index = match parts.next().unwrap_or("").parse::<usize>() {
+            Ok(index) => index,
+            Err(_) => return Err(Error::new(ENOENT))
+        };
+        let port = match self.ports.get(index) {
+            Some(port) => port.clone(),
+            None => return Err(Error::new(ENOENT))
+        };
 
-                c = '\0';
-            } else if self.cursor_control {
-                self.cursor_control = false;
-
-                if c == 'A' {
-                    sc = event::K_UP;
-                } else if c == 'B' {
-                    sc = event::K_DOWN;
-                } else if c == 'C' {
-                    sc = event::K_RIGHT;
-                } else if c == 'D' {
-                    sc = event::K_LEFT;
-                }
+        match parts.next().unwrap_or("").trim_matches('/') {
+            "" => Ok(box SerialResource::new(port, index, flags)),
+            "settings" => Ok(box SerialSettingsResource {
+                port: port,
+                index: index,
+                pos: 0,
+            }),
+            _ => Err(Error::new(ENOENT))
+        }
+    }
+}
 
-                c = '\0';
-            } else if c == '\x03' {
-                console.write(b"^C\n");
-                console.commands.send(String::new(), "Serial Control C");
-
-                c = '\0';
-                sc = 0;
-            } else if c == '\x04' {
-                console.write(b"^D\n");
-
-                {
-                    let contexts = unsafe { &mut *::env().contexts.get() };
-                    debugln!("Magic CTRL-D {}", ::common::time::Duration::monotonic().secs);
-                    for context in contexts.iter() {
-                        debugln!("  PID {}: {}", context.pid, context.name);
-
-                        if context.blocked > 0 {
-                            debugln!("    BLOCKED {}", context.blocked);
-                        }
-
-                        if let Some(current_syscall) = context.current_syscall {
-                            debugln!("    SYS {:X}: {} {} {:X} {:X} {:X}", current_syscall.0, current_syscall.1, ::syscall::name(current_syscall.1), current_syscall.2, current_syscall.3, current_syscall.4);
-                        }
-                    }
-                }
+/// The data of a serial port
+pub struct SerialResource {
+    port: Arc<SerialPort>,
+    index: usize,
+    flags: usize,
+}
 
-                c = '\0';
-                sc = 0;
-            } else if c == '\x1B' {
-                self.escape = true;
-                c = '\0';
-            } else if c == '\r' {
-                c = '\n';
-            } else if c == '\x7F' {
-                c = '\0';
-                sc = event::K_BKSP;
-            }
-
-            if c != '\0' || sc != 0 {
-                let key_event = event::KeyEvent {
-                    character: c,
-                    scancode: sc,
-                    pressed: true,
-                };
+impl SerialResource {
+    fn new(port: Arc<SerialPort>, index: usize, flags: usize) -> SerialResource {
+        port.opened.set(port.opened.get() + 1);
+        SerialResource {
+            port: port,
+            index: index,
+            flags: flags,
+        }
+    }
+}
+
+impl Resource for SerialResource {
+    fn dup(&self) -> Result<Box<Resource>> {
+        Ok(box SerialResource::new(self.port.clone(), self.index, self.flags))
+    }
+
+    fn path(&self, buf: &mut [u8]) -> Result<usize> {
+        let path = format!("serial:{}", self.index);
+
+        for (b, p) in buf.iter_mut().zip(path.bytes()) {
+            *b = p;
+        }
+
+        Ok(cmp::min(buf.len(), path.len()))
+    }
+
+    /// Blocks until a byte is received, unless opened with `O_NONBLOCK`
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
 
-                console.event(key_event.to_event());
+        loop {
+            let count = self.port.read(buf);
+            if count > 0 {
+                return Ok(count);
             }
+
+            if self.flags & O_NONBLOCK == O_NONBLOCK {
+                return Err(Error::new(EAGAIN));
+            }
+
+            self.port.rx_condition.wait("SerialResource::read");
         }
     }
+
+    /// Blocks while the transmit buffer is full, unless opened with `O_NONBLOCK`
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let mut i = 0;
+        while i < buf.len() {
+            let count = self.port.write(&buf[i ..]);
+            i += count;
+
+            if count == 0 {
+                if self.flags & O_NONBLOCK == O_NONBLOCK {
+                    return if i > 0 {
+                        Ok(i)
+                    } else {
+                        Err(Error::new(EAGAIN))
+                    };
+                }
+
+                self.port.tx_condition.wait("SerialResource::write");
+            }
+        }
+
+        Ok(i)
+    }
+
+    fn stat(&self, stat: &mut Stat) -> Result<usize> {
+        stat.st_mode = MODE_FILE;
+        Ok(0)
+    }
+
+    /// Waits until every buffered byte was given to the UART
+    fn sync(&mut self) -> Result<()> {
+        while ! unsafe { self.port.tx() }.is_empty() {
+            self.port.tx_condition.wait("SerialResource::sync");
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SerialResource {
+    fn drop(&mut self) {
+        self.port.opened.set(self.port.opened.get() - 1);
+    }
+}
+
+/// The line settings of a serial port
+pub struct SerialSettingsResource {
+    port: Arc<SerialPort>,
+    index: usize,
+    pos: usize,
+}
+
+impl Resource for SerialSettingsResource {
+    fn dup(&self) -> Result<Box<Resource>> {
+        Ok(box SerialSettingsResource {
+            port: self.port.clone(),
+            index: self.index,
+            pos: self.pos,
+        })
+    }
+
+    fn path(&self, buf: &mut [u8]) -> Result<usize> {
+        let path = format!("serial:{}/settings", self.index);
+
+        for (b, p) in buf.iter_mut().zip(path.bytes()) {
+            *b = p;
+        }
+
+        Ok(cmp::min(buf.len(), path.len()))
+    }
+
+    /// Reads the settings, such as `9600 8N1 rtscts`
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let settings = format!("{}\n", self.port.settings().to_string());
+
+        let mut count = 0;
+        for (b, p) in buf.iter_mut().zip(settings.bytes().skip(self.pos)) {
+            *b = p;
+            count += 1;
+        }
+        self.pos += count;
+
+        Ok(count)
+    }
+
+    /// Changes the settings that are given, leaving the others
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        match self.port.settings().parse(&String::from_utf8_lossy(buf)) {
+            Some(settings) => {
+                self.port.configure(settings);
+                Ok(buf.len())
+            },
+            None => Err(Error::new(EINVAL))
+        }
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
 }
@@ -1,78 +1,96 @@
+use collections::string::String;
+use collections::vec_deque::VecDeque;
+
 use core::fmt;
 
+use common::time::Duration;
+
+use logging::LogLevel;
+
+use sync::WaitCondition;
+
+/// The number of message bytes kept before the oldest entries are dropped
+pub const LOG_SIZE: usize = 65536;
+
+/// A single log message
+pub struct LogEntry {
+    /// Sequence number, increasing by one for every entry written
+    pub seq: usize,
+    /// Priority of the message
+    pub level: LogLevel,
+    /// Monotonic time the message was written
+    pub time: Duration,
+    /// Where the message came from, `None` for the kernel
+    pub tag: Option<String>,
+    pub message: String,
+}
+
+impl LogEntry {
+    fn size(&self) -> usize {
+        self.message.len() + self.tag.as_ref().map_or(0, |tag| tag.len())
+    }
+}
+
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "[{}.{:>03}] {:<6}", self.time.secs, self.time.nanos/1000000, self.level.name()));
+        if let Some(ref tag) = self.tag {
+            try!(write!(f, "{}: ", tag));
+        }
+        write!(f, "{}\n", self.message)
+    }
+}
+
 pub struct Log {
-    pub data: [u8; 65536],
-    pub start: usize,
-    pub end: usize
+    pub entries: VecDeque<LogEntry>,
+    /// Total size of the entries, see `LOG_SIZE`
+    pub size: usize,
+    /// Sequence number of the next entry
+    pub next_seq: usize,
+    /// Messages at this level or more severe are also written to the console
+    pub threshold: LogLevel,
+    /// Notified when an entry is written
+    pub condition: WaitCondition,
 }
 
 impl Log {
     pub fn new() -> Log {
         Log {
-            data: [0; 65536],
-            start: 0,
-            end: 0
+            entries: VecDeque::new(),
+            size: 0,
+            next_seq: 0,
+            threshold: LogLevel::Info,
+            condition: WaitCondition::new(),
         }
     }
 
-    fn move_start(&mut self) {
-        self.start += 1;
-        while self.start >= self.data.len() {
-            self.start -= self.data.len();
-        }
-    }
+    /// Add an entry, dropping the oldest entries if the log is full
+    pub fn write(&mut self, level: LogLevel, time: Duration, tag: Option<String>, message: String) -> &LogEntry {
+        let entry = LogEntry {
+            seq: self.next_seq,
+            level: level,
+            time: time,
+            tag: tag,
+            message: message,
+        };
+        self.next_seq += 1;
+        self.size += entry.size();
+        self.entries.push_back(entry);
 
-    fn move_end(&mut self) {
-        self.end += 1;
-        while self.end >= self.data.len() {
-            self.end -= self.data.len();
-        }
-        if self.end == self.start {
-            'adjusting: loop {
-                self.move_start();
-                if self.data[self.start] == b'\n' || self.start == self.end {
-                    self.move_start();
-                    break 'adjusting;
-                }
+        while self.size > LOG_SIZE && self.entries.len() > 1 {
+            if let Some(old) = self.entries.pop_front() {
+                self.size -= old.size();
             }
         }
-    }
 
-    pub fn read_at(&self, pos: usize, buf: &mut [u8]) -> usize {
-        let mut count = 0;
-        let mut i = self.start + pos;
-        while i >= self.data.len() {
-            i -= self.data.len()
-        }
-        for byte in buf.iter_mut() {
-            if i == self.end {
-                break;
-            }
-            *byte = self.data[i];
-            i += 1;
-            while i >= self.data.len() {
-                i -= self.data.len()
-            }
-            count += 1;
-        }
-        count
-    }
+        self.condition.notify("Log::write");
 
-    pub fn write(&mut self, buf: &[u8]) -> usize {
-        let mut count = 0;
-        for byte in buf.iter() {
-            self.data[self.end] = *byte;
-            self.move_end();
-            count += 1;
-        }
-        count
+        &self.entries[self.entries.len() - 1]
     }
-}
-
-impl fmt::Write for Log {
-    fn write_str(&mut self, s: &str) -> Result<(), fmt::Error> {
-        self.write(s.as_bytes());
 
-        Ok(())
+    /// Find the first entry with a sequence number of at least `seq` and a level of at least
+    /// `level`
+    pub fn find(&self, seq: usize, level: LogLevel) -> Option<&LogEntry> {
+        self.entries.iter().find(|entry| entry.seq >= seq && entry.level <= level)
     }
 }
@@ -6,18 +6,21 @@ use collections::vec::Vec;
 use core::cell::UnsafeCell;
 
 use arch::context::ContextManager;
+use arch::tsc::Tsc;
 use common::event::Event;
 use common::time::Duration;
 use disk::Disk;
+use drivers::rtc::Rtc;
 use network::Nic;
 use fs::{KScheme, Resource, Scheme, VecResource, Url};
 use sync::WaitQueue;
 
 use system::error::{Error, Result, ENOENT, EEXIST};
-use system::syscall::{O_CREAT, Stat};
+use system::syscall::{O_CREAT, Stat, TimeSpec};
 
 use self::console::Console;
 use self::log::Log;
+use self::profile::Profile;
 
 /// The Kernel Console
 pub mod console;
@@ -25,6 +28,9 @@ pub mod console;
 /// The Kernel Log
 pub mod log;
 
+/// The Sampling Profiler
+pub mod profile;
+
 /// The kernel environment
 pub struct Environment {
     /// Contexts
@@ -32,9 +38,15 @@ pub struct Environment {
 
     /// Clock realtime (default)
     pub clock_realtime: UnsafeCell<Duration>,
+    /// Adjustment still to be slewed into the realtime clock, in nanoseconds
+    pub clock_adjust: UnsafeCell<i64>,
     /// Monotonic clock
     pub clock_monotonic: UnsafeCell<Duration>,
+    /// Time stamp counter, for time between clock ticks
+    pub tsc: UnsafeCell<Tsc>,
 
+    /// Real time clock
+    pub rtc: UnsafeCell<Rtc>,
     /// Default console
     pub console: UnsafeCell<Console>,
     /// Disks
@@ -45,6 +57,8 @@ pub struct Environment {
     pub events: WaitQueue<Event>,
     /// Kernel logs
     pub log: UnsafeCell<Log>,
+    /// Profiler samples
+    pub profile: UnsafeCell<Profile>,
     /// Schemes
     pub schemes: UnsafeCell<Vec<Box<KScheme>>>,
 
@@ -58,13 +72,17 @@ impl Environment {
             contexts: UnsafeCell::new(ContextManager::new()),
 
             clock_realtime: UnsafeCell::new(Duration::new(0, 0)),
+            clock_adjust: UnsafeCell::new(0),
             clock_monotonic: UnsafeCell::new(Duration::new(0, 0)),
+            tsc: UnsafeCell::new(Tsc::new(::PIT_DURATION.nanos as u64)),
 
+            rtc: UnsafeCell::new(Rtc::new()),
             console: UnsafeCell::new(Console::new()),
             disks: UnsafeCell::new(Vec::new()),
             nics: UnsafeCell::new(Vec::new()),
             events: WaitQueue::new(),
             log: UnsafeCell::new(Log::new()),
+            profile: UnsafeCell::new(Profile::new()),
             schemes: UnsafeCell::new(Vec::new()),
 
             interrupts: UnsafeCell::new([0; 256]),
@@ -175,4 +193,43 @@ impl Environment {
         }
         Err(Error::new(ENOENT))
     }
+
+    /// Change the permission bits of a path
+    pub fn chmod(&self, url: Url, mode: usize) -> Result<()> {
+        let url_scheme = url.scheme();
+        if !url_scheme.is_empty() {
+            for mut scheme in unsafe { &mut *self.schemes.get() }.iter_mut() {
+                if scheme.scheme() == url_scheme {
+                    return scheme.chmod(url, mode);
+                }
+            }
+        }
+        Err(Error::new(ENOENT))
+    }
+
+    /// Change the owner and group of a path
+    pub fn chown(&self, url: Url, uid: usize, gid: usize) -> Result<()> {
+        let url_scheme = url.scheme();
+        if !url_scheme.is_empty() {
+            for mut scheme in unsafe { &mut *self.schemes.get() }.iter_mut() {
+                if scheme.scheme() == url_scheme {
+                    return scheme.chown(url, uid, gid);
+                }
+            }
+        }
+        Err(Error::new(ENOENT))
+    }
+
+    /// Set the access and modification times of a path
+    pub fn utimens(&self, url: Url, times: &[TimeSpec; 2]) -> Result<()> {
+        let url_scheme = url.scheme();
+        if !url_scheme.is_empty() {
+            for mut scheme in unsafe { &mut *self.schemes.get() }.iter_mut() {
+                if scheme.scheme() == url_scheme {
+                    return scheme.utimens(url, times);
+                }
+            }
+        }
+        Err(Error::new(ENOENT))
+    }
 }
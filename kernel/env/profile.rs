@@ -0,0 +1,167 @@
+use collections::vec::Vec;
+
+use core::{fmt, mem, ptr};
+
+use arch::context::{Context, CONTEXT_STACK_SIZE};
+use arch::regs::Regs;
+
+/// The number of samples kept before the oldest are overwritten
+pub const PROFILE_SIZE: usize = 8192;
+
+/// The number of return addresses recorded for each sample
+pub const PROFILE_DEPTH: usize = 8;
+
+/// A sample of the interrupted code, taken on a timer interrupt
+#[derive(Copy, Clone)]
+pub struct Sample {
+    /// Sequence number, increasing by one for every sample taken
+    pub seq: usize,
+    /// The PID of the interrupted context
+    pub pid: usize,
+    /// Whether userspace was interrupted
+    pub user: bool,
+    /// The interrupted instruction pointer
+    pub ip: usize,
+    /// Return addresses from the frame pointer chain, innermost first
+    pub frames: [usize; PROFILE_DEPTH],
+    /// The number of valid entries in `frames`
+    pub depth: usize,
+}
+
+impl Sample {
+    /// Sample the interrupted code, following the frame pointers in `regs.bp` for as long as they
+    /// stay inside the user memory or kernel stack of `context`
+    pub fn new(context: &Context, regs: &Regs) -> Sample {
+        let user = regs.cs & 3 == 3;
+        let frame_size = 2 * mem::size_of::<usize>();
+
+        let mut frames = [0; PROFILE_DEPTH];
+        let mut depth = 0;
+        let mut bp = regs.bp;
+        while depth < PROFILE_DEPTH && bp > 0 && bp % mem::size_of::<usize>() == 0 {
+            let readable = if user {
+                context.translate(bp, frame_size).is_ok()
+            } else {
+                context.kernel_stack > 0 && bp >= context.kernel_stack
+                    && bp + frame_size <= context.kernel_stack + CONTEXT_STACK_SIZE
+            };
+            if ! readable {
+                break;
+            }
+
+            let (next_bp, ret) = unsafe {
+                (ptr::read(bp as *const usize),
+                 ptr::read((bp + mem::size_of::<usize>()) as *const usize))
+            };
+            if ret == 0 {
+                break;
+            }
+            frames[depth] = ret;
+            depth += 1;
+
+            // Frames are further up the stack, anything else is not a frame pointer
+            if next_bp <= bp {
+                break;
+            }
+            bp = next_bp;
+        }
+
+        Sample {
+            seq: 0,
+            pid: context.pid,
+            user: user,
+            ip: regs.ip,
+            frames: frames,
+            depth: depth,
+        }
+    }
+}
+
+impl fmt::Display for Sample {
+    /// One line: the PID, `u` or `k` for user or kernel, the instruction pointer and the return
+    /// addresses, in hex
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{} {} {:X}", self.pid, if self.user { 'u' } else { 'k' }, self.ip));
+        for frame in self.frames[.. self.depth].iter() {
+            try!(write!(f, " {:X}", frame));
+        }
+        write!(f, "\n")
+    }
+}
+
+/// The sampling profiler buffer
+///
+/// There would be one for each CPU, but the kernel only runs on the boot CPU, so there is one.
+/// Samples are recorded from the timer interrupt, so the buffer is only allocated by `start`.
+pub struct Profile {
+    /// Record samples on timer interrupts
+    pub enabled: bool,
+    /// Ring buffer of the last `PROFILE_SIZE` samples
+    samples: Vec<Sample>,
+    /// Sequence number of the first sample in `samples`
+    first_seq: usize,
+    /// Sequence number of the next sample
+    pub next_seq: usize,
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+        Profile {
+            enabled: false,
+            samples: Vec::new(),
+            first_seq: 0,
+            next_seq: 0,
+        }
+    }
+
+    /// Allocate the buffer, if needed, and start recording
+    pub fn start(&mut self) {
+        if self.samples.capacity() < PROFILE_SIZE {
+            self.samples.reserve_exact(PROFILE_SIZE);
+        }
+        self.enabled = true;
+    }
+
+    /// Stop recording, keeping the recorded samples
+    pub fn stop(&mut self) {
+        self.enabled = false;
+    }
+
+    /// Drop every sample
+    pub fn clear(&mut self) {
+        // Keep the timer interrupt from recording into the buffer while it is emptied
+        let enabled = self.enabled;
+        self.enabled = false;
+        self.samples.clear();
+        self.first_seq = self.next_seq;
+        self.enabled = enabled;
+    }
+
+    /// Add a sample, overwriting the oldest if the buffer is full. Never allocates.
+    pub fn record(&mut self, mut sample: Sample) {
+        if ! self.enabled || self.samples.capacity() < PROFILE_SIZE {
+            return;
+        }
+
+        sample.seq = self.next_seq;
+        self.next_seq += 1;
+
+        if self.samples.len() < PROFILE_SIZE {
+            self.samples.push(sample);
+        } else {
+            self.samples[(sample.seq - self.first_seq) % PROFILE_SIZE] = sample;
+        }
+    }
+
+    /// Find the oldest sample with a sequence number of at least `seq`
+    pub fn find(&self, seq: usize) -> Option<&Sample> {
+        let oldest = self.next_seq - self.samples.len();
+        if seq >= self.next_seq {
+            None
+        } else if seq < oldest {
+            self.samples.get((oldest - self.first_seq) % PROFILE_SIZE)
+        } else {
+            self.samples.get((seq - self.first_seq) % PROFILE_SIZE)
+        }
+    }
+}
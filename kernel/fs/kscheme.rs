@@ -2,8 +2,8 @@ use super::{Resource, Url};
 
 use alloc::boxed::Box;
 
-use system::error::{Error, Result, EPERM};
-use system::syscall::Stat;
+use system::error::{Error, Result, EOPNOTSUPP, EPERM};
+use system::syscall::{Stat, TimeSpec};
 
 #[allow(unused_variables)]
 pub trait KScheme {
@@ -34,4 +34,16 @@ pub trait KScheme {
     fn unlink(&mut self, path: Url) -> Result<()> {
         Err(Error::new(EPERM))
     }
+
+    fn chmod(&mut self, path: Url, mode: usize) -> Result<()> {
+        Err(Error::new(EOPNOTSUPP))
+    }
+
+    fn chown(&mut self, path: Url, uid: usize, gid: usize) -> Result<()> {
+        Err(Error::new(EOPNOTSUPP))
+    }
+
+    fn utimens(&mut self, path: Url, times: &[TimeSpec; 2]) -> Result<()> {
+        Err(Error::new(EOPNOTSUPP))
+    }
 }
@@ -1,7 +1,10 @@
+use alloc::arc::Arc;
 use alloc::boxed::Box;
 
-use system::error::{Error, Result, EPERM, ESPIPE};
-use system::syscall::Stat;
+use arch::context::SharedMemory;
+
+use system::error::{Error, Result, EOPNOTSUPP, EPERM, ESPIPE};
+use system::syscall::{Stat, TimeSpec};
 
 /// Resource seek
 #[derive(Copy, Clone, Debug)]
@@ -41,6 +44,58 @@ pub trait Resource {
         Err(Error::new(EPERM))
     }
 
+    /// Read data to buffer from the given offset, without changing the seek position
+    /// Returns `ESPIPE` if the operation is not supported.
+    fn pread(&mut self, buf: &mut [u8], offset: usize) -> Result<usize> {
+        Err(Error::new(ESPIPE))
+    }
+
+    /// Write to resource at the given offset, without changing the seek position
+    /// Returns `ESPIPE` if the operation is not supported.
+    fn pwrite(&mut self, buf: &[u8], offset: usize) -> Result<usize> {
+        Err(Error::new(ESPIPE))
+    }
+
+    /// Read data to each buffer in turn, stopping at the first short read
+    fn readv(&mut self, bufs: &mut [&mut [u8]]) -> Result<usize> {
+        let mut total = 0;
+        for buf in bufs.iter_mut() {
+            let count = match self.read(buf) {
+                Ok(count) => count,
+                Err(err) => if total > 0 {
+                    break;
+                } else {
+                    return Err(err);
+                }
+            };
+            total += count;
+            if count < buf.len() {
+                break;
+            }
+        }
+        Ok(total)
+    }
+
+    /// Write each buffer in turn, stopping at the first short write
+    fn writev(&mut self, bufs: &[&[u8]]) -> Result<usize> {
+        let mut total = 0;
+        for buf in bufs.iter() {
+            let count = match self.write(buf) {
+                Ok(count) => count,
+                Err(err) => if total > 0 {
+                    break;
+                } else {
+                    return Err(err);
+                }
+            };
+            total += count;
+            if count < buf.len() {
+                break;
+            }
+        }
+        Ok(total)
+    }
+
     /// Seek to the given offset
     /// Returns `ESPIPE` if the operation is not supported.
     fn seek(&mut self, pos: ResourceSeek) -> Result<usize> {
@@ -53,6 +108,24 @@ pub trait Resource {
         Err(Error::new(EPERM))
     }
 
+    /// Change the permission bits of the resource
+    /// Returns `EOPNOTSUPP` if the operation is not supported.
+    fn chmod(&mut self, mode: usize) -> Result<()> {
+        Err(Error::new(EOPNOTSUPP))
+    }
+
+    /// Change the owner and group of the resource
+    /// Returns `EOPNOTSUPP` if the operation is not supported.
+    fn chown(&mut self, uid: usize, gid: usize) -> Result<()> {
+        Err(Error::new(EOPNOTSUPP))
+    }
+
+    /// Set the access and modification times of the resource
+    /// Returns `EOPNOTSUPP` if the operation is not supported.
+    fn utimens(&mut self, times: &[TimeSpec; 2]) -> Result<()> {
+        Err(Error::new(EOPNOTSUPP))
+    }
+
     /// Sync all buffers
     /// Returns `EPERM` if the operation is not supported.
     fn sync(&mut self) -> Result<()> {
@@ -64,4 +137,10 @@ pub trait Resource {
     fn truncate(&mut self, len: usize) -> Result<()> {
         Err(Error::new(EPERM))
     }
+
+    /// Get the memory backing the resource, so that it can be mapped
+    /// Returns `EPERM` if the operation is not supported.
+    fn map(&mut self, writeable: bool) -> Result<Arc<SharedMemory>> {
+        Err(Error::new(EPERM))
+    }
 }
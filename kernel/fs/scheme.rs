@@ -1,30 +1,55 @@
 use alloc::arc::{Arc, Weak};
 use alloc::boxed::Box;
 
-use collections::String;
+use collections::{BTreeMap, String, Vec};
 use collections::borrow::ToOwned;
 
-use core::cell::Cell;
+use core::cell::{Cell, UnsafeCell};
 use core::mem::size_of;
 use core::ops::DerefMut;
-use core::{ptr, slice};
+use core::{cmp, ptr};
 
 use arch::context::{Context, ContextMemory};
+use arch::memory::LOGICAL_OFFSET;
 
 use sync::{WaitMap, WaitQueue};
 
-use system::error::{Error, Result, EFAULT, EINVAL, ENODEV, ESPIPE};
-use system::scheme::Packet;
+use system::error::{Error, Result, EINTR, EINVAL, EIO, ENODEV, ESPIPE};
+use system::scheme::{Packet, PACKET_CANCEL};
 use system::syscall::{SYS_CLOSE, SYS_DUP, SYS_FPATH, SYS_FSTAT, SYS_FSYNC, SYS_FTRUNCATE,
                     SYS_OPEN, SYS_LSEEK, SEEK_SET, SEEK_CUR, SEEK_END, SYS_MKDIR,
-                    SYS_READ, SYS_WRITE, SYS_RMDIR, SYS_STAT, SYS_UNLINK, Stat};
+                    SYS_PREAD, SYS_PWRITE, SYS_READ, SYS_READV, SYS_WRITE, SYS_WRITEV,
+                    SYS_RMDIR, SYS_STAT, SYS_UNLINK, SYS_CHMOD, SYS_CHOWN, SYS_UTIMENS,
+                    SYS_FCHMOD, SYS_FCHOWN, SYS_FUTIMENS, IoVec, Stat, TimeSpec};
 
 use super::{Resource, ResourceSeek, KScheme, Url};
 
+/// Map physical memory into the mmap zone of a scheme daemon's context, returning the address
+/// it is mapped at there. The memory is not owned by the daemon, and is released by setting the
+/// size of its region to zero.
+pub unsafe fn capture_physical(context: &mut Context, physical_address: usize, size: usize, writeable: bool) -> usize {
+    let mmap = &mut *context.mmap.get();
+    let virtual_address = mmap.next_mem();
+    mmap.memory.push(ContextMemory {
+        physical_address: physical_address,
+        virtual_address: virtual_address,
+        virtual_size: size,
+        writeable: writeable,
+        executable: false,
+        allocated: false,
+        shared: None,
+    });
+    virtual_address
+}
+
 struct SchemeInner {
     name: String,
     context: *mut Context,
     next_id: Cell<usize>,
+    /// The number of open server resources. The scheme is dead once it drops to zero
+    servers: Cell<usize>,
+    /// Requests that have been sent and not yet answered, and whether they were cancelled
+    in_flight: UnsafeCell<BTreeMap<usize, bool>>,
     todo: WaitQueue<Packet>,
     done: WaitMap<usize, (usize, usize, usize, usize)>,
 }
@@ -35,21 +60,110 @@ impl SchemeInner {
             name: name.to_owned(),
             context: context,
             next_id: Cell::new(1),
+            servers: Cell::new(0),
+            in_flight: UnsafeCell::new(BTreeMap::new()),
             todo: WaitQueue::new(),
             done: WaitMap::new(),
         }
     }
 
-    fn call(inner: &Weak<SchemeInner>, a: usize, b: usize, c: usize, d: usize) -> Result<usize> {
-        if let Some(scheme) = inner.upgrade() {
-            let id = scheme.next_id.get();
+    /// Whether the daemon serving the scheme has closed it, or exited
+    fn dead(&self) -> bool {
+        self.servers.get() == 0
+    }
+
+    /// Unregister the scheme after its daemon closed it, and fail every request in flight
+    ///
+    /// The name is released right away, so a replacement daemon can register it while callers
+    /// still wait on requests to this scheme. This frees the `Scheme`, so those requests must only
+    /// use their `SchemeClient`.
+    fn close(&self) {
+        unsafe { &mut *::env().schemes.get() }.retain(|scheme| scheme.scheme() != self.name);
+
+        unsafe {
+            self.todo.inner().clear();
+            (*self.in_flight.get()).clear();
+        }
+        self.done.notify("SchemeInner::close");
+    }
+
+    /// Wait for the reply to a request, failing with `EIO` if the scheme dies first
+    ///
+    /// A caller killed while waiting, see `Context::kill`, cancels the request and fails with
+    /// `EINTR`, so it can exit instead of waiting on the scheme.
+    fn receive(&self, id: usize) -> Result<usize> {
+        let killed = || unsafe { & *::env().contexts.get() }.current().map(|current| current.killed).unwrap_or(false);
+
+        match self.done.receive_unless(&id, "SchemeInner::receive", || self.dead() || killed()) {
+            Some(reply) => Error::demux(reply.0),
+            None => if self.dead() {
+                Err(Error::new(EIO))
+            } else {
+                self.cancel(id);
+                Err(Error::new(EINTR))
+            }
+        }
+    }
+
+    /// Allocate an ID that is not zero, and not used by any request in flight
+    fn alloc_id(&self) -> usize {
+        let in_flight = unsafe { &mut *self.in_flight.get() };
+        loop {
+            let id = self.next_id.get();
+            self.next_id.set(id.wrapping_add(1));
+            if id != 0 && ! in_flight.contains_key(&id) {
+                in_flight.insert(id, false);
+                return id;
+            }
+        }
+    }
+
+    /// Accept a reply from the scheme, ignoring replies that are not for a request in flight
+    fn reply(&self, packet: &Packet) {
+        let in_flight = unsafe { &mut *self.in_flight.get() };
+        match in_flight.remove(&packet.id) {
+            Some(false) => self.done.send(packet.id, (packet.a, packet.b, packet.c, packet.d), "SchemeInner::reply done"),
+            Some(true) => (),
+            None => debugln!("{}: unexpected reply {}", self.name, packet.id)
+        }
+    }
+
+    /// Stop waiting for a request
+    ///
+    /// If the scheme has not read the request yet, it is dropped. Otherwise the scheme is sent a
+    /// cancel packet, and the ID stays reserved until the scheme answers the request.
+    fn cancel(&self, id: usize) {
+        let in_flight = unsafe { &mut *self.in_flight.get() };
+        if ! in_flight.contains_key(&id) {
+            unsafe { self.done.inner() }.remove(&id);
+            return;
+        }
+
+        let todo = unsafe { self.todo.inner() };
+        let len = todo.len();
+        todo.retain(|packet| packet.id != id);
+        if todo.len() < len {
+            in_flight.remove(&id);
+        } else {
+            in_flight.insert(id, true);
+            self.todo.send(Packet {
+                id: 0,
+                a: PACKET_CANCEL,
+                b: id,
+                c: 0,
+                d: 0,
+                e: 0
+            }, "SchemeInner::cancel todo");
+        }
+    }
 
-            //TODO: What should be done about collisions in self.todo or self.done?
-            let mut next_id = id + 1;
-            if next_id <= 0 {
-                next_id = 1;
+    fn call(inner: &Weak<SchemeInner>, a: usize, b: usize, c: usize, d: usize, e: usize) -> Result<usize> {
+        if let Some(scheme) = inner.upgrade() {
+            if scheme.dead() {
+                return Err(Error::new(ENODEV));
             }
-            scheme.next_id.set(next_id);
+
+            let id = scheme.alloc_id();
 
             // debugln!("{} {}: {} {} {:X} {:X} {:X}", scheme.name, id, a, ::syscall::name(a), b, c, d);
 
@@ -58,10 +172,11 @@ impl SchemeInner {
                 a: a,
                 b: b,
                 c: c,
-                d: d
+                d: d,
+                e: e
             }, "SchemeInner::call todo");
 
-            let res = Error::demux(scheme.done.receive(&id, "SchemeInner::call done").0);
+            let res = scheme.receive(id);
             // debugln!("{} {}: {} {} {:X} {:X} {:X} = {:?}", scheme.name, id, a, ::syscall::name(a), b, c, d, res);
             res
         } else {
@@ -69,30 +184,31 @@ impl SchemeInner {
         }
     }
 
-    fn capture(inner: &Weak<SchemeInner>, mut physical_address: usize, size: usize, writeable: bool) -> Result<usize> {
+    /// Map physical memory into the scheme's context
+    fn capture(inner: &Weak<SchemeInner>, physical_address: usize, size: usize, writeable: bool) -> Result<usize> {
         if let Some(scheme) = inner.upgrade() {
-            if physical_address >= 0x80000000 {
-                physical_address -= 0x80000000;
-            }
-            unsafe {
-                let mmap = &mut *(*scheme.context).mmap.get();
-                let virtual_address = mmap.next_mem();
-                mmap.memory.push(ContextMemory {
-                    physical_address: physical_address,
-                    virtual_address: virtual_address,
-                    virtual_size: size,
-                    writeable: writeable,
-                    allocated: false,
-                });
-                return Ok(virtual_address);
+            // The daemon's context may be gone
+            if scheme.dead() {
+                return Err(Error::new(ENODEV));
             }
+
+            Ok(unsafe { capture_physical(&mut *scheme.context, physical_address, size, writeable) })
         } else {
-            return Err(Error::new(ENODEV));
+            Err(Error::new(ENODEV))
         }
     }
 
+    /// Map a buffer of the kernel heap into the scheme's context
+    fn capture_kernel(inner: &Weak<SchemeInner>, address: usize, size: usize, writeable: bool) -> Result<usize> {
+        SchemeInner::capture(inner, address - LOGICAL_OFFSET, size, writeable)
+    }
+
     fn release(inner: &Weak<SchemeInner>, virtual_address: usize) {
         if let Some(scheme) = inner.upgrade() {
+            if scheme.dead() {
+                return;
+            }
+
             unsafe {
                 let mmap = &mut *(*scheme.context).mmap.get();
                 if let Ok(mut mem) = mmap.get_mem_mut(virtual_address) {
@@ -102,11 +218,90 @@ impl SchemeInner {
             }
         }
     }
-}
 
-impl Drop for SchemeInner {
-    fn drop(&mut self) {
-        unsafe { &mut *::env().schemes.get() }.retain(|scheme| scheme.scheme() != self.name);
+    /// Map a buffer of the current context into the scheme's context
+    ///
+    /// The buffer is translated page by page, so it may span pages that are not physically
+    /// contiguous, or more than one memory region. The pages are mapped to a contiguous window,
+    /// and the address of the buffer in that window is returned.
+    fn capture_buffer(inner: &Weak<SchemeInner>, address: usize, len: usize, writeable: bool) -> Result<usize> {
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let contexts = unsafe { & *::env().contexts.get() };
+        let current = try!(contexts.current());
+
+        let offset = address % 4096;
+        let start = address - offset;
+        let pages = (offset + len + 4095) / 4096;
+
+        // Translate every page first, so nothing is captured if part of the buffer is invalid
+        let mut physical_pages = Vec::with_capacity(pages);
+        for i in 0..pages {
+            let page = start + i * 4096;
+            let from = cmp::max(page, address);
+            let to = cmp::min(page + 4096, address + len);
+            match current.translate(from, to - from) {
+                Ok(physical_address) => physical_pages.push(physical_address - (from - page)),
+                Err(err) => {
+                    debugln!("{}:{} fault {:X} {}", file!(), line!(), address, len);
+                    return Err(err);
+                }
+            }
+        }
+
+        // Capture each run of physically contiguous pages. The runs are page sized, so they end up
+        // next to each other
+        let mut window = 0;
+        let mut i = 0;
+        while i < pages {
+            let mut count = 1;
+            while i + count < pages && physical_pages[i + count] == physical_pages[i] + count * 4096 {
+                count += 1;
+            }
+
+            match SchemeInner::capture(inner, physical_pages[i], count * 4096, writeable) {
+                Ok(virtual_address) => if i == 0 {
+                    window = virtual_address;
+                },
+                Err(err) => {
+                    if i > 0 {
+                        SchemeInner::release_buffer(inner, window + offset, i * 4096 - offset);
+                    }
+                    return Err(err);
+                }
+            }
+
+            i += count;
+        }
+
+        Ok(window + offset)
+    }
+
+    /// Release a buffer captured with `capture_buffer`
+    fn release_buffer(inner: &Weak<SchemeInner>, virtual_address: usize, len: usize) {
+        if len == 0 {
+            return;
+        }
+
+        if let Some(scheme) = inner.upgrade() {
+            if scheme.dead() {
+                return;
+            }
+
+            let start = virtual_address - virtual_address % 4096;
+            let end = virtual_address + len;
+            unsafe {
+                let mmap = &mut *(*scheme.context).mmap.get();
+                for mem in mmap.memory.iter_mut() {
+                    if mem.virtual_address >= start && mem.virtual_address < end {
+                        mem.virtual_size = 0;
+                    }
+                }
+                mmap.clean_mem();
+            }
+        }
     }
 }
 
@@ -116,23 +311,31 @@ pub struct SchemeResource {
 }
 
 impl SchemeResource {
-    fn call(&self, a: usize, b: usize, c: usize, d: usize) -> Result<usize> {
-        SchemeInner::call(&self.inner, a, b, c, d)
+    fn call(&self, a: usize, b: usize, c: usize, d: usize, e: usize) -> Result<usize> {
+        SchemeInner::call(&self.inner, a, b, c, d, e)
     }
 
-    fn capture(&self, physical_address: usize, size: usize, writeable: bool) -> Result<usize> {
-        SchemeInner::capture(&self.inner, physical_address, size, writeable)
+    fn capture_kernel(&self, address: usize, size: usize, writeable: bool) -> Result<usize> {
+        SchemeInner::capture_kernel(&self.inner, address, size, writeable)
     }
 
     fn release(&self, virtual_address: usize){
         SchemeInner::release(&self.inner, virtual_address);
     }
+
+    fn capture_buffer(&self, address: usize, len: usize, writeable: bool) -> Result<usize> {
+        SchemeInner::capture_buffer(&self.inner, address, len, writeable)
+    }
+
+    fn release_buffer(&self, virtual_address: usize, len: usize) {
+        SchemeInner::release_buffer(&self.inner, virtual_address, len);
+    }
 }
 
 impl Resource for SchemeResource {
     /// Duplicate the resource
     fn dup(&self) -> Result<Box<Resource>> {
-        let file_id = try!(self.call(SYS_DUP, self.file_id, 0, 0));
+        let file_id = try!(self.call(SYS_DUP, self.file_id, 0, 0, 0));
         Ok(Box::new(SchemeResource {
             inner: self.inner.clone(),
             file_id: file_id
@@ -141,68 +344,125 @@ impl Resource for SchemeResource {
 
     /// Return the URL of this resource
     fn path(&self, buf: &mut [u8]) -> Result <usize> {
-        let contexts = unsafe { & *::env().contexts.get() };
-        let current = try!(contexts.current());
-        if let Ok(physical_address) = current.translate(buf.as_mut_ptr() as usize, buf.len()) {
-            let offset = physical_address % 4096;
+        let virtual_address = try!(self.capture_buffer(buf.as_mut_ptr() as usize, buf.len(), true));
 
-            let virtual_address = try!(self.capture(physical_address - offset, buf.len() + offset, true));
+        let result = self.call(SYS_FPATH, self.file_id, virtual_address, buf.len(), 0);
 
-            let result = self.call(SYS_FPATH, self.file_id, virtual_address + offset, buf.len());
+        self.release_buffer(virtual_address, buf.len());
 
-            //debugln!("Read {:X} mapped from {:X} to {:X} offset {} length {} size {} result {:?}", physical_address, buf.as_ptr() as usize, virtual_address + offset, offset, buf.len(), virtual_size, result);
-
-            self.release(virtual_address);
-
-            result
-        } else {
-            debugln!("{}:{} fault {:X} {}", file!(), line!(), buf.as_ptr() as usize, buf.len());
-            Err(Error::new(EFAULT))
-        }
+        result
     }
 
     /// Read data to buffer
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        let contexts = unsafe { & *::env().contexts.get() };
-        let current = try!(contexts.current());
-        if let Ok(physical_address) = current.translate(buf.as_mut_ptr() as usize, buf.len()) {
-            let offset = physical_address % 4096;
-
-            let virtual_address = try!(self.capture(physical_address - offset, buf.len() + offset, true));
+        let virtual_address = try!(self.capture_buffer(buf.as_mut_ptr() as usize, buf.len(), true));
 
-            let result = self.call(SYS_READ, self.file_id, virtual_address + offset, buf.len());
+        let result = self.call(SYS_READ, self.file_id, virtual_address, buf.len(), 0);
 
-            //debugln!("Read {:X} mapped from {:X} to {:X} offset {} length {} size {} result {:?}", physical_address, buf.as_ptr() as usize, virtual_address + offset, offset, buf.len(), virtual_size, result);
+        self.release_buffer(virtual_address, buf.len());
 
-            self.release(virtual_address);
-
-            result
-        } else {
-            debugln!("{}:{} fault {:X} {}", file!(), line!(), buf.as_ptr() as usize, buf.len());
-            Err(Error::new(EFAULT))
-        }
+        result
     }
 
     /// Write to resource
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        let contexts = unsafe { & *::env().contexts.get() };
-        let current = try!(contexts.current());
-        if let Ok(physical_address) = current.translate(buf.as_ptr() as usize, buf.len()) {
-            let offset = physical_address % 4096;
+        let virtual_address = try!(self.capture_buffer(buf.as_ptr() as usize, buf.len(), false));
 
-            let virtual_address = try!(self.capture(physical_address - offset, buf.len() + offset, false));
+        let result = self.call(SYS_WRITE, self.file_id, virtual_address, buf.len(), 0);
 
-            let result = self.call(SYS_WRITE, self.file_id, virtual_address + offset, buf.len());
+        self.release_buffer(virtual_address, buf.len());
 
-            // debugln!("Write {:X} mapped from {:X} to {:X} offset {} length {} result {:?}", physical_address, buf.as_ptr() as usize, virtual_address + offset, offset, buf.len(), result);
+        result
+    }
 
-            self.release(virtual_address);
+    /// Read data to buffer at offset
+    fn pread(&mut self, buf: &mut [u8], position: usize) -> Result<usize> {
+        let virtual_address = try!(self.capture_buffer(buf.as_mut_ptr() as usize, buf.len(), true));
 
-            result
-        } else {
-            debugln!("{}:{} fault {:X} {}", file!(), line!(), buf.as_ptr() as usize, buf.len());
-            Err(Error::new(EFAULT))
+        let result = self.call(SYS_PREAD, self.file_id, virtual_address, buf.len(), position);
+
+        self.release_buffer(virtual_address, buf.len());
+
+        result
+    }
+
+    /// Write to resource at offset
+    fn pwrite(&mut self, buf: &[u8], position: usize) -> Result<usize> {
+        let virtual_address = try!(self.capture_buffer(buf.as_ptr() as usize, buf.len(), false));
+
+        let result = self.call(SYS_PWRITE, self.file_id, virtual_address, buf.len(), position);
+
+        self.release_buffer(virtual_address, buf.len());
+
+        result
+    }
+
+    /// Read data to several buffers in one request
+    fn readv(&mut self, bufs: &mut [&mut [u8]]) -> Result<usize> {
+        let mut iov = Vec::new();
+        for buf in bufs.iter_mut() {
+            match self.capture_buffer(buf.as_mut_ptr() as usize, buf.len(), true) {
+                Ok(virtual_address) => iov.push(IoVec {
+                    iov_base: virtual_address,
+                    iov_len: buf.len(),
+                }),
+                Err(err) => {
+                    for vec in iov.iter() {
+                        self.release_buffer(vec.iov_base, vec.iov_len);
+                    }
+                    return Err(err);
+                }
+            }
         }
+
+        let result = match self.capture_kernel(iov.as_ptr() as usize, iov.len() * size_of::<IoVec>(), false) {
+            Ok(iov_address) => {
+                let result = self.call(SYS_READV, self.file_id, iov_address, iov.len(), 0);
+                self.release(iov_address);
+                result
+            },
+            Err(err) => Err(err)
+        };
+
+        for vec in iov.iter() {
+            self.release_buffer(vec.iov_base, vec.iov_len);
+        }
+
+        result
+    }
+
+    /// Write several buffers in one request
+    fn writev(&mut self, bufs: &[&[u8]]) -> Result<usize> {
+        let mut iov = Vec::new();
+        for buf in bufs.iter() {
+            match self.capture_buffer(buf.as_ptr() as usize, buf.len(), false) {
+                Ok(virtual_address) => iov.push(IoVec {
+                    iov_base: virtual_address,
+                    iov_len: buf.len(),
+                }),
+                Err(err) => {
+                    for vec in iov.iter() {
+                        self.release_buffer(vec.iov_base, vec.iov_len);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+
+        let result = match self.capture_kernel(iov.as_ptr() as usize, iov.len() * size_of::<IoVec>(), false) {
+            Ok(iov_address) => {
+                let result = self.call(SYS_WRITEV, self.file_id, iov_address, iov.len(), 0);
+                self.release(iov_address);
+                result
+            },
+            Err(err) => Err(err)
+        };
+
+        for vec in iov.iter() {
+            self.release_buffer(vec.iov_base, vec.iov_len);
+        }
+
+        result
     }
 
     /// Seek
@@ -213,44 +473,54 @@ impl Resource for SchemeResource {
             ResourceSeek::End(offset) => (SEEK_END, offset as usize)
         };
 
-        self.call(SYS_LSEEK, self.file_id, offset, whence)
+        self.call(SYS_LSEEK, self.file_id, offset, whence, 0)
     }
 
     /// Stat
     fn stat(&self, stat: &mut Stat) -> Result<usize> {
-        let buf = unsafe { slice::from_raw_parts_mut(stat as *mut Stat as *mut u8, size_of::<Stat>()) };
+        let virtual_address = try!(self.capture_buffer(stat as *mut Stat as usize, size_of::<Stat>(), true));
 
-        let contexts = unsafe { & *::env().contexts.get() };
-        let current = try!(contexts.current());
-        if let Ok(physical_address) = current.translate(buf.as_mut_ptr() as usize, buf.len()) {
-            let offset = physical_address % 4096;
+        let result = self.call(SYS_FSTAT, self.file_id, virtual_address, size_of::<Stat>(), 0);
+
+        self.release_buffer(virtual_address, size_of::<Stat>());
+
+        result
+    }
 
-            let virtual_address = try!(self.capture(physical_address - offset, buf.len() + offset, true));
+    /// Change mode
+    fn chmod(&mut self, mode: usize) -> Result<()> {
+        self.call(SYS_FCHMOD, self.file_id, mode, 0, 0).and(Ok(()))
+    }
 
-            let result = self.call(SYS_FSTAT, self.file_id, virtual_address + offset, buf.len());
+    /// Change owner
+    fn chown(&mut self, uid: usize, gid: usize) -> Result<()> {
+        self.call(SYS_FCHOWN, self.file_id, uid, gid, 0).and(Ok(()))
+    }
 
-            self.release(virtual_address);
+    /// Set times
+    fn utimens(&mut self, times: &[TimeSpec; 2]) -> Result<()> {
+        let virtual_address = try!(self.capture_buffer(times.as_ptr() as usize, size_of::<[TimeSpec; 2]>(), false));
 
-            result
-        } else {
-            debugln!("{}:{} fault {:X} {}", file!(), line!(), buf.as_ptr() as usize, buf.len());
-            Err(Error::new(EFAULT))
-        }
+        let result = self.call(SYS_FUTIMENS, self.file_id, virtual_address, 0, 0);
+
+        self.release_buffer(virtual_address, size_of::<[TimeSpec; 2]>());
+
+        result.and(Ok(()))
     }
 
     /// Sync the resource
     fn sync(&mut self) -> Result<()> {
-        self.call(SYS_FSYNC, self.file_id, 0, 0).and(Ok(()))
+        self.call(SYS_FSYNC, self.file_id, 0, 0, 0).and(Ok(()))
     }
 
     fn truncate(&mut self, len: usize) -> Result<()> {
-        self.call(SYS_FTRUNCATE, self.file_id, len, 0).and(Ok(()))
+        self.call(SYS_FTRUNCATE, self.file_id, len, 0, 0).and(Ok(()))
     }
 }
 
 impl Drop for SchemeResource {
     fn drop(&mut self) {
-        let _ = self.call(SYS_CLOSE, self.file_id, 0, 0);
+        let _ = self.call(SYS_CLOSE, self.file_id, 0, 0, 0);
     }
 }
 
@@ -258,12 +528,28 @@ pub struct SchemeServerResource {
     inner: Arc<SchemeInner>,
 }
 
+impl SchemeServerResource {
+    fn new(inner: Arc<SchemeInner>) -> SchemeServerResource {
+        inner.servers.set(inner.servers.get() + 1);
+        SchemeServerResource {
+            inner: inner
+        }
+    }
+}
+
+impl Drop for SchemeServerResource {
+    fn drop(&mut self) {
+        self.inner.servers.set(self.inner.servers.get() - 1);
+        if self.inner.dead() {
+            self.inner.close();
+        }
+    }
+}
+
 impl Resource for SchemeServerResource {
     /// Duplicate the resource
     fn dup(&self) -> Result<Box<Resource>> {
-        Ok(box SchemeServerResource {
-            inner: self.inner.clone()
-        })
+        Ok(box SchemeServerResource::new(self.inner.clone()))
     }
 
     /// Return the URL of this resource
@@ -317,7 +603,9 @@ impl Resource for SchemeServerResource {
 
             while i <= buf.len() - size_of::<Packet>() {
                 let packet = unsafe { & *(buf.as_ptr().offset(i as isize) as *const Packet) };
-                self.inner.done.send(packet.id, (packet.a, packet.b, packet.c, packet.d), "SchemeServerResource::write done");
+                if packet.id != 0 {
+                    self.inner.reply(packet);
+                }
                 i += size_of::<Packet>();
             }
 
@@ -352,9 +640,7 @@ impl Scheme {
     pub fn new(name: &str) -> Result<(Box<Scheme>, Box<Resource>)> {
         let contexts = unsafe { &mut *::env().contexts.get() };
         let mut current = try!(contexts.current_mut());
-        let server = box SchemeServerResource {
-            inner: Arc::new(SchemeInner::new(name, current.deref_mut()))
-        };
+        let server = box SchemeServerResource::new(Arc::new(SchemeInner::new(name, current.deref_mut())));
         let scheme = box Scheme {
             name: name.to_owned(),
             inner: Arc::downgrade(&server.inner)
@@ -362,16 +648,11 @@ impl Scheme {
         Ok((scheme, server))
     }
 
-    fn call(&self, a: usize, b: usize, c: usize, d: usize) -> Result<usize> {
-        SchemeInner::call(&self.inner, a, b, c, d)
-    }
-
-    fn capture(&self, physical_address: usize, size: usize, writeable: bool) -> Result<usize> {
-        SchemeInner::capture(&self.inner, physical_address, size, writeable)
-    }
-
-    fn release(&self, virtual_address: usize){
-        SchemeInner::release(&self.inner, virtual_address);
+    /// A handle to make a request with, which outlives this `Scheme` if it is closed meanwhile
+    fn client(&self) -> SchemeClient {
+        SchemeClient {
+            inner: self.inner.clone()
+        }
     }
 }
 
@@ -385,11 +666,73 @@ impl KScheme for Scheme {
     }
 
     fn open(&mut self, url: Url, flags: usize) -> Result<Box<Resource>> {
+        self.client().open(url, flags)
+    }
+
+    fn mkdir(&mut self, url: Url, flags: usize) -> Result<()> {
+        self.client().mkdir(url, flags)
+    }
+
+    fn rmdir(&mut self, url: Url) -> Result<()> {
+        self.client().rmdir(url)
+    }
+
+    fn stat(&mut self, url: Url, stat: &mut Stat) -> Result<()> {
+        self.client().stat(url, stat)
+    }
+
+    fn unlink(&mut self, url: Url) -> Result<()> {
+        self.client().unlink(url)
+    }
+
+    fn chmod(&mut self, url: Url, mode: usize) -> Result<()> {
+        self.client().chmod(url, mode)
+    }
+
+    fn chown(&mut self, url: Url, uid: usize, gid: usize) -> Result<()> {
+        self.client().chown(url, uid, gid)
+    }
+
+    fn utimens(&mut self, url: Url, times: &[TimeSpec; 2]) -> Result<()> {
+        self.client().utimens(url, times)
+    }
+}
+
+/// A request to a scheme, made on a handle of its own
+///
+/// The daemon may close the scheme while the request waits for it, which drops the `Scheme` from
+/// the scheme list. The request only uses this handle, so it never touches the freed `Scheme`.
+struct SchemeClient {
+    inner: Weak<SchemeInner>
+}
+
+impl SchemeClient {
+    fn call(&self, a: usize, b: usize, c: usize, d: usize, e: usize) -> Result<usize> {
+        SchemeInner::call(&self.inner, a, b, c, d, e)
+    }
+
+    fn capture_kernel(&self, address: usize, size: usize, writeable: bool) -> Result<usize> {
+        SchemeInner::capture_kernel(&self.inner, address, size, writeable)
+    }
+
+    fn release(&self, virtual_address: usize){
+        SchemeInner::release(&self.inner, virtual_address);
+    }
+
+    fn capture_buffer(&self, address: usize, len: usize, writeable: bool) -> Result<usize> {
+        SchemeInner::capture_buffer(&self.inner, address, len, writeable)
+    }
+
+    fn release_buffer(&self, virtual_address: usize, len: usize) {
+        SchemeInner::release_buffer(&self.inner, virtual_address, len);
+    }
+
+    fn open(&self, url: Url, flags: usize) -> Result<Box<Resource>> {
         let c_str = url.to_string() + "\0";
 
-        let virtual_address = try!(self.capture(c_str.as_ptr() as usize, c_str.len(), false));
+        let virtual_address = try!(self.capture_kernel(c_str.as_ptr() as usize, c_str.len(), false));
 
-        let result = self.call(SYS_OPEN, virtual_address, flags, 0);
+        let result = self.call(SYS_OPEN, virtual_address, flags, 0, 0);
 
         self.release(virtual_address);
 
@@ -402,64 +745,105 @@ impl KScheme for Scheme {
         }
     }
 
-    fn mkdir(&mut self, url: Url, flags: usize) -> Result<()> {
+    fn mkdir(&self, url: Url, flags: usize) -> Result<()> {
         let c_str = url.to_string() + "\0";
 
-        let virtual_address = try!(self.capture(c_str.as_ptr() as usize, c_str.len(), false));
+        let virtual_address = try!(self.capture_kernel(c_str.as_ptr() as usize, c_str.len(), false));
 
-        let result = self.call(SYS_MKDIR, virtual_address, flags, 0);
+        let result = self.call(SYS_MKDIR, virtual_address, flags, 0, 0);
 
         self.release(virtual_address);
 
         result.and(Ok(()))
     }
 
-    fn rmdir(&mut self, url: Url) -> Result<()> {
+    fn rmdir(&self, url: Url) -> Result<()> {
         let c_str = url.to_string() + "\0";
 
-        let virtual_address = try!(self.capture(c_str.as_ptr() as usize, c_str.len(), false));
+        let virtual_address = try!(self.capture_kernel(c_str.as_ptr() as usize, c_str.len(), false));
 
-        let result = self.call(SYS_RMDIR, virtual_address, 0, 0);
+        let result = self.call(SYS_RMDIR, virtual_address, 0, 0, 0);
 
         self.release(virtual_address);
 
         result.and(Ok(()))
     }
 
-    fn stat(&mut self, url: Url, stat: &mut Stat) -> Result<()> {
-        let buf = unsafe { slice::from_raw_parts_mut(stat as *mut Stat as *mut u8, size_of::<Stat>()) };
+    fn stat(&self, url: Url, stat: &mut Stat) -> Result<()> {
+        let virtual_address = try!(self.capture_buffer(stat as *mut Stat as usize, size_of::<Stat>(), true));
 
-        let contexts = unsafe { & *::env().contexts.get() };
-        let current = try!(contexts.current());
-        if let Ok(physical_address) = current.translate(buf.as_mut_ptr() as usize, buf.len()) {
-            let offset = physical_address % 4096;
+        let c_str = url.to_string() + "\0";
 
-            let virtual_address = try!(self.capture(physical_address - offset, buf.len() + offset, true));
+        let c_str_address = match self.capture_kernel(c_str.as_ptr() as usize, c_str.len(), false) {
+            Ok(address) => address,
+            Err(err) => {
+                self.release_buffer(virtual_address, size_of::<Stat>());
+                return Err(err);
+            }
+        };
 
-            let c_str = url.to_string() + "\0";
+        let result = self.call(SYS_STAT, c_str_address, virtual_address, size_of::<Stat>(), 0);
 
-            let c_str_address = try!(self.capture(c_str.as_ptr() as usize, c_str.len(), false));
+        self.release(c_str_address);
+        self.release_buffer(virtual_address, size_of::<Stat>());
 
-            let result = self.call(SYS_STAT, c_str_address, virtual_address + offset, buf.len());
+        result.and(Ok(()))
+    }
 
-            self.release(c_str_address);
+    fn unlink(&self, url: Url) -> Result<()> {
+        let c_str = url.to_string() + "\0";
 
-            result.and(Ok(()))
-        } else {
-            debugln!("{}:{} fault {:X} {}", file!(), line!(), buf.as_ptr() as usize, buf.len());
-            Err(Error::new(EFAULT))
-        }
+        let virtual_address = try!(self.capture_kernel(c_str.as_ptr() as usize, c_str.len(), false));
+
+        let result = self.call(SYS_UNLINK, virtual_address, 0, 0, 0);
+
+        self.release(virtual_address);
+
+        result.and(Ok(()))
     }
 
-    fn unlink(&mut self, url: Url) -> Result<()> {
+    fn chmod(&self, url: Url, mode: usize) -> Result<()> {
         let c_str = url.to_string() + "\0";
 
-        let virtual_address = try!(self.capture(c_str.as_ptr() as usize, c_str.len(), false));
+        let virtual_address = try!(self.capture_kernel(c_str.as_ptr() as usize, c_str.len(), false));
 
-        let result = self.call(SYS_UNLINK, virtual_address, 0, 0);
+        let result = self.call(SYS_CHMOD, virtual_address, mode, 0, 0);
 
         self.release(virtual_address);
 
         result.and(Ok(()))
     }
+
+    fn chown(&self, url: Url, uid: usize, gid: usize) -> Result<()> {
+        let c_str = url.to_string() + "\0";
+
+        let virtual_address = try!(self.capture_kernel(c_str.as_ptr() as usize, c_str.len(), false));
+
+        let result = self.call(SYS_CHOWN, virtual_address, uid, gid, 0);
+
+        self.release(virtual_address);
+
+        result.and(Ok(()))
+    }
+
+    fn utimens(&self, url: Url, times: &[TimeSpec; 2]) -> Result<()> {
+        let virtual_address = try!(self.capture_buffer(times.as_ptr() as usize, size_of::<[TimeSpec; 2]>(), false));
+
+        let c_str = url.to_string() + "\0";
+
+        let c_str_address = match self.capture_kernel(c_str.as_ptr() as usize, c_str.len(), false) {
+            Ok(address) => address,
+            Err(err) => {
+                self.release_buffer(virtual_address, size_of::<[TimeSpec; 2]>());
+                return Err(err);
+            }
+        };
+
+        let result = self.call(SYS_UTIMENS, c_str_address, virtual_address, 0, 0);
+
+        self.release(c_str_address);
+        self.release_buffer(virtual_address, size_of::<[TimeSpec; 2]>());
+
+        result.and(Ok(()))
+    }
 }
@@ -1,59 +1,128 @@
-use core::{cmp, mem};
+use core::{mem, ptr};
 use super::Resource;
-use system::error::Result;
+use system::error::{Error, Result, EBUSY, EINVAL};
 use system::scheme::Packet;
-use arch::context::{Context, context_switch};
+use system::syscall::SUPERVISE_RETURN;
+use arch::context::{context_switch, SyscallReply};
+use arch::memory;
 
 /// A supervisor resource.
 ///
-/// Reading from it will simply read the relevant registers to the buffer (see `Packet`).
+/// Reading from it will block until the jailed context stops at a syscall, and then read the
+/// syscall, or the result of a forwarded syscall, to the buffer (see `Packet`).
 ///
-/// Writing will simply left shift EAX by one byte, and then OR it with the byte from the buffer,
-/// effectively writing the buffer to the EAX register (truncating the additional bytes).
+/// Writing a pointer sized integer answers the stop with the value to return, and writing a
+/// `Packet` runs the syscall in the packet instead (see `SyscallReply`).
 pub struct SupervisorResource {
-    /// The jailed context.
-    ctx: *mut Context,
+    /// The PID of the jailed context.
+    pid: usize,
+    /// The stop that was read, until it is answered.
+    stop: Option<Packet>,
 }
 
 impl SupervisorResource {
     /// Create a new supervisor resource, supervising some PID.
-    pub unsafe fn new(ctx: *mut Context) -> Result<SupervisorResource> {
+    pub fn new(pid: usize) -> Result<SupervisorResource> {
         Ok(SupervisorResource {
-            ctx: ctx,
+            pid: pid,
+            stop: None,
         })
     }
 }
 
 impl Resource for SupervisorResource {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        let ctx = unsafe { &mut *self.ctx };
-        while !ctx.blocked_syscall {
-            unsafe { context_switch() };
+        if buf.len() < mem::size_of::<Packet>() || self.stop.is_some() {
+            return Err(Error::new(EINVAL));
         }
 
-        let call: Packet = ctx.regs.into();
+        loop {
+            {
+                let contexts = unsafe { &mut *::env().contexts.get() };
+                let ctx = match contexts.find_mut(self.pid) {
+                    Ok(ctx) => if ctx.exited {
+                        return Ok(0);
+                    } else {
+                        ctx
+                    },
+                    Err(_) => return Ok(0)
+                };
+
+                if let Some(call) = ctx.syscall_stop.take() {
+                    for (&a, b) in call.iter().zip(buf.iter_mut()) {
+                        *b = a;
+                    }
 
-        for (&a, b) in call.iter().zip(buf.iter_mut()) {
-            *b = a;
+                    self.stop = Some(call);
+                    return Ok(call.len());
+                }
+            }
+
+            unsafe { context_switch() };
         }
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let stop = match self.stop {
+            Some(stop) => stop,
+            None => return Err(Error::new(EINVAL))
+        };
+
+        // A syscall that returned can only be given another result
+        let reply = if buf.len() == mem::size_of::<usize>() {
+            SyscallReply::Return(unsafe { ptr::read(buf.as_ptr() as *const usize) })
+        } else if buf.len() == mem::size_of::<Packet>() && stop.id != SUPERVISE_RETURN {
+            SyscallReply::Forward(unsafe { ptr::read(buf.as_ptr() as *const Packet) })
+        } else {
+            return Err(Error::new(EINVAL));
+        };
 
+        let contexts = unsafe { &mut *::env().contexts.get() };
+        let ctx = try!(contexts.find_mut(self.pid));
+
+        self.stop = None;
+        ctx.syscall_reply = Some(reply);
         ctx.blocked_syscall = false;
+        ctx.unblock("SupervisorResource::write");
 
-        Ok(cmp::min(buf.len(), call.len()))
+        Ok(buf.len())
     }
 
-    fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        let ctx = unsafe { &mut *self.ctx };
+    /// Reads the memory of the jailed context at `offset`, while it is blocked by a syscall
+    fn pread(&mut self, buf: &mut [u8], offset: usize) -> Result<usize> {
+        let contexts = unsafe { & *::env().contexts.get() };
+        let ctx = try!(contexts.find(self.pid));
 
-        for &i in buf.iter().take(mem::size_of::<usize>()) {
-            ctx.regs.ax <<= 8;
-            ctx.regs.ax |= i as usize;
+        if ! ctx.blocked_syscall {
+            return Err(Error::new(EBUSY));
         }
 
-        ctx.block("SupervisorResource::write");
+        let address = try!(ctx.translate(offset, buf.len()));
+        unsafe { memory::read_physical(address, buf) };
 
-        Ok(cmp::min(mem::size_of::<usize>(), buf.len()))
+        Ok(buf.len())
     }
 
     // TODO implement seek?
 }
+
+impl Drop for SupervisorResource {
+    /// Stop supervising, letting a stopped context carry on with the syscall it stopped at
+    fn drop(&mut self) {
+        let contexts = unsafe { &mut *::env().contexts.get() };
+        if let Ok(ctx) = contexts.find_mut(self.pid) {
+            ctx.supervised = false;
+
+            if ctx.blocked_syscall {
+                let stop = ctx.syscall_stop.take().or(self.stop.take());
+                ctx.syscall_reply = stop.map(|stop| if stop.id == SUPERVISE_RETURN {
+                    SyscallReply::Return(stop.a)
+                } else {
+                    SyscallReply::Forward(stop)
+                });
+                ctx.blocked_syscall = false;
+                ctx.unblock("SupervisorResource::drop");
+            }
+        }
+    }
+}
@@ -78,6 +78,31 @@ impl Resource for VecResource {
         return Ok(i);
     }
 
+    fn pread(&mut self, buf: &mut [u8], offset: usize) -> Result<usize> {
+        let mut i = 0;
+        while i < buf.len() && offset + i < self.data.len() {
+            buf[i] = self.data[offset + i];
+            i += 1;
+        }
+        return Ok(i);
+    }
+
+    fn pwrite(&mut self, buf: &[u8], offset: usize) -> Result<usize> {
+        while offset > self.data.len() {
+            self.data.push(0);
+        }
+        let mut i = 0;
+        while i < buf.len() && offset + i < self.data.len() {
+            self.data[offset + i] = buf[i];
+            i += 1;
+        }
+        while i < buf.len() {
+            self.data.push(buf[i]);
+            i += 1;
+        }
+        return Ok(i);
+    }
+
     fn seek(&mut self, pos: ResourceSeek) -> Result<usize> {
         match pos {
             ResourceSeek::Start(offset) => self.seek = min(self.data.len(), offset),
@@ -1,8 +1,11 @@
+use collections::string::String;
+
 use core::fmt::{self, Write};
 
 use common::time::Duration;
 
-#[derive(Copy, Clone)]
+/// The priority of a log message, from most to least severe
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum LogLevel {
     Critical,
     Error,
@@ -11,6 +14,31 @@ pub enum LogLevel {
     Debug,
 }
 
+impl LogLevel {
+    /// The name used as the prefix of log messages
+    pub fn name(&self) -> &'static str {
+        match *self {
+            LogLevel::Critical => "CRIT",
+            LogLevel::Error    => "ERROR",
+            LogLevel::Warning  => "WARN",
+            LogLevel::Info     => "INFO",
+            LogLevel::Debug    => "DEBUG",
+        }
+    }
+
+    /// Parse a level from its name, ignoring case. Full names like `warning` are accepted too.
+    pub fn from_name(name: &str) -> Option<LogLevel> {
+        match &*name.trim().to_lowercase() {
+            "crit" | "critical" => Some(LogLevel::Critical),
+            "error" => Some(LogLevel::Error),
+            "warn" | "warning" => Some(LogLevel::Warning),
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            _ => None
+        }
+    }
+}
+
 /// Add message to kernel logs with format
 #[macro_export]
 macro_rules! syslog {
@@ -59,20 +87,24 @@ pub fn syslog(level: LogLevel, message: &str) {
     syslog_inner(level, format_args!("{}", message));
 }
 
+/// Add a message from userspace to the kernel logs, with `tag` naming its source
+pub fn syslog_tagged(level: LogLevel, tag: String, message: &str) {
+    log_entry(level, Some(tag), String::from(message));
+}
+
 //TODO: Limit log message size
 pub fn syslog_inner(level: LogLevel, message: fmt::Arguments) {
-    let time = Duration::monotonic();
-
-    let (prefix, display) = match level {
-        LogLevel::Debug    => ("DEBUG ", false),
-        LogLevel::Info     => ("INFO  ", true),
-        LogLevel::Warning  => ("WARN  ", true),
-        LogLevel::Error    => ("ERROR ", true),
-        LogLevel::Critical => ("CRIT  ", true),
-    };
+    let mut string = String::new();
+    let _ = string.write_fmt(message);
+    log_entry(level, None, string);
+}
 
-    let _ = write!(unsafe { &mut *::env().log.get() }, "[{}.{:>03}] {}{}\n", time.secs, time.nanos/1000000, prefix, message);
+/// Record an entry, and display it if it is at or above the console threshold
+fn log_entry(level: LogLevel, tag: Option<String>, message: String) {
+    let log = unsafe { &mut *::env().log.get() };
+    let display = level <= log.threshold;
+    let entry = log.write(level, Duration::monotonic(), tag, message);
     if display {
-        let _ = write!(::common::debug::SerialConsole::new(), "[{}.{:>03}] {}{}\n", time.secs, time.nanos/1000000, prefix, message);
+        let _ = write!(::common::debug::SerialConsole::new(), "{}", entry);
     }
 }
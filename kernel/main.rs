@@ -23,31 +23,34 @@ use acpi::Acpi;
 
 use alloc::boxed::Box;
 
-use arch::context::{context_switch, Context};
+use arch::context::{context_switch, Context, OOM_STATUS};
 use arch::memory;
 use arch::paging::Page;
 use arch::regs::Regs;
+use arch::tsc;
 use arch::tss::Tss;
 
 use collections::{String, Vec};
 use collections::string::ToString;
 
-use core::{mem, usize};
+use core::{cmp, mem, usize};
 
+use common::random;
 use common::time::Duration;
 
 use drivers::pci;
 use drivers::io::{Io, Pio};
 use drivers::ps2::*;
-use drivers::rtc::*;
 use drivers::serial::{self, Serial};
 
 use env::Environment;
+use env::profile::Sample;
 
 use graphics::display;
 
 use network::schemes::{ArpScheme, EthernetScheme, IcmpScheme, IpScheme, TcpScheme, UdpScheme};
 
+use schemes::chan::ChanScheme;
 use schemes::context::ContextScheme;
 use schemes::debug::DebugScheme;
 use schemes::disk::DiskScheme;
@@ -56,8 +59,13 @@ use schemes::env::EnvScheme;
 use schemes::initfs::InitFsScheme;
 use schemes::interrupt::InterruptScheme;
 use schemes::memory::MemoryScheme;
+use schemes::pipe::FifoScheme;
+use schemes::profile::ProfileScheme;
+use schemes::rand::RandScheme;
+use schemes::shm::ShmScheme;
 use schemes::syslog::SyslogScheme;
 use schemes::test::TestScheme;
+use schemes::time::TimeScheme;
 
 use syscall::process::exit;
 use syscall::execute::execute;
@@ -171,6 +179,10 @@ pub mod usb;
 /// structure used on x86-based architectures for holding information about a specific task. See
 /// `Tss` for more information.
 pub static mut TSS_PTR: Option<&'static mut Tss> = None;
+/// The size of the stack double faults are handled on, so that an overflowing kernel stack can
+/// still be reported. This is only done on x86_64: i386 has no interrupt stack table, so an
+/// overflowing kernel stack still resets the machine there.
+const FAULT_STACK_SIZE: usize = 0x10000;
 /// The environment pointer.
 ///
 /// The pointer to the kernel environment, holding the state of the kernel.
@@ -192,7 +204,7 @@ pub fn env() -> &'static Environment {
 ///
 /// This duration defines the PIT interval, which is added to the monotonic clock and the real time
 /// clock, when interrupt 0x20 is received.
-static PIT_DURATION: Duration = Duration {
+pub static PIT_DURATION: Duration = Duration {
     secs: 0,
     nanos: 4500572,
 };
@@ -334,13 +346,21 @@ unsafe fn init(tss_data: usize) {
     }
 
     TSS_PTR = Some(&mut *(tss_data as *mut Tss));
+    if let Some(ref mut tss) = TSS_PTR {
+        let fault_stack = memory::alloc(FAULT_STACK_SIZE);
+        if fault_stack == 0 {
+            panic!("no memory for the double fault stack");
+        }
+        memory::map_logical(fault_stack, FAULT_STACK_SIZE);
+        tss.set_fault_stack(fault_stack + memory::LOGICAL_OFFSET + FAULT_STACK_SIZE - 128);
+    }
     ENV_PTR = Some(&mut *Box::into_raw(Environment::new()));
 
     match ENV_PTR {
         Some(ref mut env) => {
             (&mut *env.contexts.get()).push(Context::root());
 
-            let mut serial = Serial::new(0x3F8, 0x4);
+            let serial = Serial::new();
 
             let mut term_columns;
             let mut term_lines;
@@ -353,11 +373,11 @@ unsafe fn init(tss_data: usize) {
                     term_columns = String::new();
                     term_lines = String::new();
                     //Magic for getting serial size
-                    serial.write("ANSI Terminal Size:\n\x1B[s\x1B[9999;9999f\x1B[6n\x1B[u".as_bytes());
+                    serial.console().write_polled("ANSI Terminal Size:\n\x1B[s\x1B[9999;9999f\x1B[6n\x1B[u".as_bytes());
                     let mut escaped = 0;
                     let mut param = 0;
                     loop {
-                        let c = serial.readb() as char;
+                        let c = serial.console().read_polled() as char;
                         match c {
                             '\0' => break,
                             '\x1B' if escaped == 0 => escaped = 1,
@@ -375,10 +395,10 @@ unsafe fn init(tss_data: usize) {
                             }
                         }
                     }
-                    serial.write(term_columns.as_bytes());
-                    serial.write(", ".as_bytes());
-                    serial.write(term_lines.as_bytes());
-                    serial.write("\n".as_bytes());
+                    serial.console().write_polled(term_columns.as_bytes());
+                    serial.console().write_polled(", ".as_bytes());
+                    serial.console().write_polled(term_lines.as_bytes());
+                    serial.console().write_polled("\n".as_bytes());
                 }
             }
 
@@ -395,10 +415,14 @@ unsafe fn init(tss_data: usize) {
                     & __bss_start as *const u8 as usize, & __bss_end as *const u8 as usize);
 
             if let Some(acpi) = Acpi::new() {
+                (&mut *env.rtc.get()).century = acpi.century();
                 (&mut *env.schemes.get()).push(acpi);
             }
 
-            *env.clock_realtime.get() = Rtc::new().time();
+            random::init();
+            *env.clock_realtime.get() = (&mut *env.rtc.get()).time();
+            // The TSC after waiting for the RTC to tick over depends on the RTC's phase at boot
+            random::add_entropy(tsc::rdtsc() ^ (*env.clock_realtime.get()).secs as u64);
 
             (&mut *env.schemes.get()).push(Ps2::new());
 
@@ -406,13 +430,19 @@ unsafe fn init(tss_data: usize) {
 
             (&mut *env.schemes.get()).push(DebugScheme::new());
             (&mut *env.schemes.get()).push(InitFsScheme::new());
+            (&mut *env.schemes.get()).push(ChanScheme::new());
             (&mut *env.schemes.get()).push(box ContextScheme);
             (&mut *env.schemes.get()).push(box DisplayScheme);
             (&mut *env.schemes.get()).push(box EnvScheme);
+            (&mut *env.schemes.get()).push(FifoScheme::new());
+            (&mut *env.schemes.get()).push(box ProfileScheme);
             (&mut *env.schemes.get()).push(box InterruptScheme);
             (&mut *env.schemes.get()).push(box MemoryScheme);
+            (&mut *env.schemes.get()).push(box RandScheme);
+            (&mut *env.schemes.get()).push(ShmScheme::new());
             (&mut *env.schemes.get()).push(box SyslogScheme);
             (&mut *env.schemes.get()).push(box TestScheme);
+            (&mut *env.schemes.get()).push(box TimeScheme);
 
             //TODO: Do not do this! Find a better way
             let mut disks = Vec::new();
@@ -446,6 +476,16 @@ unsafe fn init(tss_data: usize) {
 
             (&mut *env.contexts.get()).enabled = true;
 
+            if cfg!(kernel_test) {
+                Context::spawn("ktest".into(),
+                               box move || {
+                    syslog_info!("The kernel has finished booting. Running tests");
+                    schemes::test::harness::run_boot();
+                    syslog_critical!("kernel: test: isa-debug-exit is missing, not exiting");
+                });
+                return;
+            }
+
             Context::spawn("kinit".into(),
                            box move || {
                 {
@@ -574,11 +614,39 @@ pub extern "cdecl" fn kernel(interrupt: usize, mut regs: &mut Regs) {
         })
     };
 
+    macro_rules! stack_overflow {
+        () => ({
+            let cr2: usize;
+            unsafe { asm!("mov $0, cr2" : "=r"(cr2) : : : "intel", "volatile") };
+
+            let overflow = {
+                let contexts = unsafe { & *::env().contexts.get() };
+                match contexts.current() {
+                    Ok(context) if context.stack_guard(cr2) => {
+                        syslog_critical!("stack overflow in PID {} ({})", context.pid, context.name);
+                        true
+                    },
+                    _ => false
+                }
+            };
+
+            if overflow {
+                loop {
+                    exit(127);
+                }
+            }
+        })
+    };
+
     // Do not catch init interrupt
     if interrupt < 0xFF {
         unsafe { (&mut *env().interrupts.get())[interrupt as usize] += 1 };
     }
 
+    if interrupt >= 0x20 && interrupt < 0x30 {
+        random::add_interrupt_entropy(interrupt as u8);
+    }
+
     match interrupt {
         0x20 => {
             {
@@ -588,10 +656,42 @@ pub extern "cdecl" fn kernel(interrupt: usize, mut regs: &mut Regs) {
             {
                 let mut clock_realtime = unsafe { &mut *env().clock_realtime.get() };
                 *clock_realtime = *clock_realtime + PIT_DURATION;
+
+                // Slew pending adjustments by at most 500 ppm, so the clock never jumps
+                let mut clock_adjust = unsafe { &mut *env().clock_adjust.get() };
+                if *clock_adjust != 0 {
+                    let max = PIT_DURATION.nanos as i64 / 2000;
+                    let step = cmp::max(-max, cmp::min(max, *clock_adjust));
+                    *clock_realtime = *clock_realtime + Duration::new(0, step as i32);
+                    *clock_adjust -= step;
+                    if *clock_adjust == 0 {
+                        unsafe { &mut *env().rtc.get() }.set_time(*clock_realtime);
+                    }
+                }
             }
+            unsafe { &mut *env().tsc.get() }.tick();
 
+            let mut killed = false;
             if let Ok(mut current) = unsafe { &mut *env().contexts.get() }.current_mut() {
                 current.time += 1;
+                // The privilege level of the interrupted code segment tells where the slice was spent
+                if regs.cs & 3 == 3 {
+                    current.user_time += 1;
+                    killed = current.killed;
+                } else {
+                    current.kernel_time += 1;
+                }
+
+                let mut profile = unsafe { &mut *env().profile.get() };
+                if profile.enabled {
+                    profile.record(Sample::new(current, regs));
+                }
+            }
+
+            // Contexts picked to recover memory exit here if they never make a syscall
+            if killed {
+                Pio::<u8>::new(0x20).write(0x20);
+                exit(OOM_STATUS);
             }
 
             unsafe { context_switch(); }
@@ -614,13 +714,19 @@ pub extern "cdecl" fn kernel(interrupt: usize, mut regs: &mut Regs) {
         0x5 => exception!("Bound range exceeded exception"),
         0x6 => exception!("Invalid opcode exception"),
         0x7 => exception!("Device not available exception"),
-        0x8 => exception_error!("Double fault"),
+        0x8 => {
+            stack_overflow!();
+            exception_error!("Double fault")
+        },
         0x9 => exception!("Coprocessor Segment Overrun"), // legacy
         0xA => exception_error!("Invalid TSS exception"),
         0xB => exception_error!("Segment not present exception"),
         0xC => exception_error!("Stack-segment fault"),
         0xD => exception_error!("General protection fault"),
-        0xE => exception_error!("Page fault"),
+        0xE => {
+            stack_overflow!();
+            exception_error!("Page fault")
+        },
         0x10 => exception!("x87 floating-point exception"),
         0x11 => exception_error!("Alignment check exception"),
         0x12 => exception!("Machine check exception"),
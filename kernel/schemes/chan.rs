@@ -0,0 +1,299 @@
+use alloc::arc::{Arc, Weak};
+use alloc::boxed::Box;
+
+use collections::{String, Vec};
+use collections::borrow::ToOwned;
+
+use core::cell::UnsafeCell;
+use core::cmp;
+
+use fs::{KScheme, Resource, Url, VecResource};
+
+use schemes::pipe::{PipeRead, PipeWrite};
+
+use sync::WaitQueue;
+
+use system::error::{Error, Result, EACCES, EADDRINUSE, EAGAIN, ECONNABORTED, ECONNREFUSED, ENOENT};
+use system::syscall::{MODE_DIR, MODE_FILE, O_CREAT, O_NONBLOCK, Stat};
+
+/// One end of a connection
+///
+/// Each direction is a pipe, so reads block until the other end writes, and writes block while
+/// the other end has not read what was written.
+pub struct ChanResource {
+    path: String,
+    read: Box<Resource>,
+    write: Box<Resource>,
+}
+
+impl Resource for ChanResource {
+    fn dup(&self) -> Result<Box<Resource>> {
+        Ok(box ChanResource {
+            path: self.path.clone(),
+            read: try!(self.read.dup()),
+            write: try!(self.write.dup()),
+        })
+    }
+
+    fn path(&self, buf: &mut [u8]) -> Result<usize> {
+        let path = self.path.as_bytes();
+
+        for (b, p) in buf.iter_mut().zip(path.iter()) {
+            *b = *p;
+        }
+
+        Ok(cmp::min(buf.len(), path.len()))
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.read.read(buf)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.write.write(buf)
+    }
+
+    fn stat(&self, stat: &mut Stat) -> Result<usize> {
+        self.read.stat(stat)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.write.sync()
+    }
+}
+
+/// A listening channel
+struct Listener {
+    name: String,
+    flags: usize,
+    /// Server ends of connections that have not been accepted
+    pending: WaitQueue<ChanResource>,
+    /// The addresses of the open listener resources. The listener is closed once there are none
+    resources: UnsafeCell<Vec<usize>>,
+}
+
+impl Listener {
+    /// Whether every listener resource has been closed
+    fn closed(&self) -> bool {
+        unsafe { & *self.resources.get() }.is_empty()
+    }
+
+    /// Whether the current context has a listener resource for this listener open
+    fn held(&self) -> bool {
+        let contexts = unsafe { & *::env().contexts.get() };
+        let current = match contexts.current() {
+            Ok(current) => current,
+            Err(_) => return false
+        };
+
+        let resources = unsafe { & *self.resources.get() };
+        unsafe { & *current.files.get() }.iter().any(|file| {
+            resources.contains(&(&*file.resource as *const Resource as *const u8 as usize))
+        })
+    }
+
+    /// Accept a connection, blocking until a client connects unless `O_NONBLOCK` is in `flags`.
+    /// Fails with `ECONNABORTED` if the listener is closed while waiting.
+    fn accept(&self, flags: usize) -> Result<ChanResource> {
+        loop {
+            if let Some(connection) = unsafe { self.pending.inner() }.pop_front() {
+                return Ok(connection);
+            }
+
+            if self.closed() {
+                return Err(Error::new(ECONNABORTED));
+            }
+
+            if flags & O_NONBLOCK == O_NONBLOCK {
+                return Err(Error::new(EAGAIN));
+            }
+
+            self.pending.condition.wait("Listener::accept");
+        }
+    }
+}
+
+/// The server side of a channel
+///
+/// The listener is shared by `dup`, so it is kept open across a fork. Connections are accepted
+/// by opening `chan:name/accept`, which only contexts holding the listener may do.
+pub struct ChanListenerResource {
+    listener: Arc<Listener>,
+}
+
+impl ChanListenerResource {
+    fn new(listener: Arc<Listener>) -> Box<Resource> {
+        let resource = box ChanListenerResource {
+            listener: listener,
+        };
+        unsafe { &mut *resource.listener.resources.get() }.push(&*resource as *const ChanListenerResource as usize);
+        resource
+    }
+}
+
+impl Resource for ChanListenerResource {
+    fn dup(&self) -> Result<Box<Resource>> {
+        Ok(ChanListenerResource::new(self.listener.clone()))
+    }
+
+    fn path(&self, buf: &mut [u8]) -> Result<usize> {
+        let path = format!("chan:{}", self.listener.name);
+
+        for (b, p) in buf.iter_mut().zip(path.bytes()) {
+            *b = p;
+        }
+
+        Ok(cmp::min(buf.len(), path.len()))
+    }
+
+    fn stat(&self, stat: &mut Stat) -> Result<usize> {
+        stat.st_mode = MODE_FILE;
+        stat.st_size = unsafe { self.listener.pending.inner() }.len() as u32;
+        Ok(0)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for ChanListenerResource {
+    /// Closing the last listener resource releases the name, refuses the connections that were
+    /// not accepted, and fails the accepts waiting for one
+    fn drop(&mut self) {
+        let address = self as *const ChanListenerResource as usize;
+        unsafe { &mut *self.listener.resources.get() }.retain(|&resource| resource != address);
+
+        if self.listener.closed() {
+            unsafe { self.listener.pending.inner() }.clear();
+            self.listener.pending.condition.notify("ChanListenerResource::drop");
+        }
+    }
+}
+
+/// A scheme for local, connection oriented channels
+///
+/// A server opens `chan:name` with `O_CREAT` to listen, and opens `chan:name/accept` to accept
+/// each connection, blocking until a client connects unless `O_NONBLOCK` is given. Clients open
+/// `chan:name` to connect. The name is released when the last listener resource is closed.
+pub struct ChanScheme {
+    listeners: Vec<Weak<Listener>>,
+}
+
+impl ChanScheme {
+    pub fn new() -> Box<Self> {
+        box ChanScheme {
+            listeners: Vec::new(),
+        }
+    }
+
+    /// Find an open listener. Closed listeners are dropped, even if an accept still holds them.
+    fn find(&mut self, name: &str) -> Option<Arc<Listener>> {
+        self.listeners.retain(|listener| listener.upgrade().map_or(false, |listener| ! listener.closed()));
+
+        for listener in self.listeners.iter() {
+            if let Some(listener) = listener.upgrade() {
+                if listener.name == name {
+                    return Some(listener);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl KScheme for ChanScheme {
+    fn scheme(&self) -> &str {
+        "chan"
+    }
+
+    fn open(&mut self, url: Url, flags: usize) -> Result<Box<Resource>> {
+        let name = url.reference().trim_matches('/');
+
+        if name.is_empty() {
+            let mut list = String::new();
+            for listener in self.listeners.iter() {
+                if let Some(listener) = listener.upgrade() {
+                    if listener.closed() {
+                        continue;
+                    }
+                    if ! list.is_empty() {
+                        list.push('\n');
+                    }
+                    list.push_str(&listener.name);
+                }
+            }
+
+            return Ok(box VecResource::new("chan:".to_owned(), list.into_bytes()));
+        }
+
+        if name.ends_with("/accept") {
+            let listener = match self.find(&name[.. name.len() - "/accept".len()]) {
+                Some(listener) => listener,
+                None => return Err(Error::new(ENOENT))
+            };
+
+            if ! listener.held() {
+                return Err(Error::new(EACCES));
+            }
+
+            return Ok(box try!(listener.accept(flags)));
+        }
+
+        if flags & O_CREAT == O_CREAT {
+            if self.find(name).is_some() {
+                return Err(Error::new(EADDRINUSE));
+            }
+
+            let listener = Arc::new(Listener {
+                name: name.to_owned(),
+                flags: flags,
+                pending: WaitQueue::new(),
+                resources: UnsafeCell::new(Vec::new()),
+            });
+            self.listeners.push(Arc::downgrade(&listener));
+
+            Ok(ChanListenerResource::new(listener))
+        } else {
+            let listener = match self.find(name) {
+                Some(listener) => listener,
+                None => return Err(Error::new(ECONNREFUSED))
+            };
+
+            let path = format!("chan:{}", name);
+
+            let client_read = PipeRead::new(flags);
+            let server_write = PipeWrite::new(&client_read, listener.flags);
+            let server_read = PipeRead::new(listener.flags);
+            let client_write = PipeWrite::new(&server_read, flags);
+
+            listener.pending.send(ChanResource {
+                path: path.clone(),
+                read: box server_read,
+                write: box server_write,
+            }, "ChanScheme::open");
+
+            Ok(box ChanResource {
+                path: path,
+                read: box client_read,
+                write: box client_write,
+            })
+        }
+    }
+
+    fn stat(&mut self, url: Url, stat: &mut Stat) -> Result<()> {
+        let name = url.reference().trim_matches('/');
+        if name.is_empty() {
+            stat.st_mode = MODE_DIR;
+            return Ok(());
+        }
+
+        if self.find(name).is_some() {
+            stat.st_mode = MODE_FILE;
+            Ok(())
+        } else {
+            Err(Error::new(ENOENT))
+        }
+    }
+}
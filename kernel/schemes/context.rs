@@ -2,20 +2,107 @@ use alloc::boxed::Box;
 
 use collections::string::{String, ToString};
 
-use arch::context;
+use arch::context::{self, Context, ContextMemory};
 
 use fs::{KScheme, Resource, Url, VecResource};
 
-use system::error::Result;
+use syscall;
 
-pub struct ContextScheme;
+use system::error::{Error, Result, ENOENT};
 
-impl KScheme for ContextScheme {
-    fn scheme(&self) -> &str {
-        "context"
+/// The files in each process directory
+const PROCESS_FILES: [&'static str; 7] = ["cmdline", "cwd", "env", "fds", "maps", "status", "syscall"];
+
+/// One `key: value` line per field
+fn status(context: &Context) -> String {
+    let state = if context.exited {
+        "exited"
+    } else if context.wake.is_some() {
+        "sleeping"
+    } else if context.blocked > 0 {
+        "blocked"
+    } else {
+        "running"
+    };
+
+    let mut string = String::new();
+    string.push_str(&format!("pid: {}\n", context.pid));
+    string.push_str(&format!("ppid: {}\n", context.ppid));
+    string.push_str(&format!("name: {}\n", context.name));
+    string.push_str(&format!("state: {}\n", state));
+    string.push_str(&format!("kernel: {}\n", context.stack.is_none()));
+    string.push_str(&format!("vfork: {}\n", context.vfork.is_some()));
+    string.push_str(&format!("supervised: {}\n", context.supervised));
+    string.push_str(&format!("iopl: {}\n", context.iopl));
+    string.push_str(&format!("switch: {}\n", context.switch));
+    string.push_str(&format!("time: {}\n", context.time));
+    string.push_str(&format!("user_time: {}\n", context.user_time));
+    string.push_str(&format!("kernel_time: {}\n", context.kernel_time));
+    string.push_str(&format!("memory: {}\n", context.memory_usage()));
+    string.push_str(&format!("peak_memory: {}\n", context.peak_memory));
+    string.push_str(&format!("fds: {}\n", unsafe { (*context.files.get()).len() }));
+    string
+}
+
+/// One line per memory region: start and end address in hex, permissions, and the zone
+fn maps(context: &Context) -> String {
+    fn push_map(string: &mut String, mem: &ContextMemory, zone: &str) {
+        string.push_str(&format!("{:08X}-{:08X} r{}{}{} {}\n",
+                                 mem.virtual_address,
+                                 mem.virtual_address + mem.virtual_size,
+                                 if mem.writeable { 'w' } else { '-' },
+                                 if mem.executable { 'x' } else { '-' },
+                                 if mem.shared.is_some() { 's' } else { 'p' },
+                                 zone));
     }
 
-    fn open(&mut self, _: Url, _: usize) -> Result<Box<Resource>> {
+    let mut string = String::new();
+    for &(zone, name) in [(&context.image, "image"), (&context.heap, "heap"), (&context.mmap, "mmap")].iter() {
+        for mem in unsafe { (*zone.get()).memory.iter() } {
+            push_map(&mut string, mem, name);
+        }
+    }
+    if let Some(ref stack) = context.stack {
+        push_map(&mut string, stack, "stack");
+    }
+    string
+}
+
+/// One line per file descriptor: the number, then the path it was opened with
+fn fds(context: &Context) -> String {
+    let mut string = String::new();
+    for file in unsafe { (*context.files.get()).iter() } {
+        string.push_str(&format!("{} {}\n", file.fd, file.path));
+    }
+    string
+}
+
+/// The current system call: its name, number and arguments in hex, then the instruction pointer,
+/// or `none`
+fn current_syscall(context: &Context) -> String {
+    match context.current_syscall {
+        Some((ip, number, b, c, d)) => format!("{} {} {:X} {:X} {:X} @ {:X}\n", syscall::name(number), number, b, c, d, ip),
+        None => "none\n".to_string()
+    }
+}
+
+/// Process information
+///
+/// `context:` is a table of every context. `context:/<pid>/` lists the files describing a
+/// context, which can also be reached through `context:/self/`:
+///
+/// - `cmdline`: the arguments, each followed by a NUL byte
+/// - `cwd`: the working directory
+/// - `env`: the environment, as `NAME=value` entries each followed by a NUL byte
+/// - `fds`: the open files, one `<fd> <path>` line each
+/// - `maps`: the memory regions, one `<start>-<end> <perms> <zone>` line each
+/// - `status`: one `<key>: <value>` line per field
+/// - `syscall`: the system call being handled
+pub struct ContextScheme;
+
+impl ContextScheme {
+    /// The table of every context
+    fn table(&self) -> String {
         let mut string = format!("{:<6}{:<6}{:<8}{:<8}{:<8}{:<6}{:<6}{:<6}{}\n",
                                  "PID",
                                  "PPID",
@@ -85,6 +172,70 @@ impl KScheme for ContextScheme {
             }
         }
 
-        Ok(box VecResource::new("context:".to_string(), string.into_bytes()))
+        string
+    }
+}
+
+impl KScheme for ContextScheme {
+    fn scheme(&self) -> &str {
+        "context"
+    }
+
+    fn open(&mut self, url: Url, _: usize) -> Result<Box<Resource>> {
+        let path = url.reference().trim_matches('/');
+        if path.is_empty() {
+            return Ok(box VecResource::new("context:".to_string(), self.table().into_bytes()));
+        }
+
+        let mut parts = path.splitn(2, '/');
+        let pid_str = parts.next().unwrap_or("");
+        let file = parts.next().unwrap_or("").trim_matches('/');
+
+        let contexts = unsafe { & *::env().contexts.get() };
+        let context = if pid_str == "self" {
+            try!(contexts.current())
+        } else {
+            match pid_str.parse::<usize>() {
+                Ok(pid) => try!(contexts.find(pid)),
+                Err(_) => return Err(Error::new(ENOENT))
+            }
+        };
+
+        let data = match file {
+            "" => {
+                let mut string = String::new();
+                for name in PROCESS_FILES.iter() {
+                    string.push_str(name);
+                    string.push('\n');
+                }
+                string.into_bytes()
+            },
+            "cmdline" => {
+                let mut string = String::new();
+                for arg in context.args.iter() {
+                    string.push_str(arg.trim_right_matches('\0'));
+                    string.push('\0');
+                }
+                string.into_bytes()
+            },
+            "cwd" => unsafe { (*context.cwd.get()).clone() }.into_bytes(),
+            "env" => {
+                let mut string = String::new();
+                for var in unsafe { (*context.env_vars.get()).iter() } {
+                    string.push_str(var.name());
+                    string.push('=');
+                    string.push_str(var.value());
+                    string.push('\0');
+                }
+                string.into_bytes()
+            },
+            "fds" => fds(context).into_bytes(),
+            "maps" => maps(context).into_bytes(),
+            "status" => status(context).into_bytes(),
+            "syscall" => current_syscall(context).into_bytes(),
+            _ => return Err(Error::new(ENOENT))
+        };
+
+        Ok(box VecResource::new(format!("context:/{}/{}", context.pid, file), data))
     }
 }
@@ -11,7 +11,7 @@ use fs::{KScheme, Resource, ResourceSeek, Url, VecResource};
 
 use syscall::{MODE_DIR, MODE_FILE, Stat};
 
-use system::error::{Error, Result, ENOENT};
+use system::error::{Error, Result, EINVAL, ENOENT};
 
 /// A disk resource
 pub struct DiskResource {
@@ -50,6 +50,22 @@ impl Resource for DiskResource {
         Ok(count)
     }
 
+    /// Read from a sector aligned offset, failing with `EINVAL` otherwise
+    fn pread(&mut self, buf: &mut [u8], offset: usize) -> Result<usize> {
+        if offset % 512 != 0 {
+            return Err(Error::new(EINVAL));
+        }
+        unsafe { &mut *self.disk.get() }.read(offset as u64/512, buf)
+    }
+
+    /// Write to a sector aligned offset, failing with `EINVAL` otherwise
+    fn pwrite(&mut self, buf: &[u8], offset: usize) -> Result<usize> {
+        if offset % 512 != 0 {
+            return Err(Error::new(EINVAL));
+        }
+        unsafe { &mut *self.disk.get() }.write(offset as u64/512, buf)
+    }
+
     fn seek(&mut self, pos: ResourceSeek) -> Result<usize> {
         let size = unsafe { & *self.disk.get() }.size();
         match pos {
@@ -1,6 +1,7 @@
 use alloc::boxed::Box;
 
-use arch::memory;
+use arch::memory::{self, CLUSTER_SIZE, MAX_ORDER, ZONE_HIGH, ZONE_LOW};
+use arch::slab::CACHES;
 
 use collections::string::ToString;
 
@@ -9,6 +10,10 @@ use fs::{KScheme, Resource, Url, VecResource};
 use system::error::Result;
 
 /// A memory scheme
+///
+/// Reports the used and free memory, then the free blocks in low and high memory and the
+/// allocations of each order of the frame allocator, then the slabs and objects of each slab
+/// cache.
 pub struct MemoryScheme;
 
 impl KScheme for MemoryScheme {
@@ -17,9 +22,30 @@ impl KScheme for MemoryScheme {
     }
 
     fn open(&mut self, _: Url, _: usize) -> Result<Box<Resource>> {
-        let string = format!("Memory Used: {} KB\nMemory Free: {} KB\n",
-                             memory::memory_used() / 1024,
-                             memory::memory_free() / 1024);
+        let mut string = format!("Memory Used: {} KB\nMemory Free: {} KB\n",
+                                 memory::memory_used() / 1024,
+                                 memory::memory_free() / 1024);
+
+        string.push_str(&format!("\n{:<6}{:<12}{:<8}{:<8}{}\n", "ORDER", "BLOCK", "LOW", "HIGH", "USED"));
+        for order in 0..MAX_ORDER + 1 {
+            string.push_str(&format!("{:<6}{:<12}{:<8}{:<8}{}\n",
+                                     order,
+                                     format!("{} KB", (CLUSTER_SIZE << order) / 1024),
+                                     memory::free_blocks(ZONE_LOW, order),
+                                     memory::free_blocks(ZONE_HIGH, order),
+                                     memory::used_blocks(order)));
+        }
+
+        string.push_str(&format!("\n{:<6}{:<8}{:<8}{:<8}{}\n", "CACHE", "SLAB", "SLABS", "USED", "TOTAL"));
+        for cache in unsafe { CACHES.iter() } {
+            string.push_str(&format!("{:<6}{:<8}{:<8}{:<8}{}\n",
+                                     cache.size,
+                                     format!("{} KB", cache.slab_size() / 1024),
+                                     cache.slabs,
+                                     cache.used,
+                                     cache.slabs * cache.slab_objects()));
+        }
+
         Ok(box VecResource::new("memory:".to_string(), string.into_bytes()))
     }
 }
@@ -1,3 +1,5 @@
+/// Local channels
+pub mod chan;
 /// Context scheme
 pub mod context;
 /// Debug scheme
@@ -16,7 +18,15 @@ pub mod interrupt;
 pub mod memory;
 /// Pipes
 pub mod pipe;
+/// Profiler samples
+pub mod profile;
+/// Random numbers
+pub mod rand;
+/// Shared memory
+pub mod shm;
 /// Logging scheme
 pub mod syslog;
 /// Tests
 pub mod test;
+/// Timers
+pub mod time;
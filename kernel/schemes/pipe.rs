@@ -1,36 +1,101 @@
 use alloc::arc::{Arc, Weak};
 use alloc::boxed::Box;
 
+use collections::{String, Vec};
+use collections::borrow::ToOwned;
+use collections::vec_deque::VecDeque;
+
+use core::cell::{Cell, UnsafeCell};
 use core::cmp;
 
-use fs::Resource;
+use fs::{KScheme, Resource, Url, VecResource};
+
+use sync::WaitCondition;
+
+use system::error::{Error, Result, EAGAIN, EEXIST, ENOENT, ENXIO, EPIPE};
+use system::syscall::{MODE_DIR, MODE_FILE, O_CREAT, O_EXCL, O_NONBLOCK, O_RDWR, O_WRONLY, Stat};
 
-use sync::WaitQueue;
+/// The number of bytes a pipe can hold before writers block
+pub const PIPE_CAPACITY: usize = 65536;
+
+/// The buffer and the state of both ends of a pipe
+struct Pipe {
+    queue: UnsafeCell<VecDeque<u8>>,
+    /// Number of open read ends
+    readers: Cell<usize>,
+    /// Number of open write ends
+    writers: Cell<usize>,
+    /// Notified when data is written, or a write end is opened or closed
+    read_condition: WaitCondition,
+    /// Notified when data is read, or a read end is opened or closed
+    write_condition: WaitCondition,
+}
 
-use system::error::{Error, Result, EPIPE};
+impl Pipe {
+    fn new() -> Pipe {
+        Pipe {
+            queue: UnsafeCell::new(VecDeque::new()),
+            readers: Cell::new(0),
+            writers: Cell::new(0),
+            read_condition: WaitCondition::new(),
+            write_condition: WaitCondition::new(),
+        }
+    }
+
+    unsafe fn queue<'a>(&'a self) -> &'a mut VecDeque<u8> {
+        &mut *self.queue.get()
+    }
+
+    fn open_read(&self) {
+        self.readers.set(self.readers.get() + 1);
+        self.write_condition.notify("Pipe::open_read");
+    }
+
+    fn close_read(&self) {
+        self.readers.set(self.readers.get() - 1);
+        self.write_condition.notify("Pipe::close_read");
+    }
+
+    fn open_write(&self) {
+        self.writers.set(self.writers.get() + 1);
+        self.read_condition.notify("Pipe::open_write");
+    }
+
+    fn close_write(&self) {
+        self.writers.set(self.writers.get() - 1);
+        self.read_condition.notify("Pipe::close_write");
+    }
+}
 
 /// Read side of a pipe
 pub struct PipeRead {
-    vec: Arc<WaitQueue<u8>>
+    pipe: Arc<Pipe>,
+    path: String,
+    flags: usize,
 }
 
 impl PipeRead {
-    pub fn new() -> Self {
+    pub fn new(flags: usize) -> Self {
+        Self::from_pipe(Arc::new(Pipe::new()), "pipe:r".to_owned(), flags)
+    }
+
+    fn from_pipe(pipe: Arc<Pipe>, path: String, flags: usize) -> Self {
+        pipe.open_read();
         PipeRead {
-            vec: Arc::new(WaitQueue::new())
+            pipe: pipe,
+            path: path,
+            flags: flags,
         }
     }
 }
 
 impl Resource for PipeRead {
     fn dup(&self) -> Result<Box<Resource>> {
-        Ok(box PipeRead {
-            vec: self.vec.clone(),
-        })
+        Ok(box PipeRead::from_pipe(self.pipe.clone(), self.path.clone(), self.flags))
     }
 
     fn path(&self, buf: &mut [u8]) -> Result<usize> {
-        let path = b"pipe:r";
+        let path = self.path.as_bytes();
 
         for (b, p) in buf.iter_mut().zip(path.iter()) {
             *b = *p;
@@ -40,52 +105,90 @@ impl Resource for PipeRead {
     }
 
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        if Arc::weak_count(&self.vec) == 0 && unsafe { self.vec.inner() }.is_empty() {
-            Ok(0)
-        } else {
-            if !buf.is_empty() {
-                buf[0] = self.vec.receive("PipeRead::read");
-            }
+        if buf.is_empty() {
+            return Ok(0);
+        }
 
-            let mut i = 1;
+        loop {
+            {
+                let queue = unsafe { self.pipe.queue() };
+                if ! queue.is_empty() {
+                    let mut i = 0;
+                    while i < buf.len() {
+                        match queue.pop_front() {
+                            Some(b) => {
+                                buf[i] = b;
+                                i += 1;
+                            },
+                            None => break
+                        }
+                    }
 
-            while i < buf.len() {
-                match unsafe { self.vec.inner() }.pop_front() {
-                    Some(b) => {
-                        buf[i] = b;
-                        i += 1;
-                    },
-                    None => break
+                    self.pipe.write_condition.notify("PipeRead::read");
+                    return Ok(i);
                 }
             }
 
-            Ok(i)
+            if self.pipe.writers.get() == 0 {
+                return Ok(0);
+            }
+
+            if self.flags & O_NONBLOCK == O_NONBLOCK {
+                return Err(Error::new(EAGAIN));
+            }
+
+            self.pipe.read_condition.wait("PipeRead::read");
         }
     }
+
+    fn stat(&self, stat: &mut Stat) -> Result<usize> {
+        stat.st_mode = MODE_FILE;
+        stat.st_size = unsafe { self.pipe.queue() }.len() as u32;
+        Ok(0)
+    }
 }
 
-/// Read side of a pipe
+impl Drop for PipeRead {
+    fn drop(&mut self) {
+        self.pipe.close_read();
+    }
+}
+
+/// Write side of a pipe
+///
+/// Writes block while the pipe holds `PIPE_CAPACITY` bytes, and fail with `EPIPE` once every
+/// read side is closed.
 pub struct PipeWrite {
-    vec: Weak<WaitQueue<u8>>,
+    pipe: Weak<Pipe>,
+    path: String,
+    flags: usize,
 }
 
 impl PipeWrite {
-    pub fn new(read: &PipeRead) -> Self {
+    pub fn new(read: &PipeRead, flags: usize) -> Self {
+        Self::from_pipe(&read.pipe, "pipe:w".to_owned(), flags)
+    }
+
+    fn from_pipe(pipe: &Arc<Pipe>, path: String, flags: usize) -> Self {
+        pipe.open_write();
         PipeWrite {
-            vec: Arc::downgrade(&read.vec),
+            pipe: Arc::downgrade(pipe),
+            path: path,
+            flags: flags,
         }
     }
 }
 
 impl Resource for PipeWrite {
     fn dup(&self) -> Result<Box<Resource>> {
-        Ok(box PipeWrite {
-            vec: self.vec.clone(),
-        })
+        match self.pipe.upgrade() {
+            Some(pipe) => Ok(box PipeWrite::from_pipe(&pipe, self.path.clone(), self.flags)),
+            None => Err(Error::new(EPIPE))
+        }
     }
 
     fn path(&self, buf: &mut [u8]) -> Result<usize> {
-        let path = b"pipe:w";
+        let path = self.path.as_bytes();
 
         for (b, p) in buf.iter_mut().zip(path.iter()) {
             *b = *p;
@@ -95,20 +198,256 @@ impl Resource for PipeWrite {
     }
 
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        match self.vec.upgrade() {
-            Some(vec) => {
-                for &b in buf.iter() {
-                    vec.send(b, "PipeWrite::write");
+        let mut i = 0;
+        loop {
+            let pipe = match self.pipe.upgrade() {
+                Some(pipe) => pipe,
+                None => return if i > 0 {
+                    Ok(i)
+                } else {
+                    Err(Error::new(EPIPE))
                 }
+            };
 
-                Ok(buf.len())
-            },
+            if pipe.readers.get() == 0 {
+                return if i > 0 {
+                    Ok(i)
+                } else {
+                    Err(Error::new(EPIPE))
+                };
+            }
+
+            {
+                let queue = unsafe { pipe.queue() };
+                while i < buf.len() && queue.len() < PIPE_CAPACITY {
+                    queue.push_back(buf[i]);
+                    i += 1;
+                }
+            }
+
+            pipe.read_condition.notify("PipeWrite::write");
+
+            if i >= buf.len() {
+                return Ok(i);
+            }
+
+            if self.flags & O_NONBLOCK == O_NONBLOCK {
+                return if i > 0 {
+                    Ok(i)
+                } else {
+                    Err(Error::new(EAGAIN))
+                };
+            }
+
+            pipe.write_condition.wait("PipeWrite::write");
+        }
+    }
+
+    fn stat(&self, stat: &mut Stat) -> Result<usize> {
+        stat.st_mode = MODE_FILE;
+        if let Some(pipe) = self.pipe.upgrade() {
+            stat.st_size = unsafe { pipe.queue() }.len() as u32;
+        }
+        Ok(0)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        loop {
+            let pipe = match self.pipe.upgrade() {
+                Some(pipe) => pipe,
+                None => return Ok(())
+            };
+
+            if pipe.readers.get() == 0 || unsafe { pipe.queue() }.is_empty() {
+                return Ok(());
+            }
+
+            pipe.write_condition.wait("PipeWrite::sync");
+        }
+    }
+}
+
+impl Drop for PipeWrite {
+    fn drop(&mut self) {
+        if let Some(pipe) = self.pipe.upgrade() {
+            pipe.close_write();
+        }
+    }
+}
+
+/// Both sides of a named pipe, opened with `O_RDWR`
+///
+/// It counts as a reader and a writer, so opening it never blocks, and reads block instead of
+/// returning 0 when the pipe is empty.
+pub struct PipeReadWrite {
+    read: PipeRead,
+    write: PipeWrite,
+}
+
+impl Resource for PipeReadWrite {
+    fn dup(&self) -> Result<Box<Resource>> {
+        match self.write.pipe.upgrade() {
+            Some(pipe) => Ok(box PipeReadWrite {
+                read: PipeRead::from_pipe(self.read.pipe.clone(), self.read.path.clone(), self.read.flags),
+                write: PipeWrite::from_pipe(&pipe, self.write.path.clone(), self.write.flags),
+            }),
             None => Err(Error::new(EPIPE))
         }
     }
 
+    fn path(&self, buf: &mut [u8]) -> Result<usize> {
+        self.read.path(buf)
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.read.read(buf)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.write.write(buf)
+    }
+
+    fn stat(&self, stat: &mut Stat) -> Result<usize> {
+        self.read.stat(stat)
+    }
+
     fn sync(&mut self) -> Result<()> {
-        //TODO: Wait until empty
+        self.write.sync()
+    }
+}
+
+/// A named pipe
+struct Fifo {
+    name: String,
+    pipe: Arc<Pipe>,
+}
+
+/// A scheme for named pipes
+///
+/// Opening `fifo:name` with `O_CREAT` creates the pipe. Opening it for reading blocks until a
+/// writer opens it, and opening it for writing blocks until a reader opens it, unless `O_NONBLOCK`
+/// is given. Opening it with `O_RDWR` gives both sides, and does not block. The pipe keeps its
+/// name until it is unlinked.
+pub struct FifoScheme {
+    fifos: Vec<Fifo>,
+}
+
+impl FifoScheme {
+    pub fn new() -> Box<Self> {
+        box FifoScheme {
+            fifos: Vec::new(),
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<Arc<Pipe>> {
+        for fifo in self.fifos.iter() {
+            if fifo.name == name {
+                return Some(fifo.pipe.clone());
+            }
+        }
+
+        None
+    }
+}
+
+impl KScheme for FifoScheme {
+    fn scheme(&self) -> &str {
+        "fifo"
+    }
+
+    fn open(&mut self, url: Url, flags: usize) -> Result<Box<Resource>> {
+        let name = url.reference().trim_matches('/');
+
+        if name.is_empty() {
+            let mut list = String::new();
+            for fifo in self.fifos.iter() {
+                if ! list.is_empty() {
+                    list.push('\n');
+                }
+                list.push_str(&fifo.name);
+            }
+
+            return Ok(box VecResource::new("fifo:".to_owned(), list.into_bytes()));
+        }
+
+        let pipe = match self.find(name) {
+            Some(pipe) => {
+                if flags & O_CREAT == O_CREAT && flags & O_EXCL == O_EXCL {
+                    return Err(Error::new(EEXIST));
+                }
+                pipe
+            },
+            None => {
+                if flags & O_CREAT != O_CREAT {
+                    return Err(Error::new(ENOENT));
+                }
+
+                let pipe = Arc::new(Pipe::new());
+                self.fifos.push(Fifo {
+                    name: name.to_owned(),
+                    pipe: pipe.clone(),
+                });
+                pipe
+            }
+        };
+
+        let path = format!("fifo:{}", name);
+
+        if flags & O_RDWR == O_RDWR {
+            let read = PipeRead::from_pipe(pipe.clone(), path.clone(), flags);
+            let write = PipeWrite::from_pipe(&pipe, path, flags);
+            Ok(box PipeReadWrite {
+                read: read,
+                write: write,
+            })
+        } else if flags & (O_WRONLY | O_RDWR) == O_WRONLY {
+            if pipe.readers.get() == 0 && flags & O_NONBLOCK == O_NONBLOCK {
+                return Err(Error::new(ENXIO));
+            }
+
+            let write = PipeWrite::from_pipe(&pipe, path, flags);
+            while pipe.readers.get() == 0 {
+                pipe.write_condition.wait("FifoScheme::open");
+            }
+
+            Ok(box write)
+        } else {
+            let read = PipeRead::from_pipe(pipe.clone(), path, flags);
+            if flags & O_NONBLOCK != O_NONBLOCK {
+                while pipe.writers.get() == 0 {
+                    pipe.read_condition.wait("FifoScheme::open");
+                }
+            }
+
+            Ok(box read)
+        }
+    }
+
+    fn stat(&mut self, url: Url, stat: &mut Stat) -> Result<()> {
+        let name = url.reference().trim_matches('/');
+        if name.is_empty() {
+            stat.st_mode = MODE_DIR;
+            return Ok(());
+        }
+
+        match self.find(name) {
+            Some(pipe) => {
+                stat.st_mode = MODE_FILE;
+                stat.st_size = unsafe { pipe.queue() }.len() as u32;
+                Ok(())
+            },
+            None => Err(Error::new(ENOENT))
+        }
+    }
+
+    fn unlink(&mut self, url: Url) -> Result<()> {
+        let name = url.reference().trim_matches('/');
+        if self.find(name).is_none() {
+            return Err(Error::new(ENOENT));
+        }
+
+        self.fifos.retain(|fifo| fifo.name != name);
+
         Ok(())
     }
 }
@@ -0,0 +1,110 @@
+use alloc::boxed::Box;
+
+use collections::string::{String, ToString};
+use collections::vec::Vec;
+
+use core::cmp;
+
+use fs::{KScheme, Resource, Url};
+
+use system::error::{Error, Result, EINVAL, ENOENT};
+use system::syscall::{MODE_FILE, Stat};
+
+/// The sampling profiler scheme.
+///
+/// Reading `profile:` returns one line for each recorded sample, oldest first: the PID, `u` or `k`
+/// for user or kernel, the interrupted instruction pointer and the return addresses found by
+/// following frame pointers, innermost first, all in hex. Reading returns 0 after the newest.
+///
+/// Writing `start`, `stop` or `clear` starts recording on every timer interrupt, stops it, or
+/// drops the recorded samples.
+pub struct ProfileScheme;
+
+impl KScheme for ProfileScheme {
+    fn scheme(&self) -> &str {
+        "profile"
+    }
+
+    fn open(&mut self, url: Url, _: usize) -> Result<Box<Resource>> {
+        if ! url.reference().trim_matches('/').is_empty() {
+            return Err(Error::new(ENOENT));
+        }
+
+        Ok(Box::new(ProfileResource {
+            seq: 0,
+            buffer: Vec::new(),
+        }))
+    }
+}
+
+/// The profiler samples resource.
+pub struct ProfileResource {
+    /// Sequence number of the next sample to read
+    seq: usize,
+    /// The part of the current sample that has not been read
+    buffer: Vec<u8>,
+}
+
+impl Resource for ProfileResource {
+    fn dup(&self) -> Result<Box<Resource>> {
+        Ok(Box::new(ProfileResource {
+            seq: self.seq,
+            buffer: self.buffer.clone(),
+        }))
+    }
+
+    fn path(&self, buf: &mut [u8]) -> Result<usize> {
+        let path = b"profile:";
+
+        for (b, p) in buf.iter_mut().zip(path.iter()) {
+            *b = *p;
+        }
+
+        Ok(cmp::min(buf.len(), path.len()))
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if self.buffer.is_empty() {
+            let profile = unsafe { & *::env().profile.get() };
+            match profile.find(self.seq) {
+                Some(sample) => {
+                    self.seq = sample.seq + 1;
+                    self.buffer = sample.to_string().into_bytes();
+                },
+                None => return Ok(0)
+            }
+        }
+
+        let count = cmp::min(buf.len(), self.buffer.len());
+        for (b, p) in buf.iter_mut().zip(self.buffer.drain(.. count)) {
+            *b = p;
+        }
+        Ok(count)
+    }
+
+    /// Controls recording with `start`, `stop` or `clear`
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let profile = unsafe { &mut *::env().profile.get() };
+        match String::from_utf8_lossy(buf).trim() {
+            "start" => profile.start(),
+            "stop" => profile.stop(),
+            "clear" => {
+                profile.clear();
+                self.seq = profile.next_seq;
+                self.buffer.clear();
+            },
+            _ => return Err(Error::new(EINVAL))
+        }
+
+        Ok(buf.len())
+    }
+
+    fn stat(&self, stat: &mut Stat) -> Result<usize> {
+        stat.st_mode = MODE_FILE;
+        Ok(0)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
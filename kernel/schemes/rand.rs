@@ -0,0 +1,70 @@
+use alloc::boxed::Box;
+
+use common::random;
+
+use core::cmp;
+
+use fs::{KScheme, Resource, Url};
+
+use system::error::Result;
+use system::syscall::{MODE_FILE, Stat};
+
+/// A random number resource
+///
+/// Reads return bytes from the kernel's cryptographically secure generator and never block.
+/// Writes are mixed into the entropy pool.
+pub struct RandResource;
+
+impl Resource for RandResource {
+    fn dup(&self) -> Result<Box<Resource>> {
+        Ok(box RandResource)
+    }
+
+    fn path(&self, buf: &mut [u8]) -> Result<usize> {
+        let path = b"rand:";
+
+        for (b, p) in buf.iter_mut().zip(path.iter()) {
+            *b = *p;
+        }
+
+        Ok(cmp::min(buf.len(), path.len()))
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        random::fill(buf);
+        Ok(buf.len())
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        for chunk in buf.chunks(8) {
+            let mut value = 0;
+            for &b in chunk.iter() {
+                value = value << 8 | b as u64;
+            }
+            random::add_entropy(value);
+        }
+        Ok(buf.len())
+    }
+
+    fn stat(&self, stat: &mut Stat) -> Result<usize> {
+        stat.st_mode = MODE_FILE;
+        Ok(0)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A scheme for random numbers
+pub struct RandScheme;
+
+impl KScheme for RandScheme {
+    fn scheme(&self) -> &str {
+        "rand"
+    }
+
+    fn open(&mut self, _: Url, _: usize) -> Result<Box<Resource>> {
+        Ok(box RandResource)
+    }
+}
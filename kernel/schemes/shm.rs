@@ -0,0 +1,286 @@
+use alloc::arc::{Arc, Weak};
+use alloc::boxed::Box;
+
+use arch::context::SharedMemory;
+
+use collections::{String, Vec};
+use collections::borrow::ToOwned;
+
+use core::cell::UnsafeCell;
+use core::{cmp, ptr};
+
+use fs::{KScheme, Resource, ResourceSeek, Url, VecResource};
+
+use system::error::{Error, Result, EACCES, EBUSY, EEXIST, EINVAL, ENOENT};
+use system::syscall::{MODE_DIR, MODE_FILE, O_CREAT, O_EXCL, O_RDWR, O_TRUNC, O_WRONLY, Stat};
+
+/// A named shared memory region
+struct ShmRegion {
+    name: String,
+    memory: UnsafeCell<Arc<SharedMemory>>,
+}
+
+impl ShmRegion {
+    fn memory(&self) -> &Arc<SharedMemory> {
+        unsafe { &*self.memory.get() }
+    }
+
+    /// Resize the region, keeping its contents
+    /// Returns `EBUSY` if the region is mapped, as the mappings would be left pointing at freed memory.
+    fn resize(&self, size: usize) -> Result<()> {
+        let memory = unsafe { &mut *self.memory.get() };
+        if size == memory.size {
+            return Ok(());
+        }
+
+        if Arc::strong_count(memory) > 1 {
+            return Err(Error::new(EBUSY));
+        }
+
+        let new_memory = try!(SharedMemory::new(size));
+        unsafe {
+            ::memcpy(new_memory.physical_address as *mut u8,
+                     memory.physical_address as *const u8,
+                     cmp::min(memory.size, size));
+        }
+        *memory = Arc::new(new_memory);
+
+        Ok(())
+    }
+}
+
+/// A shared memory resource
+///
+/// The region is sized with `ftruncate`, and can then be mapped with `fmap` or accessed with
+/// `read` and `write`. It is freed once it is no longer open or mapped anywhere.
+pub struct ShmResource {
+    region: Arc<ShmRegion>,
+    flags: usize,
+    seek: usize,
+}
+
+impl ShmResource {
+    fn writeable(&self) -> bool {
+        self.flags & (O_WRONLY | O_RDWR) != 0
+    }
+}
+
+impl Resource for ShmResource {
+    fn dup(&self) -> Result<Box<Resource>> {
+        Ok(box ShmResource {
+            region: self.region.clone(),
+            flags: self.flags,
+            seek: self.seek,
+        })
+    }
+
+    fn path(&self, buf: &mut [u8]) -> Result<usize> {
+        let path = format!("shm:{}", self.region.name);
+
+        for (b, p) in buf.iter_mut().zip(path.bytes()) {
+            *b = p;
+        }
+
+        Ok(cmp::min(buf.len(), path.len()))
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let seek = self.seek;
+        let count = try!(self.pread(buf, seek));
+        self.seek += count;
+        Ok(count)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let seek = self.seek;
+        let count = try!(self.pwrite(buf, seek));
+        self.seek += count;
+        Ok(count)
+    }
+
+    fn pread(&mut self, buf: &mut [u8], offset: usize) -> Result<usize> {
+        let memory = self.region.memory();
+        if offset >= memory.size {
+            return Ok(0);
+        }
+
+        let count = cmp::min(buf.len(), memory.size - offset);
+        unsafe { ptr::copy((memory.physical_address + offset) as *const u8, buf.as_mut_ptr(), count) };
+        Ok(count)
+    }
+
+    fn pwrite(&mut self, buf: &[u8], offset: usize) -> Result<usize> {
+        if ! self.writeable() {
+            return Err(Error::new(EACCES));
+        }
+
+        let memory = self.region.memory();
+        if offset >= memory.size {
+            return Ok(0);
+        }
+
+        let count = cmp::min(buf.len(), memory.size - offset);
+        unsafe { ptr::copy(buf.as_ptr(), (memory.physical_address + offset) as *mut u8, count) };
+        Ok(count)
+    }
+
+    fn seek(&mut self, pos: ResourceSeek) -> Result<usize> {
+        let size = self.region.memory().size;
+        let seek = match pos {
+            ResourceSeek::Start(offset) => offset as isize,
+            ResourceSeek::Current(offset) => self.seek as isize + offset,
+            ResourceSeek::End(offset) => size as isize + offset,
+        };
+
+        if seek < 0 {
+            return Err(Error::new(EINVAL));
+        }
+
+        self.seek = seek as usize;
+        Ok(self.seek)
+    }
+
+    fn stat(&self, stat: &mut Stat) -> Result<usize> {
+        stat.st_mode = MODE_FILE;
+        stat.st_size = self.region.memory().size as u32;
+        Ok(0)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn truncate(&mut self, len: usize) -> Result<()> {
+        if ! self.writeable() {
+            return Err(Error::new(EACCES));
+        }
+
+        self.region.resize(len)
+    }
+
+    fn map(&mut self, writeable: bool) -> Result<Arc<SharedMemory>> {
+        if writeable && ! self.writeable() {
+            return Err(Error::new(EACCES));
+        }
+
+        Ok(self.region.memory().clone())
+    }
+}
+
+/// A scheme for named shared memory regions
+///
+/// Opening `shm:name` with `O_CREAT` creates an empty region, which is then sized with `ftruncate`.
+/// Unlinking removes the name, while processes that have it open or mapped keep using it.
+pub struct ShmScheme {
+    regions: Vec<Weak<ShmRegion>>,
+}
+
+impl ShmScheme {
+    pub fn new() -> Box<Self> {
+        box ShmScheme {
+            regions: Vec::new(),
+        }
+    }
+
+    fn find(&mut self, name: &str) -> Option<Arc<ShmRegion>> {
+        self.regions.retain(|region| region.upgrade().is_some());
+
+        for region in self.regions.iter() {
+            if let Some(region) = region.upgrade() {
+                if region.name == name {
+                    return Some(region);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl KScheme for ShmScheme {
+    fn scheme(&self) -> &str {
+        "shm"
+    }
+
+    fn open(&mut self, url: Url, flags: usize) -> Result<Box<Resource>> {
+        let name = url.reference().trim_matches('/');
+
+        if name.is_empty() {
+            let mut list = String::new();
+            for region in self.regions.iter() {
+                if let Some(region) = region.upgrade() {
+                    if ! list.is_empty() {
+                        list.push('\n');
+                    }
+                    list.push_str(&region.name);
+                }
+            }
+
+            return Ok(box VecResource::new("shm:".to_owned(), list.into_bytes()));
+        }
+
+        let region = match self.find(name) {
+            Some(region) => {
+                if flags & O_CREAT == O_CREAT && flags & O_EXCL == O_EXCL {
+                    return Err(Error::new(EEXIST));
+                }
+                region
+            },
+            None => {
+                if flags & O_CREAT != O_CREAT {
+                    return Err(Error::new(ENOENT));
+                }
+
+                let region = Arc::new(ShmRegion {
+                    name: name.to_owned(),
+                    memory: UnsafeCell::new(Arc::new(try!(SharedMemory::new(0)))),
+                });
+                self.regions.push(Arc::downgrade(&region));
+                region
+            }
+        };
+
+        let resource = ShmResource {
+            region: region,
+            flags: flags,
+            seek: 0,
+        };
+
+        if flags & O_TRUNC == O_TRUNC && resource.writeable() {
+            try!(resource.region.resize(0));
+        }
+
+        Ok(box resource)
+    }
+
+    fn stat(&mut self, url: Url, stat: &mut Stat) -> Result<()> {
+        let name = url.reference().trim_matches('/');
+        if name.is_empty() {
+            stat.st_mode = MODE_DIR;
+            return Ok(());
+        }
+
+        match self.find(name) {
+            Some(region) => {
+                stat.st_mode = MODE_FILE;
+                stat.st_size = region.memory().size as u32;
+                Ok(())
+            },
+            None => Err(Error::new(ENOENT))
+        }
+    }
+
+    fn unlink(&mut self, url: Url) -> Result<()> {
+        let name = url.reference().trim_matches('/');
+        if self.find(name).is_none() {
+            return Err(Error::new(ENOENT));
+        }
+
+        self.regions.retain(|region| match region.upgrade() {
+            Some(region) => region.name != name,
+            None => false
+        });
+
+        Ok(())
+    }
+}
@@ -1,8 +1,19 @@
 use fs::{KScheme, Resource, Url};
 use alloc::boxed::Box;
-use system::error::Result;
+use collections::string::{String, ToString};
+use collections::vec::Vec;
+use core::cmp;
+use logging::{self, LogLevel};
+use system::error::{Error, Result, EINVAL, ENOENT};
+use system::syscall::{MODE_FILE, Stat};
 
 /// The kernel log scheme.
+///
+/// The path selects what is read, and may combine a level with `follow`:
+/// - `syslog:` reads every entry
+/// - `syslog:warning` reads entries at `warning` or more severe
+/// - `syslog:follow` blocks for new entries instead of returning 0 at the end of the log
+/// - `syslog:threshold` reads or sets the lowest level written to the console
 pub struct SyslogScheme;
 
 impl KScheme for SyslogScheme {
@@ -11,36 +22,182 @@ impl KScheme for SyslogScheme {
         "syslog"
     }
 
-    /// Returns a resource. The `url` and `flags` arguments are currently unused.
-    fn open(&mut self, _: Url, _: usize) -> Result<Box<Resource>> {
+    /// Returns a resource for the log, or for the console threshold. The `flags` argument is
+    /// currently unused.
+    fn open(&mut self, url: Url, _: usize) -> Result<Box<Resource>> {
+        let mut level = None;
+        let mut follow = false;
+        for part in url.reference().split('/') {
+            match part {
+                "" => (),
+                "threshold" => return Ok(Box::new(SyslogThresholdResource {
+                    pos: 0
+                })),
+                "follow" => follow = true,
+                _ => match LogLevel::from_name(part) {
+                    Some(part_level) => level = Some(part_level),
+                    None => return Err(Error::new(ENOENT))
+                }
+            }
+        }
+
         Ok(Box::new(SyslogResource {
-            pos: 0
+            level: level,
+            follow: follow,
+            seq: 0,
+            buffer: Vec::new(),
         }))
     }
 }
 
 /// The kernel log resource.
 pub struct SyslogResource {
-    pos: usize
+    /// The least severe level that is read, and the level of written messages. Every level is
+    /// read and messages are written at `Info` if it is not given.
+    level: Option<LogLevel>,
+    /// Block at the end of the log instead of returning 0
+    follow: bool,
+    /// Sequence number of the next entry to read
+    seq: usize,
+    /// The part of the current entry that has not been read
+    buffer: Vec<u8>,
 }
 
 impl Resource for SyslogResource {
     fn dup(&self) -> Result<Box<Resource>> {
         Ok(Box::new(SyslogResource {
-            pos: self.pos
+            level: self.level,
+            follow: self.follow,
+            seq: self.seq,
+            buffer: self.buffer.clone(),
         }))
     }
 
-    /// Fills `buf` with the kernel log. Each message is prefixed by its log level:
+    fn path(&self, buf: &mut [u8]) -> Result<usize> {
+        let mut path = "syslog:".to_string();
+        if let Some(level) = self.level {
+            path.push_str(&level.name().to_lowercase());
+        }
+        if self.follow {
+            path.push_str("/follow");
+        }
+
+        for (b, p) in buf.iter_mut().zip(path.bytes()) {
+            *b = p;
+        }
+
+        Ok(cmp::min(buf.len(), path.len()))
+    }
+
+    /// Fills `buf` with the kernel log. Each message is prefixed by its time and log level:
     /// - `CRIT`
     /// - `ERROR`
     /// - `WARN`
     /// - `INFO`
     /// - `DEBUG`
+    ///
+    /// Messages written from userspace follow the level with their tag.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        while self.buffer.is_empty() {
+            let log = unsafe { & *::env().log.get() };
+            match log.find(self.seq, self.level.unwrap_or(LogLevel::Debug)) {
+                Some(entry) => {
+                    self.seq = entry.seq + 1;
+                    self.buffer = entry.to_string().into_bytes();
+                },
+                None => if self.follow {
+                    log.condition.wait("SyslogResource::read");
+                } else {
+                    return Ok(0);
+                }
+            }
+        }
+
+        let count = cmp::min(buf.len(), self.buffer.len());
+        for (b, p) in buf.iter_mut().zip(self.buffer.drain(.. count)) {
+            *b = p;
+        }
+        Ok(count)
+    }
+
+    /// Adds each line of `buf` to the log at the level of the resource, tagged with the name and
+    /// PID of the writer
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let tag = {
+            let contexts = unsafe { & *::env().contexts.get() };
+            let current = try!(contexts.current());
+            format!("{}[{}]", current.name, current.pid)
+        };
+
+        let level = self.level.unwrap_or(LogLevel::Info);
+        for line in String::from_utf8_lossy(buf).lines() {
+            if ! line.is_empty() {
+                logging::syslog_tagged(level, tag.clone(), line);
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn stat(&self, stat: &mut Stat) -> Result<usize> {
+        stat.st_mode = MODE_FILE;
+        Ok(0)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The console threshold of the kernel log
+pub struct SyslogThresholdResource {
+    pos: usize
+}
+
+impl Resource for SyslogThresholdResource {
+    fn dup(&self) -> Result<Box<Resource>> {
+        Ok(Box::new(SyslogThresholdResource {
+            pos: self.pos
+        }))
+    }
+
+    fn path(&self, buf: &mut [u8]) -> Result<usize> {
+        let path = b"syslog:threshold";
+
+        for (b, p) in buf.iter_mut().zip(path.iter()) {
+            *b = *p;
+        }
+
+        Ok(cmp::min(buf.len(), path.len()))
+    }
+
+    /// Reads the name of the threshold level
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         let log = unsafe { & *::env().log.get() };
-        let count = log.read_at(self.pos, buf);
+        let name = format!("{}\n", log.threshold.name().to_lowercase());
+
+        let mut count = 0;
+        for (b, p) in buf.iter_mut().zip(name.bytes().skip(self.pos)) {
+            *b = p;
+            count += 1;
+        }
         self.pos += count;
+
         Ok(count)
     }
+
+    /// Sets the threshold from a level name, such as `warning`
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        match LogLevel::from_name(&String::from_utf8_lossy(buf)) {
+            Some(level) => {
+                unsafe { (*::env().log.get()).threshold = level };
+                Ok(buf.len())
+            },
+            None => Err(Error::new(EINVAL))
+        }
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
 }
@@ -15,3 +15,8 @@ pub fn test() -> bool {
     //test!(array.get_slice(..) == &array);
     succ!();
 }
+
+tests! {
+    "slice",
+    test: Pass,
+}
@@ -0,0 +1,164 @@
+use collections::string::String;
+use collections::vec::Vec;
+
+use common::debug::SerialConsole;
+use common::time::Duration;
+
+use drivers::io::{Io, Pio};
+
+use super::GROUPS;
+
+/// The port of QEMU's `isa-debug-exit` device, see `make test`
+const DEBUG_EXIT_PORT: u16 = 0xF4;
+/// Written to `DEBUG_EXIT_PORT` when every test passes. QEMU exits with `(value << 1) | 1`, 33.
+const DEBUG_EXIT_PASS: u8 = 0x10;
+/// Written to `DEBUG_EXIT_PORT` when a test fails. QEMU exits with 35.
+const DEBUG_EXIT_FAIL: u8 = 0x11;
+
+/// The outcome a test should have
+#[derive(Copy, Clone, PartialEq)]
+pub enum Expect {
+    Pass,
+    Fail,
+}
+
+/// A registered test, see `tests!`
+pub struct Test {
+    /// The subsystem being tested
+    pub group: &'static str,
+    pub name: &'static str,
+    pub func: fn() -> bool,
+    pub expect: Expect,
+}
+
+/// The result of running a test
+pub struct TestResult {
+    pub test: &'static Test,
+    pub passed: bool,
+    /// How long the test took
+    pub time: Duration,
+}
+
+/// How results are reported
+#[derive(Copy, Clone)]
+pub enum Format {
+    /// Colored text, one line per test
+    Text,
+    /// Test Anything Protocol, version 13
+    Tap,
+    /// A JSON object with the totals and an array of results
+    Json,
+}
+
+/// Run the tests in `group`, or every test if `group` is empty
+pub fn run(group: &str) -> Vec<TestResult> {
+    let mut results = Vec::new();
+    for tests in GROUPS.iter() {
+        for test in tests.iter() {
+            if group.is_empty() || test.group == group {
+                let start = Duration::monotonic();
+                let result = (test.func)();
+                let end = Duration::monotonic();
+
+                results.push(TestResult {
+                    test: test,
+                    passed: result == (test.expect == Expect::Pass),
+                    time: end - start,
+                });
+            }
+        }
+    }
+    results
+}
+
+/// Escape a string for a JSON string literal
+fn json_escape(string: &str) -> String {
+    let mut escaped = String::new();
+    for c in string.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Format `results`
+pub fn report(results: &[TestResult], format: Format) -> String {
+    let failed = results.iter().filter(|result| ! result.passed).count();
+
+    let mut string = String::new();
+    match format {
+        Format::Text => {
+            for result in results.iter() {
+                if result.passed {
+                    string.push_str("\x1B[32mSUCCESS: ");
+                } else {
+                    string.push_str("\x1B[31mFAILURE: ");
+                }
+                string.push_str(&format!("{}::{} ({}.{:>06} s)\x1B[0m\n",
+                                         result.test.group,
+                                         result.test.name,
+                                         result.time.secs,
+                                         result.time.nanos / 1000));
+            }
+            string.push_str(&format!("{} passed, {} failed\n", results.len() - failed, failed));
+        },
+        Format::Tap => {
+            string.push_str("TAP version 13\n");
+            string.push_str(&format!("1..{}\n", results.len()));
+            for (i, result) in results.iter().enumerate() {
+                string.push_str(&format!("{} {} - {}::{}\n",
+                                         if result.passed { "ok" } else { "not ok" },
+                                         i + 1,
+                                         result.test.group,
+                                         result.test.name));
+                string.push_str("  ---\n");
+                string.push_str(&format!("  duration_ms: {}.{:>03}\n",
+                                         result.time.secs * 1000 + (result.time.nanos / 1000000) as i64,
+                                         (result.time.nanos / 1000) % 1000));
+                string.push_str("  ...\n");
+            }
+        },
+        Format::Json => {
+            string.push_str(&format!("{{\"passed\":{},\"failed\":{},\"tests\":[", results.len() - failed, failed));
+            for (i, result) in results.iter().enumerate() {
+                if i > 0 {
+                    string.push(',');
+                }
+                string.push_str(&format!("{{\"group\":\"{}\",\"name\":\"{}\",\"passed\":{},\"nanos\":{}}}",
+                                         json_escape(result.test.group),
+                                         json_escape(result.test.name),
+                                         result.passed,
+                                         result.time.secs * 1000000000 + result.time.nanos as i64));
+            }
+            string.push_str("]}\n");
+        }
+    }
+    string
+}
+
+/// Run every test, write the results to the serial port as TAP followed by JSON, and exit QEMU
+/// with a code telling whether they passed
+///
+/// This is what the kernel does instead of starting init when built with `--cfg kernel_test`.
+/// Returns if the `isa-debug-exit` device is missing.
+pub fn run_boot() -> bool {
+    let results = run("");
+    let passed = results.iter().all(|result| result.passed);
+
+    let mut serial = SerialConsole::new();
+    serial.write(report(&results, Format::Tap).as_bytes());
+    serial.write(report(&results, Format::Json).as_bytes());
+
+    Pio::<u8>::new(DEBUG_EXIT_PORT).write(if passed {
+        DEBUG_EXIT_PASS
+    } else {
+        DEBUG_EXIT_FAIL
+    });
+
+    passed
+}
@@ -0,0 +1,23 @@
+use logging::LogLevel;
+
+pub fn level_names() -> bool {
+    test!(LogLevel::from_name("crit") == Some(LogLevel::Critical));
+    test!(LogLevel::from_name("Warning") == Some(LogLevel::Warning));
+    test!(LogLevel::from_name(" info\n") == Some(LogLevel::Info));
+    test!(LogLevel::from_name("verbose") == None);
+    test!(LogLevel::from_name(LogLevel::Error.name()) == Some(LogLevel::Error));
+    succ!();
+}
+
+pub fn level_order() -> bool {
+    test!(LogLevel::Critical < LogLevel::Error);
+    test!(LogLevel::Warning < LogLevel::Info);
+    test!(LogLevel::Info < LogLevel::Debug);
+    succ!();
+}
+
+tests! {
+    "log",
+    level_names: Pass,
+    level_order: Pass,
+}
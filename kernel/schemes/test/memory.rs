@@ -0,0 +1,151 @@
+use arch::memory::{self, CLUSTER_ALIGN, CLUSTER_SIZE, MAX_ORDER, ZONE_LOW};
+use arch::slab::{self, CACHES};
+
+/// The free blocks of every order in the low zone
+fn free_blocks() -> [usize; MAX_ORDER + 1] {
+    let mut blocks = [0; MAX_ORDER + 1];
+    for order in 0..MAX_ORDER + 1 {
+        blocks[order] = memory::free_blocks(ZONE_LOW, order);
+    }
+    blocks
+}
+
+pub fn counters_restored() -> bool {
+    let used = memory::memory_used();
+    let free = memory::memory_free();
+
+    let address = unsafe { memory::alloc(3 * CLUSTER_SIZE) };
+    test!(address > 0);
+    let size = unsafe { memory::alloc_size(address) };
+    let alloc_used = memory::memory_used();
+    let alloc_free = memory::memory_free();
+
+    // Free before checking, so a failed check does not leak the clusters
+    unsafe { memory::unalloc(address) };
+    test!(size == 3 * CLUSTER_SIZE);
+    test!(alloc_used == used + 3 * CLUSTER_SIZE);
+    test!(alloc_free == free - 3 * CLUSTER_SIZE);
+    test!(memory::memory_used() == used);
+    test!(memory::memory_free() == free);
+    succ!();
+}
+
+pub fn split_merge() -> bool {
+    let before = free_blocks();
+    let order = match (0..MAX_ORDER + 1).find(|&order| before[order] > 0) {
+        Some(order) => order,
+        None => return false
+    };
+
+    // Taking one cluster splits the smallest free block, leaving a buddy free at every order below
+    let address = unsafe { memory::alloc(CLUSTER_SIZE) };
+    test!(address > 0);
+    let split = free_blocks();
+
+    // Freeing it merges the buddies back into the block
+    unsafe { memory::unalloc(address) };
+    let merged = free_blocks();
+
+    test!(split[order] == before[order] - 1);
+    for lower in 0..order {
+        test!(split[lower] == before[lower] + 1);
+    }
+    for order in 0..MAX_ORDER + 1 {
+        test!(merged[order] == before[order]);
+    }
+    succ!();
+}
+
+pub fn tail_freed() -> bool {
+    let free = memory::memory_free();
+    let used_blocks = memory::used_blocks(3);
+
+    // Five clusters take a block of eight, and the three past the end are freed again
+    let address = unsafe { memory::alloc(5 * CLUSTER_SIZE) };
+    test!(address > 0);
+    let size = unsafe { memory::alloc_size(address) };
+    let alloc_free = memory::memory_free();
+    let alloc_used_blocks = memory::used_blocks(3);
+
+    unsafe { memory::unalloc(address) };
+    test!(size == 5 * CLUSTER_SIZE);
+    test!(alloc_free == free - 5 * CLUSTER_SIZE);
+    test!(alloc_used_blocks == used_blocks + 1);
+    test!(memory::memory_free() == free);
+    test!(memory::used_blocks(3) == used_blocks);
+    succ!();
+}
+
+pub fn alignment() -> bool {
+    for &align in [CLUSTER_SIZE, 4 * CLUSTER_SIZE, 64 * CLUSTER_SIZE, CLUSTER_ALIGN].iter() {
+        let address = unsafe { memory::alloc_aligned(CLUSTER_SIZE, align) };
+        test!(address > 0);
+        unsafe { memory::unalloc(address) };
+        test!(address % align == 0);
+    }
+
+    let address = unsafe { memory::alloc_aligned(CLUSTER_SIZE, 2 * CLUSTER_ALIGN) };
+    if address > 0 {
+        unsafe { memory::unalloc(address) };
+    }
+    test!(address == 0);
+    succ!();
+}
+
+pub fn slab_alloc_free() -> bool {
+    let index = match slab::cache_index(2048, 1) {
+        Some(index) => index,
+        None => return false
+    };
+    let cache = unsafe { &mut CACHES[index] };
+
+    let used = cache.used;
+    let slabs = cache.slabs;
+    let memory_used = memory::memory_used();
+
+    // Enough objects to fill at least two new slabs
+    let count = 2 * cache.slab_objects() + 1;
+    let mut objects = [0; 64];
+    test!(count <= objects.len());
+
+    // Every object is freed before any check, so a failed check does not leak slabs
+    let mut allocated = 0;
+    let mut valid = true;
+    for i in 0..count {
+        let object = unsafe { cache.alloc() };
+        if object == 0 {
+            break;
+        }
+        valid = valid && object % cache.size == 0
+                      && objects[.. i].iter().all(|&other| other != object);
+        objects[i] = object;
+        allocated += 1;
+    }
+    let alloc_used = cache.used;
+    let alloc_slabs = cache.slabs;
+    let alloc_memory_used = memory::memory_used();
+
+    // Empty slabs go back to the frame allocator, except for one kept for the next allocation
+    for &object in objects[.. allocated].iter() {
+        unsafe { cache.unalloc(object) };
+    }
+
+    test!(allocated == count);
+    test!(valid);
+    test!(alloc_used == used + count);
+    test!(alloc_slabs >= slabs + 2);
+    test!(alloc_memory_used > memory_used);
+    test!(cache.used == used);
+    test!(cache.slabs <= slabs + 1);
+    test!(memory::memory_used() <= memory_used + cache.slab_size());
+    succ!();
+}
+
+tests! {
+    "memory",
+    counters_restored: Pass,
+    split_merge: Pass,
+    tail_freed: Pass,
+    alignment: Pass,
+    slab_alloc_free: Pass,
+}
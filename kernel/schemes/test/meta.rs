@@ -9,3 +9,9 @@ pub fn meta_test_woah() -> bool {
     test!(true);
     succ!();
 }
+
+tests! {
+    "meta",
+    meta_test_woah: Pass,
+    meta_test_woah_fail: Fail,
+}
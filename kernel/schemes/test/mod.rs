@@ -1,10 +1,10 @@
 use alloc::boxed::Box;
 
-use collections::string::{String, ToString};
-
 use fs::{KScheme, Resource, Url, VecResource};
 
-use system::error::Result;
+use system::error::{Error, Result, ENOENT};
+
+use self::harness::Format;
 
 #[macro_export]
 macro_rules! test {
@@ -29,10 +29,59 @@ macro_rules! fail {
     )
 }
 
+/// Register the tests of a module as a group, each followed by the outcome it should have
+///
+/// ```
+/// tests! {
+///     "group",
+///     passing_test: Pass,
+///     failing_test: Fail,
+/// }
+/// ```
+#[macro_export]
+macro_rules! tests {
+    ($group:expr, $($name:ident: $expect:ident),* $(,)*) => (
+        pub const TESTS: &'static [$crate::schemes::test::harness::Test] = &[
+            $($crate::schemes::test::harness::Test {
+                group: $group,
+                name: stringify!($name),
+                func: $name,
+                expect: $crate::schemes::test::harness::Expect::$expect,
+            }),*
+        ];
+    )
+}
+
+/// Declare the test modules and register their groups, in the order they run
+macro_rules! groups {
+    ($($group:ident),* $(,)*) => (
+        $(pub mod $group;)*
+
+        /// Every registered group of tests
+        pub static GROUPS: &'static [&'static [harness::Test]] = &[
+            $($group::TESTS),*
+        ];
+    )
+}
+
+/// The test runner and its output formats
+pub mod harness;
+
 // Add your test here!
-pub mod get_slice;
-pub mod meta;
+groups! {
+    meta,
+    get_slice,
+    log,
+    memory,
+    scheme,
+    time,
+}
 
+/// The test scheme
+///
+/// Opening `test:` runs every test and returns a colored summary. `test:tap` and `test:json` give
+/// the results as TAP or JSON instead, and a group name can follow the format to run only that
+/// group, as in `test:tap/time`.
 pub struct TestScheme;
 
 impl KScheme for TestScheme {
@@ -40,57 +89,25 @@ impl KScheme for TestScheme {
         "test"
     }
 
-    fn open(&mut self, _: Url, _: usize) -> Result<Box<Resource>> {
-        let mut string = String::new();
-
-        macro_rules! reg_test {
-            (! $test:path) => (
-                if !$test() {
-                    string.push_str("\x1B[32mSUCCESS: ");
-                } else {
-                    string.push_str("\x1B[31mFAILURE: ");
-                }
-                string.push_str(stringify!($test));
-                string.push_str("\x1B[0m\n");
-            );
-            (! $test:path, $($arg:tt)*) => (
-                if !$test() {
-                    string.push_str("\x1B[32mSUCCESS: ");
-                } else {
-                    string.push_str("\x1B[31mFAILURE: ");
-                }
-                string.push_str(stringify!($test));
-                string.push_str(": ");
-                string.push_str(&format!($($arg)*));
-                string.push_str("\x1B[0m\n");
-            );
-            ($test:path) => (
-                if $test() {
-                    string.push_str("\x1B[32mSUCCESS: ");
-                } else {
-                    string.push_str("\x1B[31mFAILURE: ");
-                }
-                string.push_str(stringify!($test));
-                string.push_str("\x1B[0m\n");
-            );
-            ($test:path, $($arg:tt)*) => (
-                if $test() {
-                    string.push_str("\x1B[32mSUCCESS: ");
-                } else {
-                    string.push_str("\x1B[31mFAILURE: ");
-                }
-                string.push_str(stringify!($test));
-                string.push_str(": ");
-                string.push_str(&format!($($arg)*));
-                string.push_str("\x1B[0m\n");
-            );
+    fn open(&mut self, url: Url, _: usize) -> Result<Box<Resource>> {
+        let path = url.reference().trim_matches('/');
+
+        let mut parts = path.splitn(2, '/');
+        let format = match parts.next().unwrap_or("") {
+            "" => Format::Text,
+            "tap" => Format::Tap,
+            "json" => Format::Json,
+            _ => return Err(Error::new(ENOENT))
+        };
+        let group = parts.next().map(|group| group.trim_matches('/')).unwrap_or("");
+
+        let results = harness::run(group);
+        if results.is_empty() && ! group.is_empty() {
+            return Err(Error::new(ENOENT));
         }
 
-        // Add your test here!
-        reg_test!(meta::meta_test_woah, "Testing the testing (wut)");
-        reg_test!(!meta::meta_test_woah_fail, "Testing the fail testing (wut)");
-        reg_test!(get_slice::test, "GetSlice");
+        let string = harness::report(&results, format);
 
-        Ok(box VecResource::new("test:".to_string(), string.into_bytes()))
+        Ok(box VecResource::new(format!("test:{}", path), string.into_bytes()))
     }
 }
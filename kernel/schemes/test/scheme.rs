@@ -0,0 +1,109 @@
+use alloc::arc::Arc;
+
+use arch::context::{context_switch, Context};
+use arch::memory::{self, CLUSTER_SIZE};
+
+use core::cell::Cell;
+use core::mem::size_of;
+
+use fs::{KScheme, Url};
+use fs::scheme::{capture_physical, Scheme};
+
+use system::error::{Error, EINTR};
+use system::scheme::{Packet, PACKET_CANCEL};
+use system::syscall::SYS_OPEN;
+
+/// A frame above 4 GiB keeps its address when captured into a daemon's context
+pub fn capture_high() -> bool {
+    let frame = unsafe { memory::alloc_user(CLUSTER_SIZE) };
+    test!(frame > 0);
+
+    // Without memory above 4 GiB there is nothing to check
+    if frame as u64 >= 0x100000000 {
+        let translated = unsafe {
+            let contexts = &mut *::env().contexts.get();
+            let translated = match contexts.current_mut() {
+                Ok(context) => {
+                    let virtual_address = capture_physical(context, frame, CLUSTER_SIZE, true);
+
+                    let mmap = &mut *context.mmap.get();
+                    let translated = mmap.translate(virtual_address + 16, 16);
+                    if let Ok(mut mem) = mmap.get_mem_mut(virtual_address) {
+                        mem.virtual_size = 0;
+                    }
+                    mmap.clean_mem();
+                    translated
+                },
+                Err(_) => None
+            };
+            memory::unalloc(frame);
+            translated
+        };
+
+        test!(translated == Some(frame + 16));
+    } else {
+        unsafe { memory::unalloc(frame) };
+    }
+    succ!();
+}
+
+/// A caller killed while it waits on a scheme fails with `EINTR`, and the daemon is sent a cancel
+/// packet for its request
+pub fn cancel_interrupted() -> bool {
+    // The test serves the scheme, from the current context
+    let (scheme, mut server) = match Scheme::new("test_cancel") {
+        Ok(pair) => pair,
+        Err(_) => return false
+    };
+
+    let result = Arc::new(Cell::new(None));
+    let caller_result = result.clone();
+    let caller_pid = Context::spawn("ktest_cancel".into(), box move || {
+        let mut scheme = scheme;
+        let result = match Url::from_str("test_cancel:") {
+            Ok(url) => scheme.open(url, 0).map(|_| 0),
+            Err(err) => Err(err)
+        };
+        caller_result.set(Some(Error::mux(result)));
+    });
+
+    // Reading blocks until the caller has sent its request, and is waiting for the reply
+    let mut request = Packet::default();
+    test!(server.read(&mut request).ok() == Some(size_of::<Packet>()));
+    test!(request.a == SYS_OPEN);
+
+    unsafe {
+        let contexts = &mut *::env().contexts.get();
+        match contexts.find_mut(caller_pid) {
+            Ok(caller) => {
+                test!(caller.blocked > 0 && caller.interrupt.is_some());
+                caller.kill();
+            },
+            Err(_) => return false
+        }
+    }
+
+    for _ in 0..1000 {
+        if result.get().is_some() {
+            break;
+        }
+        unsafe { context_switch() };
+    }
+    test!(result.get() == Some(Error::mux(Err(Error::new(EINTR)))));
+
+    let mut cancel = Packet::default();
+    test!(server.read(&mut cancel).ok() == Some(size_of::<Packet>()));
+    test!(cancel.id == 0 && cancel.a == PACKET_CANCEL && cancel.b == request.id);
+
+    // Answer the cancelled request, which frees its ID
+    let mut reply = request;
+    reply.a = 0;
+    test!(server.write(&reply).ok() == Some(size_of::<Packet>()));
+    succ!();
+}
+
+tests! {
+    "scheme",
+    capture_high: Pass,
+    cancel_interrupted: Pass,
+}
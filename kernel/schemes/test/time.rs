@@ -0,0 +1,34 @@
+use common::time::{Duration, NANOS_PER_SEC};
+
+pub fn normalize() -> bool {
+    let duration = Duration::new(1, NANOS_PER_SEC + 5);
+    test!(duration.secs == 2 && duration.nanos == 5);
+    let duration = Duration::new(2, -5);
+    test!(duration.secs == 1 && duration.nanos == NANOS_PER_SEC - 5);
+    succ!();
+}
+
+pub fn arithmetic() -> bool {
+    let a = Duration::new(1, 600000000);
+    let b = Duration::new(0, 700000000);
+    let sum = a + b;
+    test!(sum.secs == 2 && sum.nanos == 300000000);
+    let dif = a - b;
+    test!(dif.secs == 0 && dif.nanos == 900000000);
+    test!(b < a);
+    succ!();
+}
+
+pub fn monotonic() -> bool {
+    let start = Duration::monotonic();
+    let end = Duration::monotonic();
+    test!(start <= end);
+    succ!();
+}
+
+tests! {
+    "time",
+    normalize: Pass,
+    arithmetic: Pass,
+    monotonic: Pass,
+}
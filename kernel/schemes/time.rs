@@ -0,0 +1,203 @@
+use alloc::arc::Arc;
+use alloc::boxed::Box;
+
+use arch::context::context_switch;
+
+use common::time::{Duration, NANOS_PER_MICRO, NANOS_PER_SEC};
+
+use core::cell::UnsafeCell;
+use core::{cmp, mem, ptr};
+
+use fs::{KScheme, Resource, Url};
+
+use system::error::{Error, Result, EAGAIN, EINVAL, ENOENT};
+use system::syscall::{CLOCK_MONOTONIC, CLOCK_REALTIME, O_NONBLOCK, ITimerSpec, Stat, TimeSpec, MODE_FILE};
+
+/// The shortest period of a timer, so that timers cannot expire faster than they can be handled
+const MIN_INTERVAL: i64 = 10 * NANOS_PER_MICRO as i64;
+
+/// The number of nanoseconds in a duration
+fn nanos(duration: Duration) -> i64 {
+    duration.secs * NANOS_PER_SEC as i64 + duration.nanos as i64
+}
+
+/// Check that a time is not negative, and its nanoseconds are less than a second
+fn valid(time: &TimeSpec) -> bool {
+    time.tv_sec >= 0 && time.tv_nsec >= 0 && time.tv_nsec < NANOS_PER_SEC as i32
+}
+
+/// Get the current value of a clock
+fn clock_now(clock: usize) -> Duration {
+    if clock == CLOCK_REALTIME {
+        Duration::realtime()
+    } else {
+        Duration::monotonic()
+    }
+}
+
+/// A timer resource
+///
+/// Writing an `ITimerSpec` arms the timer: `it_value` is the time until the first expiration,
+/// and `it_interval`, if non-zero, is the period of the following expirations, at least 10 µs. A
+/// zero `it_value` disarms the timer.
+///
+/// Reading blocks until the timer expires, then returns the number of expirations since the last
+/// read as a `u64`. Reading a disarmed timer returns 0 bytes.
+///
+/// Resources made by `dup` share the timer.
+pub struct TimerResource {
+    clock: usize,
+    flags: usize,
+    timer: Arc<UnsafeCell<Timer>>,
+}
+
+/// The state of a timer, shared by its resources
+struct Timer {
+    deadline: Option<Duration>,
+    interval: Duration,
+}
+
+impl Timer {
+    /// Count expirations up to `now`, rearming or disarming the timer
+    fn expire(&mut self, now: Duration) -> u64 {
+        let deadline = match self.deadline {
+            Some(deadline) if deadline <= now => deadline,
+            _ => return 0
+        };
+
+        let interval = nanos(self.interval);
+        if interval > 0 {
+            let count = nanos(now - deadline) / interval + 1;
+            let next = nanos(deadline) + count * interval;
+            self.deadline = Some(Duration::new(next / NANOS_PER_SEC as i64, (next % NANOS_PER_SEC as i64) as i32));
+            count as u64
+        } else {
+            self.deadline = None;
+            1
+        }
+    }
+}
+
+impl Resource for TimerResource {
+    fn dup(&self) -> Result<Box<Resource>> {
+        Ok(box TimerResource {
+            clock: self.clock,
+            flags: self.flags,
+            timer: self.timer.clone(),
+        })
+    }
+
+    fn path(&self, buf: &mut [u8]) -> Result<usize> {
+        let path: &[u8] = if self.clock == CLOCK_REALTIME {
+            b"time:realtime"
+        } else {
+            b"time:monotonic"
+        };
+
+        for (b, p) in buf.iter_mut().zip(path.iter()) {
+            *b = *p;
+        }
+
+        Ok(cmp::min(buf.len(), path.len()))
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.len() < mem::size_of::<u64>() {
+            return Err(Error::new(EINVAL));
+        }
+
+        loop {
+            let timer = unsafe { &mut *self.timer.get() };
+            let deadline = match timer.deadline {
+                Some(deadline) => deadline,
+                None => return Ok(0),
+            };
+
+            let now = clock_now(self.clock);
+            if deadline <= now {
+                let count = timer.expire(now);
+                unsafe { ptr::write(buf.as_mut_ptr() as *mut u64, count) };
+                return Ok(mem::size_of::<u64>());
+            }
+
+            if self.flags & O_NONBLOCK == O_NONBLOCK {
+                return Err(Error::new(EAGAIN));
+            }
+
+            {
+                let contexts = unsafe { &mut *::env().contexts.get() };
+                let mut current = try!(contexts.current_mut());
+
+                current.block("TimerResource::read");
+                current.wake = Some(Duration::monotonic() + (deadline - now));
+            }
+
+            unsafe { context_switch(); }
+        }
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if buf.len() < mem::size_of::<ITimerSpec>() {
+            return Err(Error::new(EINVAL));
+        }
+
+        let spec = unsafe { ptr::read(buf.as_ptr() as *const ITimerSpec) };
+        if ! valid(&spec.it_value) || ! valid(&spec.it_interval) {
+            return Err(Error::new(EINVAL));
+        }
+
+        let interval = Duration::new(spec.it_interval.tv_sec, spec.it_interval.tv_nsec);
+        if nanos(interval) != 0 && nanos(interval) < MIN_INTERVAL {
+            return Err(Error::new(EINVAL));
+        }
+
+        let timer = unsafe { &mut *self.timer.get() };
+        timer.interval = interval;
+        if spec.it_value.tv_sec > 0 || spec.it_value.tv_nsec > 0 {
+            timer.deadline = Some(clock_now(self.clock) + Duration::new(spec.it_value.tv_sec, spec.it_value.tv_nsec));
+        } else {
+            timer.deadline = None;
+        }
+
+        Ok(mem::size_of::<ITimerSpec>())
+    }
+
+    fn stat(&self, stat: &mut Stat) -> Result<usize> {
+        stat.st_mode = MODE_FILE;
+        Ok(0)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A scheme for timers on the realtime and monotonic clocks
+pub struct TimeScheme;
+
+impl KScheme for TimeScheme {
+    fn scheme(&self) -> &str {
+        "time"
+    }
+
+    fn open(&mut self, url: Url, flags: usize) -> Result<Box<Resource>> {
+        let clock = match url.reference().trim_matches('/') {
+            "" | "monotonic" => CLOCK_MONOTONIC,
+            "realtime" => CLOCK_REALTIME,
+            other => match other.parse::<usize>() {
+                Ok(CLOCK_MONOTONIC) => CLOCK_MONOTONIC,
+                Ok(CLOCK_REALTIME) => CLOCK_REALTIME,
+                _ => return Err(Error::new(ENOENT)),
+            },
+        };
+
+        Ok(box TimerResource {
+            clock: clock,
+            flags: flags,
+            timer: Arc::new(UnsafeCell::new(Timer {
+                deadline: None,
+                interval: Duration::new(0, 0),
+            })),
+        })
+    }
+}
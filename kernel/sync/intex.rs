@@ -0,0 +1,39 @@
+/// Interrupt exclusion
+///
+/// Interrupts are disabled while an `Intex` is held, and restored to their previous state when it
+/// is dropped, so that code shared with interrupt handlers cannot be interrupted by them.
+pub struct Intex {
+    flags: usize,
+}
+
+impl Intex {
+    /// Disable interrupts until the returned guard is dropped
+    pub fn static_lock() -> Intex {
+        Intex {
+            flags: unsafe { push_flags_cli() }
+        }
+    }
+}
+
+impl Drop for Intex {
+    fn drop(&mut self) {
+        // Only enable interrupts if they were enabled before
+        if self.flags & 1 << 9 == 1 << 9 {
+            unsafe { asm!("sti" : : : "memory" : "intel", "volatile"); }
+        }
+    }
+}
+
+#[cfg(target_arch = "x86")]
+unsafe fn push_flags_cli() -> usize {
+    let flags: usize;
+    asm!("pushfd ; pop $0 ; cli" : "=r"(flags) : : "memory" : "intel", "volatile");
+    flags
+}
+
+#[cfg(target_arch = "x86_64")]
+unsafe fn push_flags_cli() -> usize {
+    let flags: usize;
+    asm!("pushfq ; pop $0 ; cli" : "=r"(flags) : : "memory" : "intel", "volatile");
+    flags
+}
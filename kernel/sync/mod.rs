@@ -1,7 +1,9 @@
+pub use self::intex::Intex;
 pub use self::wait_condition::WaitCondition;
 pub use self::wait_queue::WaitQueue;
 pub use self::wait_map::WaitMap;
 
+pub mod intex;
 pub mod wait_condition;
 pub mod wait_queue;
 pub mod wait_map;
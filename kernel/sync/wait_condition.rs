@@ -29,7 +29,10 @@ impl WaitCondition {
         let mut contexts = Vec::new();
         mem::swap(unsafe { &mut *self.contexts.get() }, &mut contexts);
         for &context in contexts.iter() {
-            unsafe { (*context).unblock(reason) }
+            unsafe {
+                (*context).interrupt = None;
+                (*context).unblock(reason);
+            }
         }
     }
 
@@ -46,6 +49,15 @@ impl WaitCondition {
         }
         unsafe { context_switch(); }
     }
+
+    /// Wait like `wait`, but let `Context::kill` wake the context. Callers must check whether
+    /// the context was killed after waking.
+    pub fn wait_interruptible(&self, reason: &str) {
+        if let Ok(mut context) = unsafe { &mut *::env().contexts.get() }.current_mut() {
+            context.interrupt = Some(self as *const WaitCondition);
+        }
+        self.wait(reason);
+    }
 }
 
 impl Drop for WaitCondition {
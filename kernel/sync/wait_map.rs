@@ -32,4 +32,24 @@ impl<K, V> WaitMap<K, V> where K: Ord {
             self.condition.wait(reason);
         }
     }
+
+    /// Wait for the value of `key`, giving up with `None` once `stop` returns true. `stop` is
+    /// checked before each wait, so waiters must be woken with `notify` when it changes. The wait
+    /// is interruptible, so `stop` should check whether the context was killed.
+    pub fn receive_unless<F: Fn() -> bool>(&self, key: &K, reason: &str, stop: F) -> Option<V> {
+        loop {
+            if let Some(value) = unsafe { self.inner() }.remove(key) {
+                return Some(value);
+            }
+            if stop() {
+                return None;
+            }
+            self.condition.wait_interruptible(reason);
+        }
+    }
+
+    /// Wake every waiter, without sending a value
+    pub fn notify(&self, reason: &str) {
+        self.condition.notify(reason);
+    }
 }
@@ -4,26 +4,43 @@ use alloc::arc::Arc;
 
 use arch::context::{CONTEXT_IMAGE_ADDR, CONTEXT_IMAGE_SIZE, CONTEXT_HEAP_ADDR, CONTEXT_HEAP_SIZE,
                     CONTEXT_MMAP_ADDR, CONTEXT_MMAP_SIZE, CONTEXT_STACK_SIZE, CONTEXT_STACK_ADDR,
-                    context_switch, context_userspace, Context, ContextMemory, ContextZone};
-use arch::elf::Elf;
-use arch::memory;
+                    alloc_user_or_kill, context_switch, context_userspace, Context, ContextMemory,
+                    ContextZone};
+use arch::elf::{Elf, PF_W, PF_X};
 use arch::regs::Regs;
 
 use collections::borrow::ToOwned;
 use collections::string::String;
 use collections::vec::Vec;
 
+use common::random;
 use common::slice::GetSlice;
 
 use core::cell::UnsafeCell;
-use core::ops::DerefMut;
 use core::{mem, ptr, slice, str};
 
 use fs::Url;
 
 use system::error::{Error, Result, ENOEXEC, ENOMEM};
 
-pub fn execute_thread(context_ptr: *mut Context, entry: usize, mut args: Vec<String>) -> ! {
+/// The heap and mmap bases are moved up by a random number of pages below this
+const ASLR_ZONE_RANGE: usize = 0x10000000;
+/// The stack base is moved up by a random number of pages below this
+const ASLR_STACK_RANGE: usize = 0x4000000;
+/// Position independent executables are loaded a random number of pages below this above
+/// `CONTEXT_IMAGE_ADDR`
+const ASLR_IMAGE_RANGE: usize = 0x4000000;
+
+/// A random, page aligned offset below `range`, or 0 if `aslr` is false
+fn aslr_offset(aslr: bool, range: usize) -> usize {
+    if aslr {
+        random::rand() % (range / 4096) * 4096
+    } else {
+        0
+    }
+}
+
+pub fn execute_thread(context_ptr: *mut Context, entry: usize, stack: ContextMemory, mut args: Vec<String>) -> ! {
     Context::spawn("kexec".into(),
                    box move || {
         let context = unsafe { &mut *context_ptr };
@@ -53,7 +70,9 @@ pub fn execute_thread(context_ptr: *mut Context, entry: usize, mut args: Vec<Str
                     virtual_address: virtual_address,
                     virtual_size: virtual_size,
                     writeable: false,
+                    executable: false,
                     allocated: true,
+                    shared: None,
                 });
             }
 
@@ -67,13 +86,8 @@ pub fn execute_thread(context_ptr: *mut Context, entry: usize, mut args: Vec<Str
         context.regs = Regs::default();
         context.regs.sp = context.kernel_stack + CONTEXT_STACK_SIZE - 128;
 
-        context.stack = Some(ContextMemory {
-            physical_address: unsafe { memory::alloc_aligned(CONTEXT_STACK_SIZE, 4096) },
-            virtual_address: CONTEXT_STACK_ADDR,
-            virtual_size: CONTEXT_STACK_SIZE,
-            writeable: true,
-            allocated: true,
-        });
+        context.stack = Some(stack);
+        context.update_peak_memory();
 
         let user_sp = if let Some(ref stack) = context.stack {
             let mut sp = stack.physical_address + stack.virtual_size - 128;
@@ -106,9 +120,13 @@ pub fn execute_thread(context_ptr: *mut Context, entry: usize, mut args: Vec<Str
 }
 
 /// Execute an executable
+///
+/// The heap, mmap and stack bases, and the image base of position independent executables, are
+/// randomized unless the `ASLR` environment variable is `0`.
 pub fn execute(mut args: Vec<String>) -> Result<usize> {
     let contexts = unsafe { &mut *::env().contexts.get() };
-    let current = try!(contexts.current_mut());
+    // The context list may change if memory runs out, see `oom_kill`
+    let current = &mut **try!(contexts.current_mut());
 
     let mut vec: Vec<u8> = Vec::new();
 
@@ -124,7 +142,7 @@ pub fn execute(mut args: Vec<String>) -> Result<usize> {
             let virtual_size = 1024*1024;
             let virtual_address = mmap.next_mem();
 
-            let physical_address = memory::alloc_aligned(virtual_size, 4096);
+            let physical_address = alloc_user_or_kill(virtual_size);
             if physical_address == 0 {
                 return Err(Error::new(ENOMEM));
             }
@@ -134,7 +152,9 @@ pub fn execute(mut args: Vec<String>) -> Result<usize> {
                 virtual_address: virtual_address,
                 virtual_size: virtual_size,
                 writeable: true,
+                executable: false,
                 allocated: true,
+                shared: None,
             };
 
             memory.map();
@@ -182,42 +202,103 @@ pub fn execute(mut args: Vec<String>) -> Result<usize> {
     } else {
         match Elf::from(&vec) {
             Ok(executable) => {
-                let entry = unsafe { executable.entry() };
                 let segments = unsafe { executable.load_segment() };
 
+                let aslr = current.get_env_var("ASLR").map_or(true, |value| value != "0");
+                let base = if unsafe { executable.position_independent() } {
+                    CONTEXT_IMAGE_ADDR + aslr_offset(aslr, ASLR_IMAGE_RANGE)
+                } else {
+                    0
+                };
+                let entry = unsafe { executable.entry() } + base;
+
+                // Refuse segments that are both writeable and executable
+                if segments.iter().any(|segment| segment.flags & (PF_W | PF_X) == PF_W | PF_X) {
+                    debugln!("execute: refusing '{:?}': writeable and executable segment", url);
+                    return Err(Error::new(ENOEXEC));
+                }
+
+                if base > 0 && segments.iter().any(|segment| {
+                    base + segment.vaddr as usize + segment.mem_len as usize > CONTEXT_IMAGE_ADDR + CONTEXT_IMAGE_SIZE
+                }) {
+                    debugln!("execute: refusing '{:?}': position independent image too large", url);
+                    return Err(Error::new(ENOEXEC));
+                }
+
+                // Only relocations against the base address are supported, as there is no dynamic
+                // linker, and they must land inside the image
+                let relocations = if base > 0 {
+                    unsafe { executable.relocations() }
+                } else {
+                    Vec::new()
+                };
+                if relocations.iter().any(|relocation| ! relocation.relative() || ! segments.iter().any(|segment| {
+                    relocation.offset >= segment.vaddr
+                        && relocation.offset as usize + mem::size_of::<usize>() <= segment.vaddr as usize + segment.mem_len as usize
+                })) {
+                    debugln!("execute: refusing '{:?}': unsupported relocation", url);
+                    return Err(Error::new(ENOEXEC));
+                }
+
                 if entry > 0 && ! segments.is_empty() {
+                    // Allocate before the old image is replaced, so that running out of memory
+                    // returns an error instead of leaving the context without an image
+                    let stack_physical_address = unsafe { alloc_user_or_kill(CONTEXT_STACK_SIZE) };
+                    if stack_physical_address == 0 {
+                        return Err(Error::new(ENOMEM));
+                    }
+                    let stack = ContextMemory {
+                        physical_address: stack_physical_address,
+                        virtual_address: CONTEXT_STACK_ADDR + aslr_offset(aslr, ASLR_STACK_RANGE),
+                        virtual_size: CONTEXT_STACK_SIZE,
+                        writeable: true,
+                        executable: false,
+                        allocated: true,
+                        shared: None,
+                    };
+
+                    let mut image_memory = Vec::new();
+                    for segment in segments.iter() {
+                        let virtual_address = base + segment.vaddr as usize;
+                        let virtual_size = segment.mem_len as usize;
+
+                        let offset = virtual_address % 4096;
+
+                        let physical_address = unsafe { alloc_user_or_kill(virtual_size + offset) };
+                        if physical_address == 0 {
+                            return Err(Error::new(ENOMEM));
+                        }
+
+                        image_memory.push(ContextMemory {
+                            physical_address: physical_address,
+                            virtual_address: virtual_address - offset,
+                            virtual_size: virtual_size + offset,
+                            writeable: true,
+                            executable: false,
+                            allocated: true,
+                            shared: None,
+                        });
+                    }
+
                     unsafe { current.unmap() };
 
                     current.name = url.to_string().into();
+                    current.args = args.clone();
                     current.cwd = Arc::new(UnsafeCell::new(unsafe { (*current.cwd.get()).clone() }));
 
                     current.image = Arc::new(UnsafeCell::new(ContextZone::new(CONTEXT_IMAGE_ADDR, CONTEXT_IMAGE_SIZE)));
-                    current.heap = Arc::new(UnsafeCell::new(ContextZone::new(CONTEXT_HEAP_ADDR, CONTEXT_HEAP_SIZE)));
-                    current.mmap = Arc::new(UnsafeCell::new(ContextZone::new(CONTEXT_MMAP_ADDR, CONTEXT_MMAP_SIZE)));
+
+                    let heap_offset = aslr_offset(aslr, ASLR_ZONE_RANGE);
+                    current.heap = Arc::new(UnsafeCell::new(ContextZone::new(CONTEXT_HEAP_ADDR + heap_offset, CONTEXT_HEAP_SIZE - heap_offset)));
+                    let mmap_offset = aslr_offset(aslr, ASLR_ZONE_RANGE);
+                    current.mmap = Arc::new(UnsafeCell::new(ContextZone::new(CONTEXT_MMAP_ADDR + mmap_offset, CONTEXT_MMAP_SIZE - mmap_offset)));
                     current.env_vars = Arc::new(UnsafeCell::new(unsafe { (*current.env_vars.get()).clone() }));
 
                     {
                         let image = unsafe { &mut *current.image.get() };
 
-                        for segment in segments.iter() {
-                            let virtual_address = segment.vaddr as usize;
-                            let virtual_size = segment.mem_len as usize;
-
-                            let offset = virtual_address % 4096;
-
-                            let physical_address = unsafe { memory::alloc_aligned(virtual_size + offset, 4096) };
-
-                            if physical_address == 0 {
-                                panic!("OOM in exec");
-                            }
-
-                            let mut memory = ContextMemory {
-                                physical_address: physical_address,
-                                virtual_address: virtual_address - offset,
-                                virtual_size: virtual_size + offset,
-                                writeable: true,
-                                allocated: true,
-                            };
+                        for (segment, memory) in segments.iter().zip(image_memory.iter_mut()) {
+                            let virtual_address = base + segment.vaddr as usize;
 
                             unsafe { memory.map() };
 
@@ -227,10 +308,18 @@ pub fn execute(mut args: Vec<String>) -> Result<usize> {
                                         executable.data.as_ptr().offset(segment.off as isize),
                                         segment.file_len as usize)
                             };
+                        }
+
+                        // Relocate while every segment is mapped writeable
+                        for relocation in relocations.iter() {
+                            unsafe { relocation.relocate(base) };
+                        }
 
+                        for (segment, mut memory) in segments.iter().zip(image_memory.into_iter()) {
                             unsafe { memory.unmap() };
 
-                            memory.writeable = segment.flags & 2 == 2;
+                            memory.writeable = segment.flags & PF_W == PF_W;
+                            memory.executable = segment.flags & PF_X == PF_X;
 
                             image.memory.push(memory);
                         }
@@ -240,7 +329,7 @@ pub fn execute(mut args: Vec<String>) -> Result<usize> {
 
                     unsafe { current.map() };
 
-                    execute_thread(current.deref_mut(), entry, args);
+                    execute_thread(current, entry, stack, args);
                 } else {
                     Err(Error::new(ENOEXEC))
                 }
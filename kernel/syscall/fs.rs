@@ -2,13 +2,16 @@
 
 use arch::context::ContextFile;
 
+use collections::Vec;
+use collections::string::ToString;
+
 use fs::{ResourceSeek, Url};
 
 use schemes::pipe::{PipeRead, PipeWrite};
 
 use system::c_string_to_str;
 
-use syscall::{Stat, SEEK_CUR, SEEK_END, SEEK_SET};
+use syscall::{IoVec, Stat, TimeSpec, SEEK_CUR, SEEK_END, SEEK_SET};
 
 use system::error::{Error, Result, EBADF, EFAULT, EINVAL};
 
@@ -58,6 +61,20 @@ pub fn chdir(path: *const u8) -> Result<usize> {
     Ok(0)
 }
 
+pub fn chmod(path: *const u8, mode: usize) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+    let path_string = current.canonicalize(c_string_to_str(path));
+    ::env().chmod(try!(Url::from_str(&path_string)), mode).and(Ok(0))
+}
+
+pub fn chown(path: *const u8, uid: usize, gid: usize) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+    let path_string = current.canonicalize(c_string_to_str(path));
+    ::env().chown(try!(Url::from_str(&path_string)), uid, gid).and(Ok(0))
+}
+
 /** <!-- @MANSTART{sys_close} -->
 NAME
     sys_close - close a file descriptor
@@ -130,6 +147,7 @@ ERRORS
 pub fn dup(fd: usize) -> Result<usize> {
     let contexts = unsafe { & *::env().contexts.get() };
     let current = try!(contexts.current());
+    let path = try!(current.get_file_path(fd)).clone();
     let resource = try!(current.get_file(fd));
     let new_resource = try!(resource.dup());
     let new_fd = current.next_fd();
@@ -137,12 +155,27 @@ pub fn dup(fd: usize) -> Result<usize> {
     unsafe {
         (*current.files.get()).push(ContextFile {
             fd: new_fd,
+            path: path,
             resource: new_resource,
         });
     }
     Ok(new_fd)
 }
 
+pub fn fchmod(fd: usize, mode: usize) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let mut current = contexts.current_mut()?;
+    let mut resource = current.get_file_mut(fd)?;
+    resource.chmod(mode).and(Ok(0))
+}
+
+pub fn fchown(fd: usize, uid: usize, gid: usize) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let mut current = contexts.current_mut()?;
+    let mut resource = current.get_file_mut(fd)?;
+    resource.chown(uid, gid).and(Ok(0))
+}
+
 pub fn fpath(fd: usize, buf: *mut u8, count: usize) -> Result<usize> {
     let contexts = unsafe { & *::env().contexts.get() };
     let current = contexts.current()?;
@@ -233,6 +266,14 @@ pub fn ftruncate(fd: usize, length: usize) -> Result<usize> {
     resource.truncate(length).and(Ok(0))
 }
 
+pub fn futimens(fd: usize, times: *const [TimeSpec; 2]) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let mut current = contexts.current_mut()?;
+    let mut resource = current.get_file_mut(fd)?;
+    let times_safe = current.get_ref(times)?;
+    resource.utimens(times_safe).and(Ok(0))
+}
+
 //TODO: Link
 
 /** <!-- @MANSTART{sys_lseek} -->
@@ -383,35 +424,37 @@ pub fn open(path_c: *const u8, flags: usize) -> Result<usize> {
     let contexts = unsafe { & *::env().contexts.get() };
     let current = try!(contexts.current());
     let path = current.canonicalize(c_string_to_str(path_c));
-    let url = try!(Url::from_str(&path));
-    let resource = try!(::env().open(url, flags));
+    let resource = try!(::env().open(try!(Url::from_str(&path)), flags));
     let fd = current.next_fd();
     unsafe {
         (*current.files.get()).push(ContextFile {
             fd: fd,
+            path: path,
             resource: resource,
         });
     }
     Ok(fd)
 }
 
-pub fn pipe2(fds: *mut usize, _flags: usize) -> Result<usize> {
+pub fn pipe2(fds: *mut usize, flags: usize) -> Result<usize> {
     let contexts = unsafe { & *::env().contexts.get() };
     let current = try!(contexts.current());
     if fds as usize > 0 {
-        let read = box PipeRead::new();
-        let write = box PipeWrite::new(&read);
+        let read = box PipeRead::new(flags);
+        let write = box PipeWrite::new(&read, flags);
 
         unsafe {
             *fds.offset(0) = current.next_fd();
             (*current.files.get()).push(ContextFile {
                 fd: *fds.offset(0),
+                path: "pipe:r".to_string(),
                 resource: read,
             });
 
             *fds.offset(1) = current.next_fd();
             (*current.files.get()).push(ContextFile {
                 fd: *fds.offset(1),
+                path: "pipe:w".to_string(),
                 resource: write,
             });
         }
@@ -422,6 +465,95 @@ pub fn pipe2(fds: *mut usize, _flags: usize) -> Result<usize> {
     }
 }
 
+/** <!-- @MANSTART{sys_pread} -->
+NAME
+    sys_pread - read from a file descriptor at a given offset
+
+SYNOPSIS
+    sys_pread(fd: usize, buf: *mut u8, count: usize, offset: usize) -> Result<usize>;
+
+DESCRIPTION
+    sys_pread attempts to read up to count bytes from file descriptor fd, starting at offset, into
+    the buffer starting at buf. The seek position of fd is not changed.
+
+RETURN VALUE
+    On success, Ok(count) is returned, where count is the number of bytes read into buf. On error,
+    Err(err) is returned where err is one of the following errors
+
+ERRORS
+    EBADF
+        fd is not a valid open file decriptor
+
+    EFAULT
+        buf is outside of the accessible address space of the process
+
+    EIO
+        I/O error
+
+    ESPIPE
+        fd does not support positional reads
+
+    ESRCH
+        Currently not running in a process context (rare, would only happen during kernel init)
+<!-- @MANEND --> */
+pub fn pread(fd: usize, buf: *mut u8, count: usize, offset: usize) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let mut current = contexts.current_mut()?;
+    let mut resource = current.get_file_mut(fd)?;
+    if count > 0 {
+        let buf_safe = current.get_slice_mut(buf, count)?;
+        resource.pread(buf_safe, offset)
+    } else {
+        Ok(0)
+    }
+}
+
+/** <!-- @MANSTART{sys_pwrite} -->
+NAME
+    sys_pwrite - write to a file descriptor at a given offset
+
+SYNOPSIS
+    sys_pwrite(fd: usize, buf: *const u8, count: usize, offset: usize) -> Result<usize>;
+
+DESCRIPTION
+    sys_pwrite attempts to write up to count bytes from the buffer starting at buf to file
+    descriptor fd, starting at offset. The seek position of fd is not changed.
+
+RETURN VALUE
+    On success, Ok(count) is returned, where count is the number of bytes written from buf. On
+    error, Err(err) is returned where err is one of the following errors
+
+ERRORS
+    EBADF
+        fd is not a valid open file decriptor
+
+    EFAULT
+        buf is outside of the accessible address space of the process
+
+    EIO
+        I/O error
+
+    ENOSPC
+        The filesystem containing fd has no more space
+
+    ESPIPE
+        fd does not support positional writes
+
+    ESRCH
+        Currently not running in a process context (rare, would only happen during kernel init)
+<!-- @MANEND --> */
+pub fn pwrite(fd: usize, buf: *const u8, count: usize, offset: usize) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let mut current = contexts.current_mut()?;
+    let mut resource = current.get_file_mut(fd)?;
+    if count > 0 {
+        let buf_safe = current.get_slice(buf, count)?;
+        resource.pwrite(buf_safe, offset)
+    } else {
+        Ok(0)
+    }
+}
+
 /** <!-- @MANSTART{sys_read} -->
 NAME
     sys_read - read from a file descriptor
@@ -465,6 +597,55 @@ pub fn read(fd: usize, buf: *mut u8, count: usize) -> Result<usize> {
     }
 }
 
+/** <!-- @MANSTART{sys_readv} -->
+NAME
+    sys_readv - read from a file descriptor into multiple buffers
+
+SYNOPSIS
+    sys_readv(fd: usize, iov: *const IoVec, iovcnt: usize) -> Result<usize>;
+
+DESCRIPTION
+    sys_readv reads from file descriptor fd into the iovcnt buffers described by iov, filling each
+    buffer completely before moving on to the next
+
+RETURN VALUE
+    On success, Ok(count) is returned, where count is the total number of bytes read. On error,
+    Err(err) is returned where err is one of the following errors
+
+ERRORS
+    EBADF
+        fd is not a valid open file decriptor
+
+    EFAULT
+        iov, or one of the buffers it describes, is outside of the accessible address space of the
+        process
+
+    EIO
+        I/O error
+
+    ESRCH
+        Currently not running in a process context (rare, would only happen during kernel init)
+<!-- @MANEND --> */
+pub fn readv(fd: usize, iov: *const IoVec, iovcnt: usize) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let mut current = contexts.current_mut()?;
+    let mut resource = current.get_file_mut(fd)?;
+    let iov_safe = current.get_slice(iov, iovcnt)?;
+
+    let mut bufs = Vec::new();
+    for vec in iov_safe.iter() {
+        if vec.iov_len > 0 {
+            bufs.push(current.get_slice_mut(vec.iov_base as *mut u8, vec.iov_len)?);
+        }
+    }
+
+    if bufs.is_empty() {
+        Ok(0)
+    } else {
+        resource.readv(&mut bufs)
+    }
+}
+
 pub fn rmdir(path: *const u8) -> Result<usize> {
     let contexts = unsafe { & *::env().contexts.get() };
     let current = try!(contexts.current());
@@ -490,6 +671,15 @@ pub fn unlink(path: *const u8) -> Result<usize> {
     ::env().unlink(try!(Url::from_str(&path_string))).and(Ok(0))
 }
 
+pub fn utimens(path: *const u8, times: *const [TimeSpec; 2]) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+    let path_string = current.canonicalize(c_string_to_str(path));
+    let url = Url::from_str(&path_string)?;
+    let times_safe = current.get_ref(times)?;
+    ::env().utimens(url, times_safe).and(Ok(0))
+}
+
 /** <!-- @MANSTART{sys_write} -->
 NAME
     sys_write - read from a file descriptor
@@ -538,3 +728,57 @@ pub fn write(fd: usize, buf: *const u8, count: usize) -> Result<usize> {
         Ok(0)
     }
 }
+
+/** <!-- @MANSTART{sys_writev} -->
+NAME
+    sys_writev - write to a file descriptor from multiple buffers
+
+SYNOPSIS
+    sys_writev(fd: usize, iov: *const IoVec, iovcnt: usize) -> Result<usize>;
+
+DESCRIPTION
+    sys_writev writes the iovcnt buffers described by iov to file descriptor fd, in order
+
+RETURN VALUE
+    On success, Ok(count) is returned, where count is the total number of bytes written. On error,
+    Err(err) is returned where err is one of the following errors
+
+ERRORS
+    EBADF
+        fd is not a valid open file decriptor
+
+    EFAULT
+        iov, or one of the buffers it describes, is outside of the accessible address space of the
+        process
+
+    EIO
+        I/O error
+
+    ENOSPC
+        The filesystem containing fd has no more space
+
+    EPIPE
+        fd is connected to a pipe or socket whose reading end is closed
+
+    ESRCH
+        Currently not running in a process context (rare, would only happen during kernel init)
+<!-- @MANEND --> */
+pub fn writev(fd: usize, iov: *const IoVec, iovcnt: usize) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let mut current = contexts.current_mut()?;
+    let mut resource = current.get_file_mut(fd)?;
+    let iov_safe = current.get_slice(iov, iovcnt)?;
+
+    let mut bufs = Vec::new();
+    for vec in iov_safe.iter() {
+        if vec.iov_len > 0 {
+            bufs.push(current.get_slice(vec.iov_base as *const u8, vec.iov_len)?);
+        }
+    }
+
+    if bufs.is_empty() {
+        Ok(0)
+    } else {
+        resource.writev(&bufs)
+    }
+}
@@ -1,9 +1,9 @@
 //! System calls for basic memory management.
 
-use arch::context::ContextMemory;
-use arch::memory;
+use arch::context::{alloc_user_or_kill, realloc_user_or_kill, ContextMemory};
 
-use system::error::Result;
+use system::error::{Error, Result, EINVAL, ENOMEM};
+use system::syscall::MAP_WRITE;
 
 //TODO: Refactor file to propogate results
 
@@ -12,6 +12,9 @@ pub fn brk(addr: usize) -> Result<usize> {
 
     let contexts = unsafe { & *::env().contexts.get() };
     if let Ok(current) = contexts.current() {
+        // The context list may change if memory runs out, see `oom_kill`
+        let current = &**current;
+
         ret = unsafe { (*current.heap.get()).next_mem() };
 
         // TODO: Make this smarter, currently it attempt to resize the entire data segment
@@ -21,31 +24,35 @@ pub fn brk(addr: usize) -> Result<usize> {
                     unsafe { mem.unmap() };
 
                     let size = addr - mem.virtual_address;
-                    let physical_address = unsafe { memory::realloc_aligned(mem.physical_address, size, 4096) };
+                    let physical_address = unsafe { realloc_user_or_kill(mem.physical_address, size) };
                     if physical_address > 0 {
                         mem.physical_address = physical_address;
                         mem.virtual_size = size;
                         ret = mem.virtual_address + mem.virtual_size;
-                    } else {
-                        debugln!("BRK: Realloc failed {:X}, {}", mem.virtual_address, size);
                     }
 
                     unsafe { mem.map() };
+
+                    if physical_address == 0 && size > 0 {
+                        return Err(Error::new(ENOMEM));
+                    }
                 }
             } else {
                 debugln!("{}: {}", current.pid, current.name);
                 debugln!("BRK: End segment not writeable or allocated");
             }
-        } else if addr >= ret {
+        } else if addr > ret {
             let size = addr - ret;
-            let physical_address = unsafe { memory::alloc_aligned(size, 4096) };
+            let physical_address = unsafe { alloc_user_or_kill(size) };
             if physical_address > 0 {
                 let mut mem = ContextMemory {
                     physical_address: physical_address,
                     virtual_address: ret,
                     virtual_size: size,
                     writeable: true,
-                    allocated: true
+                    executable: false,
+                    allocated: true,
+                    shared: None
                 };
                 ret = mem.virtual_address + mem.virtual_size;
 
@@ -54,12 +61,133 @@ pub fn brk(addr: usize) -> Result<usize> {
                     (*current.heap.get()).memory.push(mem);
                 }
             } else {
-                debugln!("BRK: Alloc failed {}", size);
+                return Err(Error::new(ENOMEM));
             }
         }
     } else {
         debugln!("BRK: Context not found");
     }
 
+    if let Ok(current) = unsafe { &mut *::env().contexts.get() }.current_mut() {
+        current.update_peak_memory();
+    }
+
     Ok(ret)
 }
+
+/** <!-- @MANSTART{sys_fmap} -->
+NAME
+    sys_fmap - map a file into memory
+
+SYNOPSIS
+    sys_fmap(fd: usize, offset: usize, size: usize, flags: usize) -> Result<usize>;
+
+DESCRIPTION
+    sys_fmap maps size bytes of the memory backing the file referenced by fd, starting at offset,
+    into the address space of the calling process. The mapping is shared: writes are seen by every
+    other process that maps the same memory. If MAP_WRITE is set in flags, the mapping is
+    writeable, otherwise it is read only.
+
+    The mapping stays valid after fd is closed, and is removed with sys_funmap or when the process
+    exits.
+
+RETURN VALUE
+    On success, Ok(addr) is returned, where addr is the address of the mapping. On error,
+    Err(err) is returned where err is one of the following errors
+
+ERRORS
+    EACCES
+        MAP_WRITE was requested, but fd was not opened for writing
+
+    EBADF
+        fd is not a valid open file descriptor
+
+    EINVAL
+        offset is not page aligned, size is zero, or the range is outside of the file
+
+    EPERM
+        fd does not support mapping
+
+    ESRCH
+        Currently not running in a process context (rare, would only happen during kernel init)
+<!-- @MANEND --> */
+pub fn fmap(fd: usize, offset: usize, size: usize, flags: usize) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let mut current = try!(contexts.current_mut());
+
+    let writeable = flags & MAP_WRITE == MAP_WRITE;
+    let shared = {
+        let mut resource = try!(current.get_file_mut(fd));
+        try!(resource.map(writeable))
+    };
+
+    if offset % 4096 != 0 || size == 0 || offset > shared.size || size > shared.size - offset {
+        return Err(Error::new(EINVAL));
+    }
+
+    unsafe {
+        let mmap = &mut *current.mmap.get();
+        let virtual_address = mmap.next_mem();
+
+        let mut mem = ContextMemory {
+            physical_address: shared.physical_address + offset,
+            virtual_address: virtual_address,
+            virtual_size: size,
+            writeable: writeable,
+            executable: false,
+            allocated: false,
+            shared: Some(shared)
+        };
+
+        mem.map();
+        mmap.memory.push(mem);
+
+        current.update_peak_memory();
+
+        Ok(virtual_address)
+    }
+}
+
+/** <!-- @MANSTART{sys_funmap} -->
+NAME
+    sys_funmap - remove a mapping created by sys_fmap
+
+SYNOPSIS
+    sys_funmap(addr: usize) -> Result<usize>;
+
+DESCRIPTION
+    sys_funmap removes the mapping starting at addr. The memory is freed once it is no longer
+    mapped or open in any process.
+
+RETURN VALUE
+    On success, Ok(0) is returned. On error, Err(err) is returned where err is one of the following
+    errors
+
+ERRORS
+    EINVAL
+        addr is not the start of a mapping created by sys_fmap
+
+    ESRCH
+        Currently not running in a process context (rare, would only happen during kernel init)
+<!-- @MANEND --> */
+pub fn funmap(addr: usize) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let current = try!(contexts.current());
+
+    unsafe {
+        let mmap = &mut *current.mmap.get();
+        if let Ok(mut mem) = mmap.get_mem_mut(addr) {
+            if mem.shared.is_some() {
+                mem.unmap();
+                mem.virtual_size = 0;
+            } else {
+                return Err(Error::new(EINVAL));
+            }
+        } else {
+            return Err(Error::new(EINVAL));
+        }
+        mmap.clean_mem();
+    }
+
+    Ok(0)
+}
@@ -2,7 +2,9 @@ pub use system::error::*;
 pub use system::syscall::*;
 
 use arch::regs::Regs;
-use arch::context::context_switch;
+use arch::context::{context_switch, Context, SyscallReply, OOM_STATUS};
+
+use system::scheme::Packet;
 
 pub mod execute;
 pub mod fs;
@@ -16,19 +18,29 @@ pub fn name(number: usize) -> &'static str {
         SYS_SUPERVISE => "supervise",
 
         // Unix
+        SYS_ADJTIME => "adjtime",
         SYS_BRK => "brk",
         SYS_CHDIR => "chdir",
+        SYS_CHMOD => "chmod",
+        SYS_CHOWN => "chown",
         SYS_CLONE => "clone",
         SYS_CLOSE => "close",
         SYS_CLOCK_GETTIME => "clock_gettime",
+        SYS_CLOCK_SETTIME => "clock_settime",
         SYS_DUP => "dup",
         SYS_EXECVE => "execve",
         SYS_EXIT => "exit",
+        SYS_FCHMOD => "fchmod",
+        SYS_FCHOWN => "fchown",
+        SYS_FMAP => "fmap",
         SYS_FPATH => "fpath",
         SYS_FSTAT => "fstat",
         SYS_FSYNC => "fsync",
         SYS_FTRUNCATE => "ftruncate",
+        SYS_FUNMAP => "funmap",
+        SYS_FUTIMENS => "futimens",
         SYS_GETPID => "getpid",
+        SYS_GETRUSAGE => "getrusage",
         SYS_IOPL => "iopl",
         // TODO: link
         SYS_LSEEK => "lseek",
@@ -36,18 +48,40 @@ pub fn name(number: usize) -> &'static str {
         SYS_NANOSLEEP => "nanosleep",
         SYS_OPEN => "open",
         SYS_PIPE2 => "pipe2",
+        SYS_PREAD => "pread",
+        SYS_PWRITE => "pwrite",
         SYS_READ => "read",
+        SYS_READV => "readv",
         SYS_RMDIR => "rmdir",
         SYS_STAT => "stat",
         SYS_UNLINK => "unlink",
+        SYS_UTIMENS => "utimens",
         SYS_WAITPID => "waitpid",
         SYS_WRITE => "write",
+        SYS_WRITEV => "writev",
         SYS_YIELD => "yield",
 
         _ => "unknown",
     }
 }
 
+/// Block a supervised context until its supervisor has answered `call`
+fn supervisor_stop(cur: &mut Context, call: Packet) -> Option<SyscallReply> {
+    // Block the process.
+    cur.syscall_stop = Some(call);
+    cur.syscall_reply = None;
+    cur.blocked_syscall = true;
+    cur.block("syscall::handle Supervise");
+    // Clear the timer.
+    cur.wake = None;
+
+    while cur.blocked > 0 {
+        unsafe { context_switch() };
+    }
+
+    cur.syscall_reply.take()
+}
+
 /// Handle the syscall defined by the given registers.
 ///
 /// AX defines which syscall to use. The arguments are provided in other registers, as specified by
@@ -61,16 +95,22 @@ pub fn handle(regs: &mut Regs) {
             cur.current_syscall = Some((regs.ip, regs.ax, regs.bx, regs.cx, regs.dx));
             // debugln!("PID {}: {} @ {:X}: {} {} {:X} {:X} {:X}", cur.pid, cur.name, regs.ip, regs.ax, name(regs.ax), regs.bx, regs.cx, regs.dx);
             if cur.supervised {
-                // Block the process.
-                cur.blocked_syscall = true;
-                cur.block("syscall::handle Supervise");
-                // Clear the timer.
-                cur.wake = None;
-
-                loop {
-                    if cur.blocked > 0 {
-                        unsafe { context_switch() };
-                    } else {
+                match supervisor_stop(cur, (*regs).into()) {
+                    Some(SyscallReply::Forward(call)) => {
+                        regs.ax = call.a;
+                        regs.bx = call.b;
+                        regs.cx = call.c;
+                        regs.dx = call.d;
+                        regs.si = call.e;
+                        cur.syscall_forwarded = true;
+                    },
+                    Some(SyscallReply::Return(value)) => {
+                        cur.current_syscall = None;
+                        regs.ax = value;
+                        return;
+                    },
+                    None => {
+                        cur.current_syscall = None;
                         return;
                     }
                 }
@@ -78,6 +118,8 @@ pub fn handle(regs: &mut Regs) {
         }
     }
 
+    let number = regs.ax;
+
     let result = match regs.ax {
         // These are arranged in such a way that the most frequent syscalls preceeds less frequent
         // once, to acheive the best performance.
@@ -86,6 +128,10 @@ pub fn handle(regs: &mut Regs) {
         SYS_WRITE => fs::write(regs.bx, regs.cx as *mut u8, regs.dx),
         SYS_READ => fs::read(regs.bx, regs.cx as *mut u8, regs.dx),
         SYS_LSEEK => fs::lseek(regs.bx, regs.cx as isize, regs.dx),
+        SYS_PREAD => fs::pread(regs.bx, regs.cx as *mut u8, regs.dx, regs.si),
+        SYS_PWRITE => fs::pwrite(regs.bx, regs.cx as *const u8, regs.dx, regs.si),
+        SYS_READV => fs::readv(regs.bx, regs.cx as *const IoVec, regs.dx),
+        SYS_WRITEV => fs::writev(regs.bx, regs.cx as *const IoVec, regs.dx),
         SYS_OPEN => fs::open(regs.bx as *const u8, regs.cx),
         SYS_CLOSE => fs::close(regs.bx),
         SYS_CLONE => process::clone(regs),
@@ -98,28 +144,70 @@ pub fn handle(regs: &mut Regs) {
         SYS_DUP => fs::dup(regs.bx),
         SYS_IOPL => process::iopl(regs),
         SYS_CLOCK_GETTIME => time::clock_gettime(regs.bx, regs.cx as *mut TimeSpec),
+        SYS_CLOCK_SETTIME => time::clock_settime(regs.bx, regs.cx as *const TimeSpec),
+        SYS_ADJTIME => time::adjtime(regs.bx as *const TimeSpec, regs.cx as *mut TimeSpec),
         SYS_EXECVE => process::execve(regs.bx as *const u8, regs.cx as *const *const u8),
         SYS_EXIT => process::exit(regs.bx),
         SYS_GETPID => process::getpid(),
+        SYS_GETRUSAGE => time::getrusage(regs.bx, regs.cx as *mut RUsage),
         // TODO: link
         SYS_PIPE2 => fs::pipe2(regs.bx as *mut usize, regs.cx),
         SYS_RMDIR => fs::rmdir(regs.bx as *const u8),
         SYS_STAT => fs::stat(regs.bx as *const u8, regs.cx as *mut Stat),
         SYS_UNLINK => fs::unlink(regs.bx as *const u8),
+        SYS_CHMOD => fs::chmod(regs.bx as *const u8, regs.cx),
+        SYS_CHOWN => fs::chown(regs.bx as *const u8, regs.cx, regs.dx),
+        SYS_UTIMENS => fs::utimens(regs.bx as *const u8, regs.cx as *const [TimeSpec; 2]),
+        SYS_FCHMOD => fs::fchmod(regs.bx, regs.cx),
+        SYS_FCHOWN => fs::fchown(regs.bx, regs.cx, regs.dx),
+        SYS_FUTIMENS => fs::futimens(regs.bx, regs.cx as *const [TimeSpec; 2]),
         SYS_WAITPID => process::waitpid(regs.bx as isize, regs.cx as *mut usize, regs.dx),
         SYS_BRK => memory::brk(regs.bx),
+        SYS_FMAP => memory::fmap(regs.bx, regs.cx, regs.dx, regs.si),
+        SYS_FUNMAP => memory::funmap(regs.bx),
         SYS_CHDIR => fs::chdir(regs.bx as *const u8),
         SYS_SUPERVISE => process::supervise(regs.bx),
         _ => Err(Error::new(ENOSYS)),
     };
 
-    {
+    let killed = {
         let contexts = unsafe { &mut *::env().contexts.get() };
         if let Ok(cur) = contexts.current_mut() {
             // debugln!("PID {}: {} @ {:X}: {} {} {:X} {:X} {:X} = {:?}", cur.pid, cur.name, regs.ip, regs.ax, name(regs.ax), regs.bx, regs.cx, regs.dx, result);
             cur.current_syscall = None;
+            cur.killed
+        } else {
+            false
         }
+    };
+
+    // Exit instead of returning, if picked to recover memory
+    if killed {
+        process::exit(OOM_STATUS);
     }
 
     regs.ax = Error::mux(result);
+
+    // Let the supervisor see, and change, the result of a forwarded syscall
+    {
+        let contexts = unsafe { &mut *::env().contexts.get() };
+        if let Ok(cur) = contexts.current_mut() {
+            let forwarded = cur.syscall_forwarded;
+            cur.syscall_forwarded = false;
+            if ! forwarded || ! cur.supervised {
+                return;
+            }
+
+            if let Some(SyscallReply::Return(value)) = supervisor_stop(cur, Packet {
+                id: SUPERVISE_RETURN,
+                a: regs.ax,
+                b: number,
+                c: 0,
+                d: 0,
+                e: 0,
+            }) {
+                regs.ax = value;
+            }
+        }
+    }
 }
@@ -6,7 +6,7 @@ use arch::regs::Regs;
 use collections::{BTreeMap, Vec};
 use collections::string::ToString;
 
-use core::mem;
+use core::{cmp, mem};
 use core::ops::DerefMut;
 
 use system::{c_array_to_slice, c_string_to_str};
@@ -37,19 +37,28 @@ pub fn exit(status: usize) -> ! {
         let contexts = unsafe { &mut *::env().contexts.get() };
 
         let mut statuses = BTreeMap::new();
-        let (pid, ppid) = {
+        let (pid, ppid, heap, user_time, kernel_time, peak_memory) = {
             if let Ok(mut current) = contexts.current_mut() {
                 mem::swap(&mut statuses, &mut unsafe { current.statuses.inner() }.deref_mut());
                 current.exit();
-                (current.pid, current.ppid)
+                (current.pid, current.ppid, current.heap.get(),
+                 current.user_time + current.child_user_time,
+                 current.kernel_time + current.child_kernel_time,
+                 cmp::max(current.peak_memory, current.child_peak_memory))
             } else {
-                (0, 0)
+                (0, 0, 0 as *mut _, 0, 0, 0)
             }
         };
 
         for mut context in contexts.iter_mut() {
-            // Add exit status to parent
+            // Add exit status to parent, and resource usage unless this is a thread, which shares
+            // the heap of its parent
             if context.pid == ppid {
+                if context.heap.get() != heap {
+                    context.child_user_time += user_time;
+                    context.child_kernel_time += kernel_time;
+                    context.child_peak_memory = cmp::max(context.child_peak_memory, peak_memory);
+                }
                 context.statuses.send(pid, status, "exit parent status");
                 for (pid, status) in statuses.iter() {
                     context.statuses.send(*pid, *status, "exit child status");
@@ -140,15 +149,12 @@ pub fn sched_yield() -> Result<usize> {
 /// docs in the `system` crate).
 ///
 /// This routine is done by having a field defining whether the process is blocked by a syscall.
-/// When the syscall is read from the file handle, this field is set to false, but the process is
-/// still stopped (because it is marked as `blocked`), until the new value of the EAX register is
-/// written to the file handle.
+/// The process leaves the syscall in `syscall_stop` for the supervisor to read, and stays
+/// blocked until the supervisor writes its answer to `syscall_reply`.
 pub fn supervise(pid: usize) -> Result<usize> {
     let contexts = unsafe { &mut *::env().contexts.get() };
     let cur_pid = try!(contexts.current_mut()).pid;
 
-    let procc;
-
     {
         let jailed = try!(contexts.find_mut(pid));
 
@@ -158,8 +164,6 @@ pub fn supervise(pid: usize) -> Result<usize> {
         }
 
         jailed.supervised = true;
-
-        procc = &mut **jailed as *mut _;
     }
 
     let current = try!(contexts.current_mut());
@@ -169,7 +173,8 @@ pub fn supervise(pid: usize) -> Result<usize> {
     unsafe {
         (*current.files.get()).push(ContextFile {
             fd: fd,
-            resource: box try!(SupervisorResource::new(procc)),
+            path: format!("supervise:{}", pid),
+            resource: box try!(SupervisorResource::new(pid)),
         });
     }
 
@@ -1,12 +1,16 @@
 //! System calles related to time.
 
 use arch::context::context_switch;
+use arch::tsc;
 
-use common::time::Duration;
+use common::time::{Duration, NANOS_PER_SEC};
 
-use syscall::{CLOCK_MONOTONIC, CLOCK_REALTIME, TimeSpec};
+use core::cmp;
 
-use system::error::{Error, Result, EINVAL};
+use syscall::{CLOCK_MONOTONIC, CLOCK_PROCESS_CPUTIME_ID, CLOCK_REALTIME, CLOCK_THREAD_CPUTIME_ID,
+              RUSAGE_CHILDREN, RUSAGE_SELF, RUSAGE_THREAD, RUsage, TimeSpec};
+
+use system::error::{Error, Result, EINVAL, EPERM};
 
 /// Get the time of a given clock.
 pub fn clock_gettime(clock: usize, tp: *mut TimeSpec) -> Result<usize> {
@@ -27,25 +31,128 @@ pub fn clock_gettime(clock: usize, tp: *mut TimeSpec) -> Result<usize> {
             tp_safe.tv_nsec = clock_monotonic.nanos;
             Ok(0)
         }
+        CLOCK_PROCESS_CPUTIME_ID => {
+            // Threads share the heap of their process
+            let mut ticks = 0;
+            for context in contexts.iter() {
+                if context.heap.get() == current.heap.get() {
+                    ticks += context.time;
+                }
+            }
+
+            let clock_process = Duration::from_ticks(ticks);
+            tp_safe.tv_sec = clock_process.secs;
+            tp_safe.tv_nsec = clock_process.nanos;
+            Ok(0)
+        }
+        CLOCK_THREAD_CPUTIME_ID => {
+            let clock_thread = Duration::from_ticks(current.time);
+            tp_safe.tv_sec = clock_thread.secs;
+            tp_safe.tv_nsec = clock_thread.nanos;
+            Ok(0)
+        }
+        _ => Err(Error::new(EINVAL)),
+    }
+}
+
+/// Set the time of a given clock. Only the realtime clock can be set.
+pub fn clock_settime(clock: usize, tp: *const TimeSpec) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = contexts.current()?;
+    let tp_safe = current.get_ref(tp)?;
+
+    match clock {
+        CLOCK_REALTIME => {
+            if tp_safe.tv_nsec < 0 || tp_safe.tv_nsec >= NANOS_PER_SEC {
+                return Err(Error::new(EINVAL));
+            }
+
+            let time = Duration::new(tp_safe.tv_sec, tp_safe.tv_nsec);
+            unsafe {
+                *::env().clock_realtime.get() = time;
+                *::env().clock_adjust.get() = 0;
+                (*::env().rtc.get()).set_time(time);
+            }
+            Ok(0)
+        }
+        CLOCK_MONOTONIC | CLOCK_PROCESS_CPUTIME_ID | CLOCK_THREAD_CPUTIME_ID => Err(Error::new(EPERM)),
         _ => Err(Error::new(EINVAL)),
     }
 }
 
+/// Slew the realtime clock by the given delta, returning the previous pending adjustment.
+pub fn adjtime(delta: *const TimeSpec, olddelta: *mut TimeSpec) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = contexts.current()?;
+
+    let clock_adjust = unsafe { &mut *::env().clock_adjust.get() };
+
+    if let Ok(olddelta_safe) = current.get_ref_mut(olddelta) {
+        olddelta_safe.tv_sec = *clock_adjust / NANOS_PER_SEC as i64;
+        olddelta_safe.tv_nsec = (*clock_adjust % NANOS_PER_SEC as i64) as i32;
+    }
+
+    if delta as usize > 0 {
+        let delta_safe = current.get_ref(delta)?;
+        *clock_adjust = delta_safe.tv_sec * NANOS_PER_SEC as i64 + delta_safe.tv_nsec as i64;
+    }
+
+    Ok(0)
+}
+
+/// Spin until `wake`, which must be less than a PIT tick away
+///
+/// Interrupts are enabled while spinning, so the clocks keep running and the context may be
+/// switched away. The spin is bounded by TSC cycles, so it ends even if the clocks stop.
+fn spin_until(wake: Duration) {
+    let now = Duration::monotonic();
+    if wake <= now {
+        return;
+    }
+
+    let remaining = if wake - now < ::PIT_DURATION {
+        wake - now
+    } else {
+        ::PIT_DURATION
+    };
+    let cycles = unsafe { & *::env().tsc.get() }.cycles(remaining.nanos as u64);
+
+    let start = tsc::rdtsc();
+    unsafe { asm!("sti" : : : "memory" : "intel", "volatile"); }
+    while tsc::rdtsc() - start < cycles {
+        unsafe { asm!("pause" : : : "memory" : "intel", "volatile"); }
+    }
+    unsafe { asm!("cli" : : : "memory" : "intel", "volatile"); }
+}
+
 /// Sleep in N nanoseconds.
+///
+/// Contexts are only woken on PIT ticks, so the sleep blocks until the last tick before it ends,
+/// and spins on the TSC for the rest. Without a calibrated TSC it has tick resolution.
 pub fn nanosleep(req: *const TimeSpec, rem: *mut TimeSpec) -> Result<usize> {
-    {
+    let wake = {
         let contexts = unsafe { &mut *::env().contexts.get() };
         let mut current = try!(contexts.current_mut());
 
         // Copied with * to avoid borrow issue on current.blocked = true
         let req_safe = *current.get_ref(req)?;
 
-        current.block("nanosleep");
-        current.wake = Some(Duration::monotonic() + Duration::new(req_safe.tv_sec, req_safe.tv_nsec));
-    }
+        let duration = Duration::new(req_safe.tv_sec, req_safe.tv_nsec);
+        let wake = Duration::monotonic() + duration;
+        if ! unsafe { & *::env().tsc.get() }.calibrated() {
+            current.block("nanosleep");
+            current.wake = Some(wake);
+        } else if duration > ::PIT_DURATION {
+            current.block("nanosleep");
+            current.wake = Some(wake - ::PIT_DURATION);
+        }
+        wake
+    };
 
     unsafe { context_switch(); }
 
+    spin_until(wake);
+
     {
         let contexts = unsafe { & *::env().contexts.get() };
         let current = try!(contexts.current());
@@ -58,3 +165,49 @@ pub fn nanosleep(req: *const TimeSpec, rem: *mut TimeSpec) -> Result<usize> {
 
     Ok(0)
 }
+
+/// Get the resource usage of the current thread, process, or its exited children.
+pub fn getrusage(who: usize, usage: *mut RUsage) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = contexts.current()?;
+    let usage_safe = current.get_ref_mut(usage)?;
+
+    let (user_time, kernel_time, peak_memory, switches) = match who {
+        RUSAGE_SELF => {
+            // Threads share the heap of their process
+            let mut user_time = 0;
+            let mut kernel_time = 0;
+            let mut peak_memory = 0;
+            let mut switches = 0;
+            for context in contexts.iter() {
+                if context.heap.get() == current.heap.get() {
+                    user_time += context.user_time;
+                    kernel_time += context.kernel_time;
+                    peak_memory = cmp::max(peak_memory, context.peak_memory);
+                    switches += context.switch;
+                }
+            }
+            (user_time, kernel_time, peak_memory, switches)
+        },
+        RUSAGE_THREAD => (current.user_time, current.kernel_time, current.peak_memory, current.switch),
+        RUSAGE_CHILDREN => (current.child_user_time, current.child_kernel_time, current.child_peak_memory, 0),
+        _ => return Err(Error::new(EINVAL)),
+    };
+
+    let utime = Duration::from_ticks(user_time);
+    let stime = Duration::from_ticks(kernel_time);
+    *usage_safe = RUsage {
+        ru_utime: TimeSpec {
+            tv_sec: utime.secs,
+            tv_nsec: utime.nanos,
+        },
+        ru_stime: TimeSpec {
+            tv_sec: stime.secs,
+            tv_nsec: stime.nanos,
+        },
+        ru_maxrss: peak_memory / 1024,
+        ru_nswitch: switches,
+    };
+
+    Ok(0)
+}
@@ -2592,12 +2592,36 @@ pub struct RandomState {
 
 impl RandomState {
     /// Constructs a new `RandomState` that is initialized with random keys.
+    ///
+    /// The keys are read from `rand:` once per process. Each new `RandomState` adds a counter to
+    /// the first key, as upstream does with its per-thread keys, so the keys still differ.
     #[inline]
     #[allow(deprecated)] // rand
     pub fn new() -> RandomState {
         use rand::XorShiftRng;
-        let mut r = SeedableRng::from_seed([342275, 283987, 32924834, 321984834]): XorShiftRng;
-        RandomState { k0: r.gen(), k1: r.gen() }
+        use rand_old;
+        use slice;
+        use sync::Once;
+        use sync::atomic::{AtomicUsize, Ordering};
+
+        static SEED_ONCE: Once = Once::new();
+        static mut SEED: [u64; 2] = [0; 2];
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        SEED_ONCE.call_once(|| {
+            let mut keys = [0u64; 2];
+            if ! rand_old::fill(unsafe { slice::from_raw_parts_mut(keys.as_mut_ptr() as *mut u8, mem::size_of::<[u64; 2]>()) }) {
+                let mut r = SeedableRng::from_seed([342275, 283987, 32924834, 321984834]): XorShiftRng;
+                keys = [r.gen(), r.gen()];
+            }
+            unsafe { SEED = keys; }
+        });
+
+        let keys = unsafe { SEED };
+        RandomState {
+            k0: keys[0].wrapping_add(NEXT.fetch_add(1, Ordering::Relaxed) as u64),
+            k1: keys[1],
+        }
     }
 }
 
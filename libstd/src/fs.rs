@@ -5,11 +5,12 @@ use mem;
 use path::{PathBuf, Path};
 use string::String;
 use sys_common::AsInner;
+use time::{Duration, SystemTime, UNIX_EPOCH};
 use vec::Vec;
 
-use system::syscall::{sys_open, sys_dup, sys_close, sys_fpath, sys_ftruncate, sys_read,
-              sys_write, sys_lseek, sys_fsync, sys_mkdir, sys_rmdir, sys_stat, sys_unlink};
-use system::syscall::{O_RDWR, O_RDONLY, O_WRONLY, O_APPEND, O_CREAT, O_TRUNC, MODE_DIR, MODE_FILE, SEEK_SET, SEEK_CUR, SEEK_END, Stat};
+use system::syscall::{sys_open, sys_dup, sys_close, sys_fchmod, sys_fpath, sys_ftruncate, sys_futimens, sys_read,
+              sys_write, sys_lseek, sys_fsync, sys_mkdir, sys_rmdir, sys_stat, sys_chmod, sys_unlink};
+use system::syscall::{O_RDWR, O_RDONLY, O_WRONLY, O_APPEND, O_CREAT, O_TRUNC, MODE_DIR, MODE_FILE, MODE_PERM, SEEK_SET, SEEK_CUR, SEEK_END, Stat, TimeSpec};
 
 /// A Unix-style file
 #[derive(Debug)]
@@ -67,6 +68,17 @@ impl File {
     pub fn set_len(&mut self, size: u64) -> Result<()> {
         sys_ftruncate(self.fd, size as usize).and(Ok(())).map_err(|x| Error::from_sys(x))
     }
+
+    /// Change the permissions of the file
+    pub fn set_permissions(&self, perm: Permissions) -> Result<()> {
+        sys_fchmod(self.fd, perm.mode as usize).and(Ok(())).map_err(|x| Error::from_sys(x))
+    }
+
+    /// Set the access and modification times of the file
+    pub fn set_times(&self, accessed: SystemTime, modified: SystemTime) -> Result<()> {
+        let times = [timespec(accessed), timespec(modified)];
+        sys_futimens(self.fd, &times).and(Ok(())).map_err(|x| Error::from_sys(x))
+    }
 }
 
 impl AsRawFd for File {
@@ -222,6 +234,44 @@ impl OpenOptions {
     }
 }
 
+/// Convert a `SystemTime` to a `TimeSpec` relative to the epoch
+fn timespec(time: SystemTime) -> TimeSpec {
+    let duration = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::new(0, 0));
+    TimeSpec {
+        tv_sec: duration.as_secs() as i64,
+        tv_nsec: duration.subsec_nanos() as i32,
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Permissions {
+    mode: u16
+}
+
+impl Permissions {
+    pub fn readonly(&self) -> bool {
+        self.mode & 0o222 == 0
+    }
+
+    pub fn set_readonly(&mut self, readonly: bool) {
+        if readonly {
+            self.mode &= !0o222;
+        } else {
+            self.mode |= 0o222;
+        }
+    }
+
+    pub fn mode(&self) -> u16 {
+        self.mode
+    }
+
+    pub fn from_mode(mode: u16) -> Permissions {
+        Permissions {
+            mode: mode & MODE_PERM
+        }
+    }
+}
+
 pub struct Metadata {
     stat: Stat
 }
@@ -245,6 +295,26 @@ impl Metadata {
     pub fn len(&self) -> u64 {
         self.stat.st_size as u64
     }
+
+    pub fn permissions(&self) -> Permissions {
+        Permissions::from_mode(self.stat.st_mode)
+    }
+
+    pub fn accessed(&self) -> Result<SystemTime> {
+        Ok(UNIX_EPOCH + Duration::new(self.stat.st_atime as u64, self.stat.st_atime_nsec))
+    }
+
+    pub fn modified(&self) -> Result<SystemTime> {
+        Ok(UNIX_EPOCH + Duration::new(self.stat.st_mtime as u64, self.stat.st_mtime_nsec))
+    }
+
+    pub fn uid(&self) -> u32 {
+        self.stat.st_uid as u32
+    }
+
+    pub fn gid(&self) -> u32 {
+        self.stat.st_gid as u32
+    }
 }
 
 pub struct DirEntry {
@@ -380,6 +450,16 @@ pub fn remove_dir<P: AsRef<Path>>(path: P) -> Result<()> {
     }.map_err(|x| Error::from_sys(x))
 }
 
+/// Change the permissions of a file or directory
+pub fn set_permissions<P: AsRef<Path>>(path: P, perm: Permissions) -> Result<()> {
+    let path_str = path.as_ref().as_os_str().as_inner();
+    let mut path_c = path_str.to_owned();
+    path_c.push_str("\0");
+    unsafe {
+        sys_chmod(path_c.as_ptr(), perm.mode as usize).and(Ok(()))
+    }.map_err(|x| Error::from_sys(x))
+}
+
 /// Removes a file from the filesystem
 pub fn remove_file<P: AsRef<Path>>(path: P) -> Result<()> {
     let path_str = path.as_ref().as_os_str().as_inner();
@@ -0,0 +1,42 @@
+use core_collections::borrow::ToOwned;
+use fs::File;
+use io::{Error, Result};
+use os::unix::io::AsRawFd;
+use path::Path;
+use sys_common::AsInner;
+
+use system::syscall::{sys_chown, sys_fchown, sys_pread, sys_pwrite};
+
+/// Positional I/O on files
+pub trait FileExt {
+    /// Read from the given offset, without changing the seek position
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize>;
+
+    /// Write at the given offset, without changing the seek position
+    fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize>;
+}
+
+impl FileExt for File {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        sys_pread(self.as_raw_fd(), buf, offset as usize).map_err(|x| Error::from_sys(x))
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize> {
+        sys_pwrite(self.as_raw_fd(), buf, offset as usize).map_err(|x| Error::from_sys(x))
+    }
+}
+
+/// Change the owner and group of a file or directory
+pub fn chown<P: AsRef<Path>>(path: P, uid: u32, gid: u32) -> Result<()> {
+    let path_str = path.as_ref().as_os_str().as_inner();
+    let mut path_c = path_str.to_owned();
+    path_c.push_str("\0");
+    unsafe {
+        sys_chown(path_c.as_ptr(), uid as usize, gid as usize).and(Ok(()))
+    }.map_err(|x| Error::from_sys(x))
+}
+
+/// Change the owner and group of an open file
+pub fn fchown(file: &File, uid: u32, gid: u32) -> Result<()> {
+    sys_fchown(file.as_raw_fd(), uid as usize, gid as usize).and(Ok(())).map_err(|x| Error::from_sys(x))
+}
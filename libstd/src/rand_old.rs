@@ -1,11 +1,35 @@
-// TODO: Cleanup and make correct and safe
+use mem;
+use slice;
+
+use system::syscall::{sys_close, sys_open, sys_read, O_RDONLY};
 
 static mut NEXT: u64 = 0;
 
+/// Read random bytes from the kernel's `rand:` scheme
+pub fn fill(buf: &mut [u8]) -> bool {
+    let path = b"rand:\0";
+    match unsafe { sys_open(path.as_ptr(), O_RDONLY, 0) } {
+        Ok(fd) => {
+            let result = sys_read(fd, buf);
+            let _ = sys_close(fd);
+            match result {
+                Ok(count) => count == buf.len(),
+                Err(_) => false
+            }
+        },
+        Err(_) => false
+    }
+}
+
 /// Generate pseudo random number
 pub fn rand() -> usize {
     unsafe {
-        NEXT = NEXT * 1103515245 + 12345;
+        if NEXT == 0 {
+            let mut seed = 0u64;
+            fill(slice::from_raw_parts_mut(&mut seed as *mut u64 as *mut u8, mem::size_of::<u64>()));
+            NEXT = seed | 1;
+        }
+        NEXT = NEXT.wrapping_mul(1103515245).wrapping_add(12345);
         (NEXT / 65536) as usize
     }
 }